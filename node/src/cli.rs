@@ -7,6 +7,11 @@ pub struct Cli {
 
 	#[clap(flatten)]
 	pub run: RunCmd,
+
+	/// Index the lending events of the finalized blocks into a history of the accounts, served
+	/// by the `lending_getAccountHistory` RPC. Needs `--state-pruning archive`.
+	#[arg(long)]
+	pub lending_history: bool,
 }
 
 #[derive(Debug, clap::Subcommand)]
@@ -9,6 +9,9 @@ use crate::{
 use frame_benchmarking_cli::{BenchmarkCmd, ExtrinsicFactory, SUBSTRATE_REFERENCE_HARDWARE};
 use kylix_runtime::{Block, LendingPoolApi, EXISTENTIAL_DEPOSIT};
 use sc_cli::SubstrateCli;
+use sc_client_api::BlockchainEvents;
+use sc_rpc::SubscriptionTaskExecutor;
+use sc_rpc_api::DenyUnsafe;
 use sc_service::PartialComponents;
 use sp_api::ProvideRuntimeApi;
 use sp_blockchain::HeaderBackend;
@@ -58,9 +61,17 @@ impl SubstrateCli for Cli {
 }
 
 // Added function to handle lending pool command
-pub fn handle_lending_pool_command<C, Block>(client: Arc<C>) -> Result<(), Error>
+pub fn handle_lending_pool_command<C, Block>(
+	client: Arc<C>,
+	executor: SubscriptionTaskExecutor,
+) -> Result<(), Error>
 where
-	C: ProvideRuntimeApi<Block> + HeaderBackend<Block> + Send + Sync + 'static,
+	C: ProvideRuntimeApi<Block>
+		+ HeaderBackend<Block>
+		+ BlockchainEvents<Block>
+		+ Send
+		+ Sync
+		+ 'static,
 	Block: BlockT,
 	C::Api: LendingPoolApi<Block>,
 {
@@ -69,8 +80,8 @@ where
 
 	// Use the runtime to execute the async RPC call
 	let result = runtime.block_on(async {
-		let lending_pool_rpc = LendingPoolApiImpl::new(client.clone());
-		lending_pool_rpc.get_lending_pools(None, None)
+		let lending_pool_rpc = LendingPoolApiImpl::new(client.clone(), executor, DenyUnsafe::No);
+		lending_pool_rpc.get_lending_pools(None, None, None, None, None, None)
 	});
 
 	match result {
@@ -245,10 +256,13 @@ pub fn run() -> sc_cli::Result<()> {
 			let runner = cli.create_runner(&cli.run)?;
 			runner.sync_run(|config| {
 				// Create the service components, including the client
-				let PartialComponents { client, .. } = service::new_partial(&config)?;
+				let PartialComponents { client, task_manager, .. } = service::new_partial(&config)?;
 
 				// Call the handle_lending_pool_command function with the client
-				let result = handle_lending_pool_command(client.clone());
+				let result = handle_lending_pool_command(
+					client.clone(),
+					Arc::new(task_manager.spawn_handle()),
+				);
 
 				match result {
 					Ok(_) => Ok(()),
@@ -258,8 +272,9 @@ pub fn run() -> sc_cli::Result<()> {
 		},
 		None => {
 			let runner = cli.create_runner(&cli.run)?;
+			let lending_history = cli.lending_history;
 			runner.run_node_until_exit(|config| async move {
-				service::new_full(config).map_err(sc_cli::Error::Service)
+				service::new_full(config, lending_history).map_err(sc_cli::Error::Service)
 			})
 		},
 	}
@@ -0,0 +1,598 @@
+//! Off-chain history of the lending activity of the accounts.
+//!
+//! When the node runs with `--lending-history`, [`run_indexer`] follows the finalized blocks,
+//! decodes the events of the lending pallet and appends them to the history of the accounts they
+//! concern. The history is kept in the offchain column of the node database, under its own prefix,
+//! and served by the `lending_getAccountHistory` RPC.
+
+use futures::StreamExt;
+use kylix_runtime::{
+	lending::{self, LendingPoolId},
+	opaque::Block,
+	AccountId, AssetId, Balance, BlockNumber, Hash, RuntimeEvent,
+};
+use sc_client_api::{Backend, BlockchainEvents, StorageProvider};
+use serde::{Deserialize, Serialize};
+use sp_blockchain::HeaderBackend;
+use sp_core::{offchain::OffchainStorage, storage::StorageKey, twox_128};
+use sp_runtime::{
+	codec::{self, Compact, Decode},
+	traits::Header,
+	FixedU128,
+};
+use std::sync::Arc;
+
+/// The prefix of the history keys in the offchain storage
+const HISTORY_PREFIX: &[u8] = b"kylix-lending-history";
+/// The key of the number of the last indexed block
+const LAST_INDEXED_KEY: &[u8] = b"last-indexed";
+/// The number of entries stored under a single key of the history of an account
+const PAGE_SIZE: u32 = 128;
+
+type EventRecord = frame_system::EventRecord<RuntimeEvent, Hash>;
+
+/// A lending event of an account, with the fields of the pallet event
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LendingAction {
+	LiquiditySupplied {
+		asset: AssetId,
+		pool_id: LendingPoolId,
+		balance: Balance,
+		scaled_balance: Balance,
+		supply_index: FixedU128,
+	},
+	LiquidityWithdrawn {
+		asset: AssetId,
+		pool_id: LendingPoolId,
+		balance: Balance,
+		scaled_balance: Balance,
+		supply_index: FixedU128,
+	},
+	Borrowed {
+		pool_id: LendingPoolId,
+		borrowed_asset_id: AssetId,
+		borrowed_balance: Balance,
+		scaled_balance: Balance,
+		borrow_index: FixedU128,
+		collateral_asset_id: AssetId,
+		collateral_balance: Balance,
+	},
+	Repaid {
+		pool_id: LendingPoolId,
+		repaid_asset_id: AssetId,
+		repaid_balance: Balance,
+		scaled_balance: Balance,
+		borrow_index: FixedU128,
+		collateral_asset_id: AssetId,
+		collateral_balance: Balance,
+	},
+	/// A repayment of the loan of `borrower` by `payer`, in the history of both
+	RepaidOnBehalf {
+		payer: AccountId,
+		borrower: AccountId,
+		pool_id: LendingPoolId,
+		repaid_asset_id: AssetId,
+		repaid_balance: Balance,
+		scaled_balance: Balance,
+		borrow_index: FixedU128,
+		collateral_asset_id: AssetId,
+		collateral_balance: Balance,
+	},
+	LPTokenMinted {
+		asset: AssetId,
+		pool_id: LendingPoolId,
+		balance: Balance,
+		supply_index: FixedU128,
+	},
+	/// A liquidation of the loan of `borrower` by `liquidator`, in the history of both
+	Liquidated {
+		liquidator: AccountId,
+		borrower: AccountId,
+		borrowed_asset_id: AssetId,
+		repaid_balance: Balance,
+		collateral_asset_id: AssetId,
+		collateral_balance: Balance,
+	},
+}
+
+/// An entry of the history of an account.
+/// Used as response for rpc
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+	pub block_number: BlockNumber,
+	/// The index of the event in the events of the block
+	pub event_index: u32,
+	pub action: LendingAction,
+}
+
+impl HistoryEntry {
+	/// The position of the entry in the chain, entries are appended in this order
+	fn position(&self) -> (BlockNumber, u32) {
+		(self.block_number, self.event_index)
+	}
+}
+
+/// Returns the history entry of a lending event with the accounts it concerns, nothing for the
+/// other events.
+fn history_entries(
+	block_number: BlockNumber,
+	event_index: u32,
+	event: RuntimeEvent,
+) -> Vec<(AccountId, HistoryEntry)> {
+	let event = match event {
+		RuntimeEvent::Lending(event) => event,
+		_ => return Vec::new(),
+	};
+	let (accounts, action) = match event {
+		lending::Event::LiquiditySupplied {
+			who,
+			asset,
+			pool_id,
+			balance,
+			scaled_balance,
+			supply_index,
+		} => (
+			vec![who],
+			LendingAction::LiquiditySupplied {
+				asset,
+				pool_id,
+				balance,
+				scaled_balance,
+				supply_index,
+			},
+		),
+		lending::Event::LiquidityWithdrawn {
+			who,
+			asset,
+			pool_id,
+			balance,
+			scaled_balance,
+			supply_index,
+		} => (
+			vec![who],
+			LendingAction::LiquidityWithdrawn {
+				asset,
+				pool_id,
+				balance,
+				scaled_balance,
+				supply_index,
+			},
+		),
+		lending::Event::Borrowed {
+			who,
+			pool_id,
+			borrowed_asset_id,
+			borrowed_balance,
+			scaled_balance,
+			borrow_index,
+			collateral_asset_id,
+			collateral_balance,
+		} => (
+			vec![who],
+			LendingAction::Borrowed {
+				pool_id,
+				borrowed_asset_id,
+				borrowed_balance,
+				scaled_balance,
+				borrow_index,
+				collateral_asset_id,
+				collateral_balance,
+			},
+		),
+		lending::Event::Repaid {
+			who,
+			pool_id,
+			repaid_asset_id,
+			repaid_balance,
+			scaled_balance,
+			borrow_index,
+			collateral_asset_id,
+			collateral_balance,
+		} => (
+			vec![who],
+			LendingAction::Repaid {
+				pool_id,
+				repaid_asset_id,
+				repaid_balance,
+				scaled_balance,
+				borrow_index,
+				collateral_asset_id,
+				collateral_balance,
+			},
+		),
+		lending::Event::RepaidOnBehalf {
+			payer,
+			borrower,
+			pool_id,
+			repaid_asset_id,
+			repaid_balance,
+			scaled_balance,
+			borrow_index,
+			collateral_asset_id,
+			collateral_balance,
+		} => (
+			vec![payer.clone(), borrower.clone()],
+			LendingAction::RepaidOnBehalf {
+				payer,
+				borrower,
+				pool_id,
+				repaid_asset_id,
+				repaid_balance,
+				scaled_balance,
+				borrow_index,
+				collateral_asset_id,
+				collateral_balance,
+			},
+		),
+		lending::Event::LPTokenMinted { who, asset, pool_id, balance, supply_index } =>
+			(vec![who], LendingAction::LPTokenMinted { asset, pool_id, balance, supply_index }),
+		lending::Event::Liquidated {
+			liquidator,
+			borrower,
+			borrowed_asset_id,
+			repaid_balance,
+			collateral_asset_id,
+			collateral_balance,
+		} => (
+			vec![liquidator.clone(), borrower.clone()],
+			LendingAction::Liquidated {
+				liquidator,
+				borrower,
+				borrowed_asset_id,
+				repaid_balance,
+				collateral_asset_id,
+				collateral_balance,
+			},
+		),
+		_ => return Vec::new(),
+	};
+	let entry = HistoryEntry { block_number, event_index, action };
+	accounts.into_iter().map(|who| (who, entry.clone())).collect()
+}
+
+/// Decodes the event records of a block one by one, so that the records before one that does
+/// not decode are kept, with the error. A record does not decode when the runtime of the block
+/// had other events than the native runtime, e.g. before a runtime upgrade. The records are not
+/// length prefixed, so the ones after it are lost too.
+fn decode_event_records(mut data: &[u8]) -> (Vec<EventRecord>, Option<codec::Error>) {
+	let len = match Compact::<u32>::decode(&mut data) {
+		Ok(len) => len.0,
+		Err(e) => return (Vec::new(), Some(e)),
+	};
+	let mut records = Vec::new();
+	for _ in 0..len {
+		match EventRecord::decode(&mut data) {
+			Ok(record) => records.push(record),
+			Err(e) => return (records, Some(e)),
+		}
+	}
+	(records, None)
+}
+
+/// The lending history of the accounts, stored in an offchain storage.
+///
+/// The history of an account is split in pages of [`PAGE_SIZE`] entries, so that appending an
+/// entry only rewrites the last page. The number of entries of an account is stored under the
+/// account key, and its pages under the account key followed by the page index.
+#[derive(Clone)]
+pub struct LendingHistory<S> {
+	storage: S,
+}
+
+impl<S: OffchainStorage> LendingHistory<S> {
+	pub fn new(storage: S) -> Self {
+		Self { storage }
+	}
+
+	/// The number of the last indexed block, `None` if no block was indexed yet
+	pub fn last_indexed(&self) -> Option<BlockNumber> {
+		let value = self.storage.get(HISTORY_PREFIX, LAST_INDEXED_KEY)?;
+		Some(BlockNumber::from_le_bytes(value.try_into().ok()?))
+	}
+
+	fn set_last_indexed(&mut self, block_number: BlockNumber) {
+		self.storage.set(HISTORY_PREFIX, LAST_INDEXED_KEY, &block_number.to_le_bytes());
+	}
+
+	fn len(&self, who: &AccountId) -> u32 {
+		self.storage
+			.get(HISTORY_PREFIX, who.as_ref())
+			.and_then(|value| value.try_into().ok())
+			.map(u32::from_le_bytes)
+			.unwrap_or_default()
+	}
+
+	fn page_key(who: &AccountId, page: u32) -> Vec<u8> {
+		[who.as_ref(), &page.to_le_bytes()[..]].concat()
+	}
+
+	fn page(&self, who: &AccountId, page: u32) -> Result<Vec<HistoryEntry>, serde_json::Error> {
+		match self.storage.get(HISTORY_PREFIX, &Self::page_key(who, page)) {
+			Some(value) => serde_json::from_slice(&value),
+			None => Ok(Vec::new()),
+		}
+	}
+
+	/// Appends an entry to the history of `who`.
+	///
+	/// An entry that is not after the last entry of the account is ignored, so that a block
+	/// indexed again after a restart does not duplicate its entries.
+	pub fn append(
+		&mut self,
+		who: &AccountId,
+		entry: HistoryEntry,
+	) -> Result<(), serde_json::Error> {
+		let len = self.len(who);
+		let index = len / PAGE_SIZE;
+		let mut page = self.page(who, index)?;
+		let last = match (page.last(), len.checked_sub(1)) {
+			(Some(last), _) => Some(last.position()),
+			// The new entry starts a new page, the last entry is on the previous one
+			(None, Some(last)) =>
+				self.page(who, last / PAGE_SIZE)?.last().map(HistoryEntry::position),
+			(None, None) => None,
+		};
+		if last.is_some_and(|last| last >= entry.position()) {
+			return Ok(())
+		}
+
+		page.push(entry);
+		self.storage
+			.set(HISTORY_PREFIX, &Self::page_key(who, index), &serde_json::to_vec(&page)?);
+		self.storage.set(HISTORY_PREFIX, who.as_ref(), &(len + 1).to_le_bytes());
+		Ok(())
+	}
+
+	/// Returns the history of `who` in the blocks `from..=to`, oldest first. The bounds default to
+	/// the first and the last indexed block.
+	pub fn account_history(
+		&self,
+		who: &AccountId,
+		from: Option<BlockNumber>,
+		to: Option<BlockNumber>,
+	) -> Result<Vec<HistoryEntry>, serde_json::Error> {
+		let from = from.unwrap_or_default();
+		let to = to.unwrap_or(BlockNumber::MAX);
+		let mut history = Vec::new();
+		for index in 0..self.len(who).div_ceil(PAGE_SIZE) {
+			let page = self.page(who, index)?;
+			let last = match page.last() {
+				Some(last) => last.block_number,
+				None => continue,
+			};
+			if last < from {
+				continue
+			}
+			history
+				.extend(page.into_iter().filter(|entry| (from..=to).contains(&entry.block_number)));
+			if last > to {
+				break
+			}
+		}
+		Ok(history)
+	}
+
+	/// Indexes the lending events of the blocks after the last indexed block, up to `finalized`.
+	///
+	/// The events of a block that do not decode with the native runtime are skipped, so that the
+	/// blocks of an older runtime do not stop the indexing.
+	fn index_until<B, C>(&mut self, client: &C, finalized: BlockNumber) -> Result<(), String>
+	where
+		B: Backend<Block>,
+		C: HeaderBackend<Block> + StorageProvider<Block, B>,
+	{
+		let events_key = StorageKey([twox_128(b"System"), twox_128(b"Events")].concat());
+		let first = self.last_indexed().map_or(1, |last| last.saturating_add(1));
+		for block_number in first..=finalized {
+			let hash = client
+				.hash(block_number)
+				.map_err(|e| e.to_string())?
+				.ok_or_else(|| format!("Block #{} not found", block_number))?;
+			let (records, error) =
+				match client.storage(hash, &events_key).map_err(|e| e.to_string())? {
+					Some(data) => decode_event_records(&data.0),
+					None => (Vec::new(), None),
+				};
+			if let Some(e) = error {
+				log::warn!(
+					target: "lending-history",
+					"Skipping the events of block #{} from event #{}, which do not decode: {}",
+					block_number,
+					records.len(),
+					e
+				);
+			}
+			for (event_index, record) in records.into_iter().enumerate() {
+				for (who, entry) in history_entries(block_number, event_index as u32, record.event)
+				{
+					self.append(&who, entry).map_err(|e| e.to_string())?;
+				}
+			}
+			self.set_last_indexed(block_number);
+		}
+		Ok(())
+	}
+}
+
+/// Follows the finalized blocks and indexes their lending events into `history`.
+///
+/// The blocks finalized while the indexer was not running are indexed first. On failure, the
+/// indexing is retried from the failed block on the next finalized block.
+pub async fn run_indexer<B, C, S>(client: Arc<C>, mut history: LendingHistory<S>)
+where
+	B: Backend<Block>,
+	C: BlockchainEvents<Block> + HeaderBackend<Block> + StorageProvider<Block, B>,
+	S: OffchainStorage,
+{
+	let mut finality = client.finality_notification_stream();
+	let mut finalized = client.info().finalized_number;
+	loop {
+		if let Err(e) = history.index_until(&*client, finalized) {
+			log::warn!(target: "lending-history", "Unable to index the lending events: {}", e);
+		}
+		match finality.next().await {
+			Some(notification) => finalized = *notification.header.number(),
+			None => return,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use sp_core::offchain::storage::InMemOffchainStorage;
+	use sp_runtime::codec::Encode;
+
+	fn supplied(block_number: BlockNumber, event_index: u32) -> HistoryEntry {
+		HistoryEntry {
+			block_number,
+			event_index,
+			action: LendingAction::LiquiditySupplied {
+				asset: 1,
+				pool_id: 100,
+				balance: 1_000,
+				scaled_balance: 1_000,
+				supply_index: FixedU128::from_u32(1),
+			},
+		}
+	}
+
+	#[test]
+	fn test_history_entry_of_lending_events() {
+		let alice = AccountId::new([1; 32]);
+		let event = RuntimeEvent::Lending(lending::Event::Borrowed {
+			who: alice.clone(),
+			pool_id: 100,
+			borrowed_asset_id: 1,
+			borrowed_balance: 100,
+			scaled_balance: 50,
+			borrow_index: FixedU128::from_u32(2),
+			collateral_asset_id: 2,
+			collateral_balance: 300,
+		});
+		assert_eq!(
+			history_entries(7, 2, event),
+			vec![(
+				alice.clone(),
+				HistoryEntry {
+					block_number: 7,
+					event_index: 2,
+					action: LendingAction::Borrowed {
+						pool_id: 100,
+						borrowed_asset_id: 1,
+						borrowed_balance: 100,
+						scaled_balance: 50,
+						borrow_index: FixedU128::from_u32(2),
+						collateral_asset_id: 2,
+						collateral_balance: 300,
+					},
+				}
+			)]
+		);
+
+		let event =
+			RuntimeEvent::Lending(lending::Event::LendingPoolAdded { who: alice, asset: 1 });
+		assert_eq!(history_entries(7, 3, event), vec![]);
+	}
+
+	#[test]
+	fn test_history_entries_of_both_parties() {
+		let alice = AccountId::new([1; 32]);
+		let bob = AccountId::new([2; 32]);
+		let event = RuntimeEvent::Lending(lending::Event::Liquidated {
+			liquidator: alice.clone(),
+			borrower: bob.clone(),
+			borrowed_asset_id: 1,
+			repaid_balance: 100,
+			collateral_asset_id: 2,
+			collateral_balance: 210,
+		});
+		let entry = HistoryEntry {
+			block_number: 7,
+			event_index: 2,
+			action: LendingAction::Liquidated {
+				liquidator: alice.clone(),
+				borrower: bob.clone(),
+				borrowed_asset_id: 1,
+				repaid_balance: 100,
+				collateral_asset_id: 2,
+				collateral_balance: 210,
+			},
+		};
+		assert_eq!(
+			history_entries(7, 2, event),
+			vec![(alice.clone(), entry.clone()), (bob.clone(), entry)]
+		);
+
+		let event = RuntimeEvent::Lending(lending::Event::RepaidOnBehalf {
+			payer: alice.clone(),
+			borrower: bob.clone(),
+			pool_id: 100,
+			repaid_asset_id: 1,
+			repaid_balance: 100,
+			scaled_balance: 50,
+			borrow_index: FixedU128::from_u32(2),
+			collateral_asset_id: 2,
+			collateral_balance: 300,
+		});
+		let accounts: Vec<_> =
+			history_entries(7, 3, event).into_iter().map(|(who, _)| who).collect();
+		assert_eq!(accounts, vec![alice, bob]);
+	}
+
+	#[test]
+	fn test_decode_event_records_keeps_the_records_before_an_undecodable_one() {
+		let alice = AccountId::new([1; 32]);
+		let record = |event_index| EventRecord {
+			phase: frame_system::Phase::ApplyExtrinsic(event_index),
+			event: RuntimeEvent::Lending(lending::Event::LendingPoolAdded {
+				who: alice.clone(),
+				asset: 1,
+			}),
+			topics: vec![],
+		};
+		let records = vec![record(0), record(1)];
+		let (decoded, error) = decode_event_records(&records.encode());
+		assert_eq!(decoded, records);
+		assert!(error.is_none());
+
+		// a third record of an event the native runtime does not know
+		let mut data = Compact(3u32).encode();
+		data.extend(records.iter().flat_map(Encode::encode));
+		data.extend([0, 0, 0, 0, u8::MAX, u8::MAX]);
+		let (decoded, error) = decode_event_records(&data);
+		assert_eq!(decoded, records);
+		assert!(error.is_some());
+	}
+
+	#[test]
+	fn test_account_history() {
+		let alice = AccountId::new([1; 32]);
+		let bob = AccountId::new([2; 32]);
+		let mut history = LendingHistory::new(InMemOffchainStorage::default());
+		assert_eq!(history.last_indexed(), None);
+
+		// Fill more than a page, with a few entries appended again
+		for block_number in 1..=PAGE_SIZE + 10 {
+			history.append(&alice, supplied(block_number, 0)).unwrap();
+			history.append(&alice, supplied(block_number, 0)).unwrap();
+		}
+		history.append(&bob, supplied(5, 1)).unwrap();
+		history.set_last_indexed(PAGE_SIZE + 10);
+
+		let all = history.account_history(&alice, None, None).unwrap();
+		assert_eq!(all.len() as u32, PAGE_SIZE + 10);
+		assert_eq!(all.first(), Some(&supplied(1, 0)));
+		assert_eq!(all.last(), Some(&supplied(PAGE_SIZE + 10, 0)));
+
+		let range = history
+			.account_history(&alice, Some(PAGE_SIZE - 1), Some(PAGE_SIZE + 2))
+			.unwrap();
+		let blocks: Vec<_> = range.iter().map(|entry| entry.block_number).collect();
+		assert_eq!(blocks, (PAGE_SIZE - 1..=PAGE_SIZE + 2).collect::<Vec<_>>());
+
+		assert_eq!(history.account_history(&bob, None, None).unwrap(), vec![supplied(5, 1)]);
+		assert_eq!(history.account_history(&bob, Some(6), None).unwrap(), vec![]);
+		assert_eq!(history.last_indexed(), Some(PAGE_SIZE + 10));
+	}
+}
@@ -1,4 +1,5 @@
 pub mod chain_spec;
+pub mod lending_history;
 pub mod rpc;
 pub mod rpc_api;
 pub mod rpc_impl;
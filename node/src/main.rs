@@ -7,6 +7,7 @@ mod service;
 mod benchmarking;
 mod cli;
 mod command;
+mod lending_history;
 mod rpc;
 mod rpc_api;
 mod rpc_impl;
@@ -7,45 +7,58 @@
 
 use std::sync::Arc;
 
+use crate::lending_history::LendingHistory;
 use jsonrpsee::RpcModule;
 use kylix_runtime::{opaque::Block, AccountId, Balance, Nonce};
+use sc_client_api::BlockchainEvents;
+use sc_rpc::SubscriptionTaskExecutor;
 use sc_transaction_pool_api::TransactionPool;
 use sp_api::ProvideRuntimeApi;
 use sp_block_builder::BlockBuilder;
 use sp_blockchain::{Error as BlockChainError, HeaderBackend, HeaderMetadata};
+use sp_core::offchain::OffchainStorage;
 
 pub use sc_rpc_api::DenyUnsafe;
 
 /// Full client dependencies.
-pub struct FullDeps<C, P> {
+pub struct FullDeps<C, P, S> {
 	/// The client instance to use.
 	pub client: Arc<C>,
 	/// Transaction pool instance.
 	pub pool: Arc<P>,
 	/// Whether to deny unsafe calls
 	pub deny_unsafe: DenyUnsafe,
+	/// Executor running the subscriptions
+	pub subscription_executor: SubscriptionTaskExecutor,
+	/// The lending history indexed by the node, `None` if the indexer is disabled
+	pub lending_history: Option<LendingHistory<S>>,
 }
 
 /// Instantiate all full RPC extensions.
-pub fn create_full<C, P>(
-	deps: FullDeps<C, P>,
+pub fn create_full<C, P, S>(
+	deps: FullDeps<C, P, S>,
 ) -> Result<RpcModule<()>, Box<dyn std::error::Error + Send + Sync>>
 where
 	C: ProvideRuntimeApi<Block>,
 	C: HeaderBackend<Block> + HeaderMetadata<Block, Error = BlockChainError> + 'static,
+	C: BlockchainEvents<Block>,
 	C: Send + Sync + 'static,
 	C::Api: substrate_frame_rpc_system::AccountNonceApi<Block, AccountId, Nonce>,
 	C::Api: pallet_transaction_payment_rpc::TransactionPaymentRuntimeApi<Block, Balance>,
 	C::Api: BlockBuilder<Block> + kylix_runtime::LendingPoolApi<Block>,
 	P: TransactionPool + 'static,
+	S: OffchainStorage + 'static,
 {
 	use pallet_transaction_payment_rpc::{TransactionPayment, TransactionPaymentApiServer};
 	use substrate_frame_rpc_system::{System, SystemApiServer};
 
-	use crate::{rpc_api::LendingPoolApiServer, rpc_impl::LendingPoolApiImpl};
+	use crate::{
+		rpc_api::{LendingHistoryApiServer, LendingPoolApiServer},
+		rpc_impl::{LendingHistoryApiImpl, LendingPoolApiImpl},
+	};
 
 	let mut module = RpcModule::new(());
-	let FullDeps { client, pool, deny_unsafe } = deps;
+	let FullDeps { client, pool, deny_unsafe, subscription_executor, lending_history } = deps;
 
 	module.merge(System::new(client.clone(), pool, deny_unsafe).into_rpc())?;
 	module.merge(TransactionPayment::new(client.clone()).into_rpc())?;
@@ -55,7 +68,17 @@ where
 	// to call into the runtime.
 	// `module.merge(YourRpcTrait::into_rpc(YourRpcStruct::new(ReferenceToClient, ...)))?;`
 
-	module.merge(LendingPoolApiServer::into_rpc(LendingPoolApiImpl::new(client.clone())))?;
+	// The subscriptions are fed by the client's block import and finality notification streams.
+	// They call the runtime at every block, so they are unsafe.
+	module.merge(LendingPoolApiServer::into_rpc(LendingPoolApiImpl::new(
+		client.clone(),
+		subscription_executor,
+		deny_unsafe,
+	)))?;
+
+	if let Some(history) = lending_history {
+		module.merge(LendingHistoryApiServer::into_rpc(LendingHistoryApiImpl::new(history)))?;
+	}
 
 	Ok(module)
 }
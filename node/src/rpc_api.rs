@@ -1,10 +1,12 @@
+use crate::lending_history::HistoryEntry;
 use jsonrpsee::{core::RpcResult, proc_macros::rpc};
 use kylix_runtime::{
 	lending::{
-		AggregatedTotals, BorrowedAsset, CollateralAsset, LendingPoolInfo, SuppliedAsset,
-		TotalBorrow, TotalCollateral, TotalDeposit,
+		AggregatedTotals, BorrowedAsset, CollateralAsset, LendingPoolInfo, LiquidatablePosition,
+		PoolStats, RateCurve, SimulatedAction, SimulationResult, SuppliedAsset, TotalBorrow,
+		TotalCollateral, TotalDeposit,
 	},
-	AccountId, AssetId, Balance, UserLTVInfo,
+	AccountId, AssetId, Balance, BlockNumber, UserLTVInfo,
 };
 use sp_runtime::FixedU128;
 
@@ -13,28 +15,53 @@ use sp_runtime::FixedU128;
 /// This trait outlines the RPC methods available for interacting with lending pools
 /// on the blockchain. It is marked with the `#[rpc(client, server)]` attribute to
 /// automatically generate client and server implementations for each RPC method.
+///
+/// Failed calls return one of the error codes of [`crate::rpc_impl::Error`]: `1` if the runtime
+/// API could not be called, `2` if the lending pool does not exist, `3` if an asset price is not
+/// set, `4` on arithmetic overflow, `5` for any other error of the pallet and `7` if the runtime
+/// at the block does not support the request. The error data holds the runtime API failure
+/// message, or the `LendingApiError` returned by the runtime.
+///
+/// Every method takes an optional `at` block hash as last parameter, like the standard Substrate
+/// RPCs, to query the state of a past block. It defaults to the best block. The blocks of
+/// runtimes with version 1 of the lending runtime API are queried with its methods, whose
+/// responses are converted to the current ones. They can't paginate or filter the lending pools.
 #[rpc(client, server)]
-pub trait LendingPoolApi {
+pub trait LendingPoolApi<BlockHash> {
 	/// Retrieves lending pool information and aggregated totals.
 	///
-	/// Returns details of all lending pools, including pool identifiers, associated assets,
-	/// and their aggregated totals.
+	/// Returns details of a page of lending pools, including pool identifiers, associated
+	/// assets, and their aggregated totals. To fetch the next page, pass the `asset_id` of the
+	/// last pool of the previous page as `start_key`.
+	///
+	/// # Parameters
+	///
+	/// * `asset_id` - Only return the pool of this asset.
+	/// * `account_id` - Include the supplied and asset balances of this account.
+	/// * `start_key` - Only return the pools after the pool of this asset.
+	/// * `limit` - Return at most this many pools, all of them if `None`.
+	/// * `active_only` - Skip the pools that are not activated, `false` if omitted.
+	/// * `at` - The hash of the block to query, the best block if `None`.
 	///
 	/// # Returns
 	///
 	/// * `RpcResult<(Vec<LendingPoolInfo>, AggregatedTotals)>` - A result containing:
 	///   - A vector of `LendingPoolInfo`, each representing a lending pool.
-	///   - `AggregatedTotals`, which aggregates data for all lending pools.
+	///   - `AggregatedTotals`, which aggregates data for the returned lending pools.
 	///
 	/// # Errors
 	///
 	/// Returns an `RpcResult` containing an error if data retrieval fails, e.g., due to
-	/// blockchain state access issues.
+	/// blockchain state access issues, or if there is no lending pool for `asset_id`.
 	#[method(name = "getLendingPools")]
 	fn get_lending_pools(
 		&self,
 		asset_id: Option<AssetId>,
 		account_id: Option<AccountId>,
+		start_key: Option<AssetId>,
+		limit: Option<u32>,
+		active_only: Option<bool>,
+		at: Option<BlockHash>,
 	) -> RpcResult<(Vec<LendingPoolInfo>, AggregatedTotals)>;
 
 	/// Retrieves the Loan-to-Value (LTV) information for a user.
@@ -42,18 +69,21 @@ pub trait LendingPoolApi {
 	/// # Parameters
 	///
 	/// * `account` - The `AccountId` of the user.
+	/// * `at` - The hash of the block to query, the best block if `None`.
 	///
 	/// # Returns
 	///
-	/// * `RpcResult<UserLTVInfo>` - The LTV information for the user.
+	/// * `RpcResult<UserLTVInfo>` - The LTV information for the user: its overall LTV and health
+	///   factor, and the LTV, health factor and collateral liquidation price of each of its loans.
 	#[method(name = "getUserLtv")]
-	fn get_user_ltv(&self, account: AccountId) -> RpcResult<UserLTVInfo>;
+	fn get_user_ltv(&self, account: AccountId, at: Option<BlockHash>) -> RpcResult<UserLTVInfo>;
 
 	/// Retrieves the supplied assets and total deposits for a user.
 	///
 	/// # Parameters
 	///
 	/// * `account` - The `AccountId` of the user.
+	/// * `at` - The hash of the block to query, the best block if `None`.
 	///
 	/// # Returns
 	///
@@ -64,6 +94,7 @@ pub trait LendingPoolApi {
 	fn get_asset_wise_supplies(
 		&self,
 		account: AccountId,
+		at: Option<BlockHash>,
 	) -> RpcResult<(Vec<SuppliedAsset>, TotalDeposit)>;
 
 	/// Retrieves borrowed assets, collaterals, total borrow, and total collateral for a user.
@@ -71,6 +102,7 @@ pub trait LendingPoolApi {
 	/// # Parameters
 	///
 	/// * `account` - The `AccountId` of the user.
+	/// * `at` - The hash of the block to query, the best block if `None`.
 	///
 	/// # Returns
 	///
@@ -83,6 +115,7 @@ pub trait LendingPoolApi {
 	fn get_asset_wise_borrows_collaterals(
 		&self,
 		account: AccountId,
+		at: Option<BlockHash>,
 	) -> RpcResult<(Vec<BorrowedAsset>, Vec<CollateralAsset>, TotalBorrow, TotalCollateral)>;
 
 	/// Fetches the price of a specified asset relative to a base asset.
@@ -91,16 +124,22 @@ pub trait LendingPoolApi {
 	///
 	/// * `asset` - The `AssetId` for which the price is to be retrieved.
 	/// * `base_asset` - An optional `AssetId` representing the base asset for the price comparison.
+	/// * `at` - The hash of the block to query, the best block if `None`.
 	///
 	/// # Returns
 	///
-	/// * `RpcResult<Option<FixedU128>>` - The price as a `FixedU128`, if available.
+	/// * `RpcResult<FixedU128>` - The price as a `FixedU128`.
+	///
+	/// # Errors
+	///
+	/// Returns a `PriceNotSet` error if no price of `asset` in `base_asset` is available.
 	#[method(name = "getAssetPrice")]
 	fn get_asset_price(
 		&self,
 		asset: AssetId,
 		base_asset: Option<AssetId>,
-	) -> RpcResult<Option<FixedU128>>;
+		at: Option<BlockHash>,
+	) -> RpcResult<FixedU128>;
 
 	/// Estimates the amount of collateral required for a specified borrow amount and asset.
 	///
@@ -112,21 +151,232 @@ pub trait LendingPoolApi {
 	/// * `borrow_asset` - The `AssetId` of the asset the user wants to borrow.
 	/// * `borrow_amount` - The amount of the asset to be borrowed.
 	/// * `collateral_asset` - The `AssetId` of the asset to be used as collateral.
+	/// * `at` - The hash of the block to query, the best block if `None`.
 	///
 	/// # Returns
 	///
-	/// * `RpcResult<Option<Balance>>` - The estimated amount of collateral required as `Balance`,
-	///   if the estimation is available.
+	/// * `RpcResult<Balance>` - The estimated amount of collateral required as `Balance`.
 	///
 	/// # Errors
 	///
 	/// Returns an error if the estimation process fails or cannot retrieve the necessary data
-	/// from the runtime.
+	/// from the runtime, e.g. `PoolNotFound` or `PriceNotSet`.
 	#[method(name = "getEstimateCollateralAmount")]
 	fn get_estimate_collateral_amount(
 		&self,
 		borrow_asset: AssetId,
 		borrow_amount: Balance,
 		collateral_asset: AssetId,
-	) -> RpcResult<Option<Balance>>;
+		at: Option<BlockHash>,
+	) -> RpcResult<Balance>;
+
+	/// Simulates an action of an account, without changing the state.
+	///
+	/// The action is performed in a storage transaction that is rolled back, to show what a
+	/// supply, withdrawal, borrow or repayment would do before signing it.
+	///
+	/// # Parameters
+	///
+	/// * `account` - The `AccountId` of the user performing the action.
+	/// * `action` - The `SimulatedAction` to perform.
+	/// * `at` - The hash of the block to query, the best block if `None`.
+	///
+	/// # Returns
+	///
+	/// * `RpcResult<SimulationResult>` - The LTV and health factor of the user and the rates of the
+	///   pool after the action, or the error the action would fail with.
+	#[method(name = "simulateAction")]
+	fn simulate_action(
+		&self,
+		account: AccountId,
+		action: SimulatedAction,
+		at: Option<BlockHash>,
+	) -> RpcResult<SimulationResult>;
+
+	/// Retrieves the maximum amount a user can borrow against a collateral asset.
+	///
+	/// # Parameters
+	///
+	/// * `account` - The `AccountId` of the user.
+	/// * `borrow_asset` - The `AssetId` of the asset to borrow.
+	/// * `collateral_asset` - The `AssetId` of the asset to be used as collateral.
+	/// * `at` - The hash of the block to query, the best block if `None`.
+	///
+	/// # Returns
+	///
+	/// * `RpcResult<Balance>` - The largest amount a borrow accepts, limited by the liquidity of
+	///   the pool and the collateral of the user. Zero if the pool is not active.
+	///
+	/// # Errors
+	///
+	/// Returns an error if data retrieval fails, e.g. `PoolNotFound` or `PriceNotSet`.
+	#[method(name = "getMaxBorrowable")]
+	fn max_borrowable(
+		&self,
+		account: AccountId,
+		borrow_asset: AssetId,
+		collateral_asset: AssetId,
+		at: Option<BlockHash>,
+	) -> RpcResult<Balance>;
+
+	/// Retrieves the maximum amount a user can withdraw from a lending pool.
+	///
+	/// # Parameters
+	///
+	/// * `account` - The `AccountId` of the user.
+	/// * `asset` - The `AssetId` of the asset of the lending pool.
+	/// * `at` - The hash of the block to query, the best block if `None`.
+	///
+	/// # Returns
+	///
+	/// * `RpcResult<Balance>` - The deposit of the user including interest, limited by the
+	///   liquidity of the pool.
+	///
+	/// # Errors
+	///
+	/// Returns an error if data retrieval fails, e.g. `PoolNotFound`.
+	#[method(name = "getMaxWithdrawable")]
+	fn max_withdrawable(
+		&self,
+		account: AccountId,
+		asset: AssetId,
+		at: Option<BlockHash>,
+	) -> RpcResult<Balance>;
+
+	/// Lists the loans of the accounts whose weighted health factor is below one.
+	///
+	/// Loans are paged in storage order: pass the borrower, borrowed asset and collateral asset
+	/// of the last position of a page as `cursor` to get the next one.
+	///
+	/// # Parameters
+	///
+	/// * `limit` - Return at most this many positions.
+	/// * `cursor` - Only return the loans after this loan.
+	/// * `at` - The hash of the block to query, the best block if `None`.
+	///
+	/// # Returns
+	///
+	/// * `RpcResult<Vec<LiquidatablePosition<AccountId>>>` - The liquidatable loans with their
+	///   debt, collateral and health factor, valued in USDT. Loans whose value can not be computed
+	///   are skipped.
+	#[method(name = "getLiquidatablePositions")]
+	fn get_liquidatable_positions(
+		&self,
+		limit: u32,
+		cursor: Option<(AccountId, AssetId, AssetId)>,
+		at: Option<BlockHash>,
+	) -> RpcResult<Vec<LiquidatablePosition<AccountId>>>;
+
+	/// Retrieves the market statistics of a lending pool.
+	///
+	/// # Parameters
+	///
+	/// * `asset` - The `AssetId` of the asset of the lending pool.
+	/// * `at` - The hash of the block to query, the best block if `None`.
+	///
+	/// # Returns
+	///
+	/// * `RpcResult<PoolStats>` - The totals with accrued interest, indexes, number of suppliers
+	///   and borrowers, utilisation, rate model and risk parameters of the pool.
+	///
+	/// # Errors
+	///
+	/// Returns a `PoolNotFound` error if there is no lending pool for `asset`.
+	#[method(name = "getPoolStats")]
+	fn get_pool_stats(&self, asset: AssetId, at: Option<BlockHash>) -> RpcResult<PoolStats>;
+
+	/// Samples the interest rate curve of a lending pool, for charts.
+	///
+	/// # Parameters
+	///
+	/// * `asset` - The `AssetId` of the asset of the lending pool.
+	/// * `points` - The number of evenly spaced utilisations to sample, between 2 and
+	///   `MAX_RATE_CURVE_POINTS`.
+	/// * `at` - The hash of the block to query, the best block if `None`.
+	///
+	/// # Returns
+	///
+	/// * `RpcResult<RateCurve>` - The borrow and supply rates at each utilisation, and the current
+	///   utilisation of the pool with the rates of the curve at it.
+	///
+	/// # Errors
+	///
+	/// Returns a `PoolNotFound` error if there is no lending pool for `asset`.
+	#[method(name = "getRateCurve")]
+	fn get_rate_curve(
+		&self,
+		asset: AssetId,
+		points: u32,
+		at: Option<BlockHash>,
+	) -> RpcResult<RateCurve>;
+
+	/// Subscribes to the lending pools and their aggregated totals.
+	///
+	/// Sends the pools at the best block, then again whenever a new best or finalized block
+	/// changes them. The pools are returned with the interest accrued up to each block, so while
+	/// any pool has borrows an update is sent for every new best or finalized block.
+	///
+	/// The subscription queries the runtime at every block, so it is unsafe: nodes whose RPC
+	/// methods are exposed with `--rpc-methods safe` reject it.
+	///
+	/// # Returns
+	///
+	/// * `(Vec<LendingPoolInfo>, AggregatedTotals)` notifications, as returned by `getLendingPools`
+	///   without parameters.
+	#[subscription(
+		name = "subscribeLendingPools" => "lendingPools",
+		unsubscribe = "unsubscribeLendingPools",
+		item = (Vec<LendingPoolInfo>, AggregatedTotals)
+	)]
+	fn subscribe_lending_pools(&self);
+
+	/// Subscribes to the Loan-to-Value (LTV) information of a user.
+	///
+	/// Sends the LTV information at the best block, then again whenever a new best or finalized
+	/// block changes it. The debts include the interest accrued up to each block, so while the
+	/// user has loans an update is sent for every new best or finalized block.
+	///
+	/// Unsafe like `subscribeLendingPools`.
+	///
+	/// # Parameters
+	///
+	/// * `account` - The `AccountId` of the user.
+	///
+	/// # Returns
+	///
+	/// * `UserLTVInfo` notifications, as returned by `getUserLtv`.
+	#[subscription(
+		name = "subscribeUserPosition" => "userPosition",
+		unsubscribe = "unsubscribeUserPosition",
+		item = UserLTVInfo
+	)]
+	fn subscribe_user_position(&self, account: AccountId);
+}
+
+/// RPC interface for the lending history indexed by the node.
+///
+/// Only served when the node runs with `--lending-history`, which indexes the lending events of
+/// the finalized blocks. Failed calls return the error code `6` of [`crate::rpc_impl::Error`].
+#[rpc(client, server, namespace = "lending")]
+pub trait LendingHistoryApi {
+	/// Retrieves the lending history of an account.
+	///
+	/// # Parameters
+	///
+	/// * `account` - The `AccountId` of the user.
+	/// * `from` - Only return the entries from this block, the first indexed block if `None`.
+	/// * `to` - Only return the entries up to this block, the last indexed block if `None`.
+	///
+	/// # Returns
+	///
+	/// * `RpcResult<Vec<HistoryEntry>>` - The supplies, withdrawals, borrows, repayments, LP token
+	///   mints and liquidations of the account in finalized blocks, oldest first. Repayments on
+	///   behalf of a borrower and liquidations are in the history of both parties.
+	#[method(name = "getAccountHistory")]
+	fn get_account_history(
+		&self,
+		account: AccountId,
+		from: Option<BlockNumber>,
+		to: Option<BlockNumber>,
+	) -> RpcResult<Vec<HistoryEntry>>;
 }
@@ -1,17 +1,277 @@
-use crate::rpc_api::LendingPoolApiServer;
-use jsonrpsee::core::RpcResult;
+use crate::{
+	lending_history::{HistoryEntry, LendingHistory},
+	rpc_api::{LendingHistoryApiServer, LendingPoolApiServer},
+};
+use futures::{future, stream, FutureExt, StreamExt};
+use jsonrpsee::{
+	core::{Error as JsonRpseeError, RpcResult},
+	types::{
+		error::{CallError, ErrorObject},
+		SubscriptionResult,
+	},
+	SubscriptionSink,
+};
 use kylix_runtime::{
 	lending::{
-		AggregatedTotals, BorrowedAsset, CollateralAsset, LendingPoolInfo, SuppliedAsset,
-		TotalBorrow, TotalCollateral, TotalDeposit,
+		AggregatedTotals, BorrowedAsset, CollateralAsset, LendingApiError, LendingPoolInfo,
+		LiquidatablePosition, PoolStats, RateCurve, SimulatedAction, SimulationResult,
+		SuppliedAsset, TotalBorrow, TotalCollateral, TotalDeposit,
 	},
-	AccountId, AssetId, Balance, LendingPoolApi, UserLTVInfo,
+	AccountId, AssetId, Balance, BlockNumber, LendingPoolApi, UserLTVInfo,
 };
-use sp_api::ProvideRuntimeApi;
+use sc_client_api::BlockchainEvents;
+use sc_rpc::SubscriptionTaskExecutor;
+use sc_rpc_api::DenyUnsafe;
+use serde::Serialize;
+use sp_api::{ApiError, ApiExt, ProvideRuntimeApi};
 use sp_blockchain::HeaderBackend;
+use sp_core::offchain::OffchainStorage;
 use sp_runtime::{traits::Block as BlockT, FixedU128};
 use std::sync::Arc;
 
+/// Error codes of the lending RPCs. Every error carries a data payload: the message of the
+/// runtime API or lending history failure, or the `LendingApiError` returned by the runtime.
+pub enum Error {
+	/// The runtime API could not be called
+	RuntimeError,
+	/// There is no lending pool for the asset
+	PoolNotFound,
+	/// The price of an asset is not set
+	PriceNotSet,
+	/// A computation overflowed or divided by zero
+	Overflow,
+	/// The lending pallet raised another error
+	PalletError,
+	/// The lending history could not be read
+	HistoryError,
+	/// The runtime at the block does not support the request
+	Unsupported,
+}
+
+impl From<Error> for i32 {
+	fn from(e: Error) -> i32 {
+		match e {
+			Error::RuntimeError => 1,
+			Error::PoolNotFound => 2,
+			Error::PriceNotSet => 3,
+			Error::Overflow => 4,
+			Error::PalletError => 5,
+			Error::HistoryError => 6,
+			Error::Unsupported => 7,
+		}
+	}
+}
+
+/// Maps a failure to call the runtime API to an RPC error
+fn runtime_error(error: ApiError) -> JsonRpseeError {
+	CallError::Custom(ErrorObject::owned(
+		Error::RuntimeError.into(),
+		"Unable to call the lending runtime API",
+		Some(error.to_string()),
+	))
+	.into()
+}
+
+/// Maps an error returned by the lending runtime API to an RPC error carrying it as data
+fn api_error(error: LendingApiError) -> JsonRpseeError {
+	let (code, message) = match &error {
+		LendingApiError::PoolNotFound { .. } =>
+			(Error::PoolNotFound, "Lending pool not found".to_string()),
+		LendingApiError::PriceNotSet { .. } =>
+			(Error::PriceNotSet, "Asset price not set".to_string()),
+		LendingApiError::Overflow => (Error::Overflow, "Arithmetic overflow".to_string()),
+		LendingApiError::Other { error } =>
+			(Error::PalletError, format!("Lending error: {}", String::from_utf8_lossy(error))),
+	};
+	CallError::Custom(ErrorObject::owned(code.into(), message, Some(error))).into()
+}
+
+/// Rejects a request the runtime at the block cannot answer
+fn unsupported_error(message: &str) -> JsonRpseeError {
+	CallError::Custom(ErrorObject::owned(Error::Unsupported.into(), message, None::<()>)).into()
+}
+
+/// The first version of the lending runtime API returning a `LendingApiError` on failure
+const API_VERSION_WITH_ERRORS: u32 = 2;
+/// The first version of the lending runtime API paginating `get_lending_pools`
+const API_VERSION_WITH_PAGINATION: u32 = 3;
+
+/// The lending runtime API at a block, calling the methods of the version of the runtime API
+/// there. The responses of version 1 are converted to the current ones.
+struct LendingApiAt<'a, Api, Block: BlockT> {
+	api: &'a Api,
+	at: Block::Hash,
+	version: u32,
+}
+
+impl<'a, Api, Block> LendingApiAt<'a, Api, Block>
+where
+	Api: ApiExt<Block> + LendingPoolApi<Block>,
+	Block: BlockT,
+{
+	/// Reads the version of the lending runtime API at `at`
+	fn new(api: &'a Api, at: Block::Hash) -> RpcResult<Self> {
+		let version = api
+			.api_version::<dyn LendingPoolApi<Block>>(at)
+			.map_err(runtime_error)?
+			.ok_or_else(|| unsupported_error("The runtime has no lending API at this block"))?;
+		Ok(Self { api, at, version })
+	}
+}
+
+#[allow(deprecated)]
+impl<'a, Api, Block> LendingApiAt<'a, Api, Block>
+where
+	Api: LendingPoolApi<Block>,
+	Block: BlockT,
+{
+	fn get_lending_pools(
+		&self,
+		asset_id: Option<AssetId>,
+		account_id: Option<AccountId>,
+		start_key: Option<AssetId>,
+		limit: Option<u32>,
+		active_only: bool,
+	) -> RpcResult<(Vec<LendingPoolInfo>, AggregatedTotals)> {
+		if self.version >= API_VERSION_WITH_PAGINATION {
+			return self
+				.api
+				.get_lending_pools(self.at, asset_id, account_id, start_key, limit, active_only)
+				.map_err(runtime_error)?
+				.map_err(api_error)
+		}
+		if start_key.is_some() || limit.is_some() || active_only {
+			return Err(unsupported_error(
+				"The runtime does not paginate or filter the lending pools at this block",
+			))
+		}
+		let (pools, totals) = if self.version >= API_VERSION_WITH_ERRORS {
+			self.api
+				.get_lending_pools_before_version_3(self.at, asset_id, account_id)
+				.map_err(runtime_error)?
+				.map_err(api_error)?
+		} else {
+			let (pools, totals) = self
+				.api
+				.get_lending_pools_before_version_2(self.at, asset_id, account_id)
+				.map_err(runtime_error)?;
+			match asset_id {
+				Some(asset) if pools.is_empty() =>
+					return Err(api_error(LendingApiError::PoolNotFound { asset })),
+				_ => (pools, totals),
+			}
+		};
+		Ok((pools.into_iter().map(Into::into).collect(), totals))
+	}
+
+	fn get_user_ltv(&self, account: AccountId) -> RpcResult<UserLTVInfo> {
+		if self.version >= API_VERSION_WITH_ERRORS {
+			return self
+				.api
+				.get_user_ltv(self.at, account)
+				.map_err(runtime_error)?
+				.map_err(api_error)
+		}
+		let info = self
+			.api
+			.get_user_ltv_before_version_2(self.at, account)
+			.map_err(runtime_error)?;
+		Ok(info.into())
+	}
+
+	fn get_asset_wise_supplies(
+		&self,
+		account: AccountId,
+	) -> RpcResult<(Vec<SuppliedAsset>, TotalDeposit)> {
+		if self.version >= API_VERSION_WITH_ERRORS {
+			return self
+				.api
+				.get_asset_wise_supplies(self.at, account)
+				.map_err(runtime_error)?
+				.map_err(api_error)
+		}
+		let (supplies, total_deposit) = self
+			.api
+			.get_asset_wise_supplies_before_version_2(self.at, account)
+			.map_err(runtime_error)?;
+		Ok((supplies.into_iter().map(Into::into).collect(), total_deposit))
+	}
+
+	fn get_asset_wise_borrows_collaterals(
+		&self,
+		account: AccountId,
+	) -> RpcResult<(Vec<BorrowedAsset>, Vec<CollateralAsset>, TotalBorrow, TotalCollateral)> {
+		if self.version >= API_VERSION_WITH_ERRORS {
+			return self
+				.api
+				.get_asset_wise_borrows_collaterals(self.at, account)
+				.map_err(runtime_error)?
+				.map_err(api_error)
+		}
+		let (borrows, collaterals, total_borrow, total_collateral) = self
+			.api
+			.get_asset_wise_borrows_collaterals_before_version_2(self.at, account)
+			.map_err(runtime_error)?;
+		Ok((
+			borrows.into_iter().map(Into::into).collect(),
+			collaterals.into_iter().map(Into::into).collect(),
+			total_borrow,
+			total_collateral,
+		))
+	}
+
+	/// Version 1 returns `None` whenever the price is not available
+	fn get_asset_price(&self, asset: AssetId, base_asset: Option<AssetId>) -> RpcResult<FixedU128> {
+		if self.version >= API_VERSION_WITH_ERRORS {
+			return self
+				.api
+				.get_asset_price(self.at, asset, base_asset)
+				.map_err(runtime_error)?
+				.map_err(api_error)
+		}
+		self.api
+			.get_asset_price_before_version_2(self.at, asset, base_asset)
+			.map_err(runtime_error)?
+			.ok_or_else(|| {
+				// the runtime values the asset in USDT by default
+				let base_asset = base_asset.unwrap_or(1);
+				api_error(LendingApiError::PriceNotSet { asset, base_asset })
+			})
+	}
+
+	/// Version 1 returns `None` whatever the estimate failed on
+	fn get_estimate_collateral_amount(
+		&self,
+		borrow_asset: AssetId,
+		borrow_amount: Balance,
+		collateral_asset: AssetId,
+	) -> RpcResult<Balance> {
+		if self.version >= API_VERSION_WITH_ERRORS {
+			return self
+				.api
+				.get_estimate_collateral_amount(
+					self.at,
+					borrow_asset,
+					borrow_amount,
+					collateral_asset,
+				)
+				.map_err(runtime_error)?
+				.map_err(api_error)
+		}
+		self.api
+			.get_estimate_collateral_amount_before_version_2(
+				self.at,
+				borrow_asset,
+				borrow_amount,
+				collateral_asset,
+			)
+			.map_err(runtime_error)?
+			.ok_or_else(|| {
+				api_error(LendingApiError::Other { error: b"EstimateNotAvailable".to_vec() })
+			})
+	}
+}
+
 /// RPC method implementation for the Lending Pool API.
 ///
 /// Provides methods to interact with lending pool functionality as defined in the runtime API.
@@ -19,6 +279,10 @@ use std::sync::Arc;
 pub struct LendingPoolApiImpl<C, P> {
 	/// Shared reference to the client for accessing blockchain state and runtime APIs.
 	client: Arc<C>,
+	/// Executor running the subscriptions.
+	executor: SubscriptionTaskExecutor,
+	/// Whether to deny the subscriptions, which are unsafe.
+	deny_unsafe: DenyUnsafe,
 	/// Marker for associating the struct with a type `P`, used when the implementation depends on
 	/// a phantom type parameter.
 	_marker: std::marker::PhantomData<P>,
@@ -31,14 +295,75 @@ impl<C, P> LendingPoolApiImpl<C, P> {
 	///
 	/// * `client` - An `Arc` reference to the client providing access to runtime APIs and
 	///   blockchain state.
-	pub fn new(client: Arc<C>) -> Self {
-		Self { client, _marker: Default::default() }
+	/// * `executor` - The executor running the subscriptions.
+	/// * `deny_unsafe` - Whether to deny the subscriptions, which are unsafe.
+	pub fn new(
+		client: Arc<C>,
+		executor: SubscriptionTaskExecutor,
+		deny_unsafe: DenyUnsafe,
+	) -> Self {
+		Self { client, executor, deny_unsafe, _marker: Default::default() }
+	}
+}
+
+impl<C, Block> LendingPoolApiImpl<C, Block>
+where
+	C: HeaderBackend<Block> + BlockchainEvents<Block> + Send + Sync + 'static,
+	Block: BlockT,
+{
+	/// Pipes the result of `query` at the best block into `sink`, then again at every new best
+	/// or finalized block whose result differs from the last one sent. Blocks at which `query`
+	/// fails are skipped.
+	///
+	/// The lending runtime APIs accrue interest up to the timestamp of the block they are called
+	/// at, so while a pool has borrows its results differ at every block and an update is sent
+	/// for every new best or finalized block. Each subscription thus calls the runtime at every
+	/// block for as long as it is open, which is why they are unsafe and rejected when
+	/// `deny_unsafe` is set.
+	fn subscribe<T, F>(&self, mut sink: SubscriptionSink, query: F)
+	where
+		T: Serialize + PartialEq + Clone + Send + 'static,
+		F: Fn(Block::Hash) -> Option<T> + Send + 'static,
+	{
+		if let Err(err) = self.deny_unsafe.check_if_safe() {
+			let _ = sink.reject(JsonRpseeError::from(err));
+			return
+		}
+
+		let best = stream::once(future::ready(self.client.info().best_hash));
+		let imported = self.client.import_notification_stream().filter_map(|notification| {
+			future::ready(notification.is_new_best.then_some(notification.hash))
+		});
+		let finalized =
+			self.client.finality_notification_stream().map(|notification| notification.hash);
+
+		let mut last = None;
+		let updates = best
+			.chain(stream::select(imported, finalized))
+			.filter_map(move |hash| {
+				let update = query(hash).filter(|update| last.as_ref() != Some(update));
+				if update.is_some() {
+					last = update.clone();
+				}
+				future::ready(update)
+			})
+			.boxed();
+
+		let fut = async move {
+			sink.pipe_from_stream(updates).await;
+		};
+		self.executor.spawn("kylix-lending-rpc-subscription", Some("rpc"), fut.boxed());
 	}
 }
 
-impl<C, Block> LendingPoolApiServer for LendingPoolApiImpl<C, Block>
+impl<C, Block> LendingPoolApiServer<<Block as BlockT>::Hash> for LendingPoolApiImpl<C, Block>
 where
-	C: ProvideRuntimeApi<Block> + HeaderBackend<Block> + Send + Sync + 'static,
+	C: ProvideRuntimeApi<Block>
+		+ HeaderBackend<Block>
+		+ BlockchainEvents<Block>
+		+ Send
+		+ Sync
+		+ 'static,
 	Block: BlockT,
 	C::Api: LendingPoolApi<Block>,
 {
@@ -55,21 +380,28 @@ where
 	///
 	/// # Errors
 	///
-	/// Returns an error if the runtime API call fails.
+	/// Returns an error if the runtime API call fails, `PoolNotFound` if there is no pool for
+	/// `asset_id`, or `Unsupported` if the runtime at the block can't paginate or filter them.
 	fn get_lending_pools(
 		&self,
 		asset_id: Option<AssetId>,
 		account_id: Option<AccountId>,
+		start_key: Option<AssetId>,
+		limit: Option<u32>,
+		active_only: Option<bool>,
+		at: Option<<Block as BlockT>::Hash>,
 	) -> RpcResult<(Vec<LendingPoolInfo>, AggregatedTotals)> {
-		// Access runtime API.
 		let api = self.client.runtime_api();
-		// Retrieve hash of the best block.
-		let best_block_hash = self.client.info().best_hash;
-		// Invoke the runtime method and handle errors.
-		let result = api
-			.get_lending_pools(best_block_hash, asset_id, account_id)
-			.map_err(|e| jsonrpsee::core::Error::Custom(e.to_string()))?;
-		Ok(result)
+		// Query the given block, or the best one.
+		let at = at.unwrap_or_else(|| self.client.info().best_hash);
+		// Invoke the method of the runtime API version at the block.
+		LendingApiAt::<_, Block>::new(&*api, at)?.get_lending_pools(
+			asset_id,
+			account_id,
+			start_key,
+			limit,
+			active_only.unwrap_or_default(),
+		)
 	}
 
 	/// Retrieves Loan-to-Value (LTV) information for a specific user.
@@ -77,14 +409,15 @@ where
 	/// # Returns
 	///
 	/// `RpcResult<UserLTVInfo>` containing the user's LTV information.
-	fn get_user_ltv(&self, account: AccountId) -> RpcResult<UserLTVInfo> {
+	fn get_user_ltv(
+		&self,
+		account: AccountId,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> RpcResult<UserLTVInfo> {
 		let api = self.client.runtime_api();
-		let best_block_hash = self.client.info().best_hash;
+		let at = at.unwrap_or_else(|| self.client.info().best_hash);
 
-		let result = api
-			.get_user_ltv(best_block_hash, account)
-			.map_err(|e| jsonrpsee::core::Error::Custom(e.to_string()))?;
-		Ok(result)
+		LendingApiAt::<_, Block>::new(&*api, at)?.get_user_ltv(account)
 	}
 
 	/// Retrieves supplied assets and total deposits for a user.
@@ -97,14 +430,12 @@ where
 	fn get_asset_wise_supplies(
 		&self,
 		account: AccountId,
+		at: Option<<Block as BlockT>::Hash>,
 	) -> RpcResult<(Vec<SuppliedAsset>, TotalDeposit)> {
 		let api = self.client.runtime_api();
-		let best_block_hash = self.client.info().best_hash;
+		let at = at.unwrap_or_else(|| self.client.info().best_hash);
 
-		let result = api
-			.get_asset_wise_supplies(best_block_hash, account)
-			.map_err(|e| jsonrpsee::core::Error::Custom(e.to_string()))?;
-		Ok(result)
+		LendingApiAt::<_, Block>::new(&*api, at)?.get_asset_wise_supplies(account)
 	}
 
 	/// Retrieves borrowed assets, collateral assets, total borrow, and total collateral for a user.
@@ -118,57 +449,558 @@ where
 	fn get_asset_wise_borrows_collaterals(
 		&self,
 		account: AccountId,
+		at: Option<<Block as BlockT>::Hash>,
 	) -> RpcResult<(Vec<BorrowedAsset>, Vec<CollateralAsset>, TotalBorrow, TotalCollateral)> {
 		let api = self.client.runtime_api();
-		let best_block_hash = self.client.info().best_hash;
+		let at = at.unwrap_or_else(|| self.client.info().best_hash);
 
-		let result = api
-			.get_asset_wise_borrows_collaterals(best_block_hash, account)
-			.map_err(|e| jsonrpsee::core::Error::Custom(e.to_string()))?;
-		Ok(result)
+		LendingApiAt::<_, Block>::new(&*api, at)?.get_asset_wise_borrows_collaterals(account)
 	}
 
 	/// Retrieves the price of a specific asset relative to an optional base asset.
 	///
 	/// # Returns
 	///
-	/// `RpcResult<Option<FixedU128>>` containing the asset price as `FixedU128`, if available.
+	/// `RpcResult<FixedU128>` containing the asset price, or `PriceNotSet` if it is not
+	/// available.
 	fn get_asset_price(
 		&self,
 		asset: AssetId,
 		base_asset: Option<AssetId>,
-	) -> RpcResult<Option<FixedU128>> {
+		at: Option<<Block as BlockT>::Hash>,
+	) -> RpcResult<FixedU128> {
 		let api = self.client.runtime_api();
-		let best_block_hash = self.client.info().best_hash;
+		let at = at.unwrap_or_else(|| self.client.info().best_hash);
 
-		let result = api
-			.get_asset_price(best_block_hash, asset, base_asset)
-			.map_err(|e| jsonrpsee::core::Error::Custom(e.to_string()))?;
-		Ok(result)
+		LendingApiAt::<_, Block>::new(&*api, at)?.get_asset_price(asset, base_asset)
 	}
 
 	/// Estimates the amount of collateral required for a specified borrow amount and asset.
 	///
 	/// # Returns
 	///
-	/// A `RpcResult<Option<Balance>>` containing the estimated amount of collateral required.
+	/// A `RpcResult<Balance>` containing the estimated amount of collateral required.
 	fn get_estimate_collateral_amount(
 		&self,
 		borrow_asset: AssetId,
 		borrow_amount: Balance,
 		collateral_asset: AssetId,
-	) -> RpcResult<Option<Balance>> {
+		at: Option<<Block as BlockT>::Hash>,
+	) -> RpcResult<Balance> {
 		let api = self.client.runtime_api();
-		let best_block_hash = self.client.info().best_hash;
+		let at = at.unwrap_or_else(|| self.client.info().best_hash);
+
+		LendingApiAt::<_, Block>::new(&*api, at)?.get_estimate_collateral_amount(
+			borrow_asset,
+			borrow_amount,
+			collateral_asset,
+		)
+	}
+
+	/// Simulates an action of an account in a storage transaction that is rolled back.
+	///
+	/// # Returns
+	///
+	/// A `RpcResult<SimulationResult>` containing the position and pool rates after the action,
+	/// or the error the action would fail with.
+	fn simulate_action(
+		&self,
+		account: AccountId,
+		action: SimulatedAction,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> RpcResult<SimulationResult> {
+		let api = self.client.runtime_api();
+		let at = at.unwrap_or_else(|| self.client.info().best_hash);
 
 		let result = api
-			.get_estimate_collateral_amount(
-				best_block_hash,
-				borrow_asset,
-				borrow_amount,
-				collateral_asset,
-			)
-			.map_err(|e| jsonrpsee::core::Error::Custom(e.to_string()))?;
+			.simulate_action(at, account, action)
+			.map_err(runtime_error)?
+			.map_err(api_error)?;
+		Ok(result)
+	}
+
+	/// Retrieves the maximum amount a user can borrow against a collateral asset.
+	///
+	/// # Returns
+	///
+	/// A `RpcResult<Balance>` containing the largest amount a borrow accepts.
+	fn max_borrowable(
+		&self,
+		account: AccountId,
+		borrow_asset: AssetId,
+		collateral_asset: AssetId,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> RpcResult<Balance> {
+		let api = self.client.runtime_api();
+		let at = at.unwrap_or_else(|| self.client.info().best_hash);
+
+		let result = api
+			.max_borrowable(at, account, borrow_asset, collateral_asset)
+			.map_err(runtime_error)?
+			.map_err(api_error)?;
 		Ok(result)
 	}
+
+	/// Retrieves the maximum amount a user can withdraw from a lending pool.
+	///
+	/// # Returns
+	///
+	/// A `RpcResult<Balance>` containing the largest amount a withdrawal accepts.
+	fn max_withdrawable(
+		&self,
+		account: AccountId,
+		asset: AssetId,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> RpcResult<Balance> {
+		let api = self.client.runtime_api();
+		let at = at.unwrap_or_else(|| self.client.info().best_hash);
+
+		let result = api
+			.max_withdrawable(at, account, asset)
+			.map_err(runtime_error)?
+			.map_err(api_error)?;
+		Ok(result)
+	}
+
+	/// Lists the loans of the accounts whose weighted health factor is below one.
+	///
+	/// # Returns
+	///
+	/// A `RpcResult<Vec<LiquidatablePosition<AccountId>>>` containing up to `limit` positions
+	/// after `cursor`.
+	fn get_liquidatable_positions(
+		&self,
+		limit: u32,
+		cursor: Option<(AccountId, AssetId, AssetId)>,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> RpcResult<Vec<LiquidatablePosition<AccountId>>> {
+		let api = self.client.runtime_api();
+		let at = at.unwrap_or_else(|| self.client.info().best_hash);
+
+		let result = api
+			.get_liquidatable_positions(at, limit, cursor)
+			.map_err(runtime_error)?
+			.map_err(api_error)?;
+		Ok(result)
+	}
+
+	/// Retrieves the market statistics of a lending pool.
+	///
+	/// # Returns
+	///
+	/// A `RpcResult<PoolStats>` computed from the pool with its indexes updated to the block.
+	fn get_pool_stats(
+		&self,
+		asset: AssetId,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> RpcResult<PoolStats> {
+		let api = self.client.runtime_api();
+		let at = at.unwrap_or_else(|| self.client.info().best_hash);
+
+		let result = api.get_pool_stats(at, asset).map_err(runtime_error)?.map_err(api_error)?;
+		Ok(result)
+	}
+
+	/// Samples the interest rate curve of a lending pool.
+	///
+	/// # Returns
+	///
+	/// A `RpcResult<RateCurve>` computed on chain with the interest rate model of the pool.
+	fn get_rate_curve(
+		&self,
+		asset: AssetId,
+		points: u32,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> RpcResult<RateCurve> {
+		let api = self.client.runtime_api();
+		let at = at.unwrap_or_else(|| self.client.info().best_hash);
+
+		let result = api
+			.get_rate_curve(at, asset, points)
+			.map_err(runtime_error)?
+			.map_err(api_error)?;
+		Ok(result)
+	}
+
+	/// Subscribes to the lending pools, sending them whenever a new best or finalized block
+	/// changes them, which is every block while interest accrues.
+	fn subscribe_lending_pools(&self, sink: SubscriptionSink) -> SubscriptionResult {
+		let client = self.client.clone();
+		self.subscribe(sink, move |at| {
+			let api = client.runtime_api();
+			LendingApiAt::<_, Block>::new(&*api, at)
+				.ok()?
+				.get_lending_pools(None, None, None, None, false)
+				.ok()
+		});
+		Ok(())
+	}
+
+	/// Subscribes to the LTV information of `account`, sending it whenever a new best or
+	/// finalized block changes it, which is every block while interest accrues on its loans.
+	fn subscribe_user_position(
+		&self,
+		sink: SubscriptionSink,
+		account: AccountId,
+	) -> SubscriptionResult {
+		let client = self.client.clone();
+		self.subscribe(sink, move |at| {
+			let api = client.runtime_api();
+			LendingApiAt::<_, Block>::new(&*api, at)
+				.ok()?
+				.get_user_ltv(account.clone())
+				.ok()
+		});
+		Ok(())
+	}
+}
+
+/// RPC method implementation for the lending history API.
+///
+/// Reads the history indexed by the node in its offchain storage.
+pub struct LendingHistoryApiImpl<S> {
+	/// The history of the accounts, shared with the indexer.
+	history: LendingHistory<S>,
+}
+
+impl<S> LendingHistoryApiImpl<S> {
+	/// Creates a new `LendingHistoryApiImpl` serving `history`.
+	pub fn new(history: LendingHistory<S>) -> Self {
+		Self { history }
+	}
+}
+
+impl<S> LendingHistoryApiServer for LendingHistoryApiImpl<S>
+where
+	S: OffchainStorage + 'static,
+{
+	/// Retrieves the lending history of `account` in the blocks `from..=to`.
+	fn get_account_history(
+		&self,
+		account: AccountId,
+		from: Option<BlockNumber>,
+		to: Option<BlockNumber>,
+	) -> RpcResult<Vec<HistoryEntry>> {
+		self.history.account_history(&account, from, to).map_err(|e| {
+			CallError::Custom(ErrorObject::owned(
+				Error::HistoryError.into(),
+				"Unable to read the lending history",
+				Some(e.to_string()),
+			))
+			.into()
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use jsonrpsee::types::EmptyServerParams;
+	use kylix_runtime::{
+		lending::{AssetInfoV1, LendingPoolInfoV1, SuppliedAssetV1},
+		opaque::Block,
+		Runtime, UserLTVInfoV1,
+	};
+	use sc_client_api::{
+		FinalityNotifications, ImportNotifications, StorageEventStream, StorageNotifications,
+	};
+	use sc_utils::mpsc::{tracing_unbounded, TracingUnboundedReceiver};
+	use sp_api::{mock_impl_runtime_apis, ApiRef};
+	use sp_blockchain::{BlockStatus, Info};
+	use sp_core::{storage::StorageKey, testing::TaskExecutor, H256};
+	use sp_io::TestExternalities;
+	use sp_runtime::traits::NumberFor;
+	use std::{collections::HashMap, sync::Mutex};
+
+	type Hash = <Block as BlockT>::Hash;
+	type Lending = kylix_runtime::lending::Pallet<Runtime>;
+
+	const USDT: AssetId = 1;
+	const DOT: AssetId = 2;
+	const KSM: AssetId = 3;
+
+	/// A chain whose best block is `best_hash`, with the runtime state of each block
+	struct TestClient {
+		best_hash: Hash,
+		states: Arc<HashMap<Hash, Mutex<TestExternalities>>>,
+	}
+
+	/// Runs the lending pallet on the state of the block a call is made at
+	struct TestRuntimeApi {
+		states: Arc<HashMap<Hash, Mutex<TestExternalities>>>,
+	}
+
+	impl ProvideRuntimeApi<Block> for TestClient {
+		type Api = TestRuntimeApi;
+
+		fn runtime_api(&self) -> ApiRef<'_, Self::Api> {
+			TestRuntimeApi { states: self.states.clone() }.into()
+		}
+	}
+
+	mock_impl_runtime_apis! {
+		impl LendingPoolApi<Block> for TestRuntimeApi {
+			#[advanced]
+			fn get_asset_price(
+				&self,
+				at: <Block as BlockT>::Hash,
+				asset: AssetId,
+				base_asset: Option<AssetId>,
+			) -> Result<Result<FixedU128, LendingApiError>, ApiError> {
+				let base_asset = base_asset.unwrap_or(USDT);
+				let state = self
+					.states
+					.get(&at)
+					.ok_or_else(|| ApiError::UnknownBlock(format!("{at:?}")))?;
+				Ok(state.lock().unwrap().execute_with(|| {
+					Lending::get_asset_price(asset, base_asset)
+						.map_err(|e| LendingApiError::from_pallet_error(e, asset, base_asset))
+				}))
+			}
+		}
+	}
+
+	/// The state after setting the prices of DOT in USDT, one block after the other
+	fn state_with_dot_prices(prices: &[u128]) -> Mutex<TestExternalities> {
+		let mut state = TestExternalities::default();
+		for price in prices {
+			state.execute_with(|| {
+				Lending::do_set_asset_price(DOT, USDT, FixedU128::from(*price)).unwrap();
+			});
+		}
+		Mutex::new(state)
+	}
+
+	/// A runtime of version 1 of the lending API, with a DOT pool that ALICE supplied to and
+	/// without any price
+	struct V1RuntimeApi;
+
+	fn dot_asset_info_v1() -> AssetInfoV1 {
+		AssetInfoV1 {
+			asset_id: DOT,
+			asset_symbol: b"DOT".to_vec(),
+			asset_name: b"Polkadot".to_vec(),
+			decimals: 10,
+			asset_icon: b"<url>/dot.svg".to_vec(),
+			balance: 1_000,
+			usdt_balance: 5_000,
+		}
+	}
+
+	mock_impl_runtime_apis! {
+		impl LendingPoolApi<Block> for V1RuntimeApi {
+			fn get_lending_pools_before_version_2(
+				asset: Option<AssetId>,
+				_account: Option<AccountId>,
+			) -> (Vec<LendingPoolInfoV1>, AggregatedTotals) {
+				let pool = LendingPoolInfoV1 {
+					id: 100,
+					asset_id: DOT,
+					asset: b"Polkadot".to_vec(),
+					asset_decimals: 10,
+					asset_icon: b"<url>/dot.svg".to_vec(),
+					asset_symbol: b"DOT".to_vec(),
+					collateral_q: 500_000,
+					utilization: FixedU128::from_rational(1, 4),
+					borrow_apy: FixedU128::from_rational(1, 10),
+					borrow_apy_s: FixedU128::from(0),
+					supply_apy: FixedU128::from_rational(1, 50),
+					supply_apy_s: FixedU128::from(0),
+					is_activated: true,
+					user_supplied_balance: None,
+					user_asset_balance: None,
+				};
+				let pools = match asset {
+					Some(asset) if asset != DOT => vec![],
+					_ => vec![pool],
+				};
+				(pools, AggregatedTotals { total_supply: 5_000, total_borrow: 1_250 })
+			}
+
+			fn get_user_ltv_before_version_2(_account: AccountId) -> UserLTVInfoV1 {
+				UserLTVInfoV1 {
+					current_ltv: FixedU128::from_rational(1, 2),
+					sale_ltv: FixedU128::from_rational(1, 2),
+					liquidation_ltv: FixedU128::from_rational(4, 5),
+				}
+			}
+
+			fn get_asset_wise_supplies_before_version_2(
+				_account: AccountId,
+			) -> (Vec<SuppliedAssetV1>, TotalDeposit) {
+				let supplied = SuppliedAssetV1 {
+					asset_info: dot_asset_info_v1(),
+					apy: FixedU128::from_rational(1, 50),
+					supplied: 1_000,
+				};
+				(vec![supplied], 5_000)
+			}
+
+			fn get_asset_price_before_version_2(
+				_asset: AssetId,
+				_base_asset: Option<AssetId>,
+			) -> Option<FixedU128> {
+				None
+			}
+		}
+	}
+
+	/// The RPC error code of `result`
+	fn error_code<T: std::fmt::Debug>(result: RpcResult<T>) -> i32 {
+		match result {
+			Err(JsonRpseeError::Call(CallError::Custom(error))) => error.code(),
+			result => panic!("expected an RPC error, got {result:?}"),
+		}
+	}
+
+	impl HeaderBackend<Block> for TestClient {
+		fn header(&self, _: Hash) -> sp_blockchain::Result<Option<<Block as BlockT>::Header>> {
+			Ok(None)
+		}
+
+		fn info(&self) -> Info<Block> {
+			Info {
+				best_hash: self.best_hash,
+				best_number: 2,
+				genesis_hash: Default::default(),
+				finalized_hash: Default::default(),
+				finalized_number: 0,
+				finalized_state: None,
+				number_leaves: 1,
+				block_gap: None,
+			}
+		}
+
+		fn status(&self, _: Hash) -> sp_blockchain::Result<BlockStatus> {
+			Ok(BlockStatus::InChain)
+		}
+
+		fn number(&self, _: Hash) -> sp_blockchain::Result<Option<NumberFor<Block>>> {
+			Ok(None)
+		}
+
+		fn hash(&self, _: NumberFor<Block>) -> sp_blockchain::Result<Option<Hash>> {
+			Ok(None)
+		}
+	}
+
+	/// A stream without notifications, its sender is dropped right away
+	fn no_notifications<T>() -> TracingUnboundedReceiver<T> {
+		tracing_unbounded("kylix-test-notifications", 1).1
+	}
+
+	/// A chain that imports no blocks
+	impl BlockchainEvents<Block> for TestClient {
+		fn import_notification_stream(&self) -> ImportNotifications<Block> {
+			no_notifications()
+		}
+
+		fn every_import_notification_stream(&self) -> ImportNotifications<Block> {
+			no_notifications()
+		}
+
+		fn finality_notification_stream(&self) -> FinalityNotifications<Block> {
+			no_notifications()
+		}
+
+		fn storage_changes_notification_stream(
+			&self,
+			filter_keys: Option<&[StorageKey]>,
+			child_filter_keys: Option<&[(StorageKey, Option<Vec<StorageKey>>)]>,
+		) -> sp_blockchain::Result<StorageEventStream<Hash>> {
+			Ok(StorageNotifications::<Block>::new(None).listen(filter_keys, child_filter_keys))
+		}
+	}
+
+	#[test]
+	fn test_query_at_older_block() {
+		let genesis_hash = H256::repeat_byte(0);
+		let old_hash = H256::repeat_byte(1);
+		let best_hash = H256::repeat_byte(2);
+		// the price is set in the old block and updated in the best block
+		let states = HashMap::from([
+			(genesis_hash, state_with_dot_prices(&[])),
+			(old_hash, state_with_dot_prices(&[5])),
+			(best_hash, state_with_dot_prices(&[5, 7])),
+		]);
+		let client = TestClient { best_hash, states: Arc::new(states) };
+		let rpc = LendingPoolApiImpl::<_, Block>::new(
+			Arc::new(client),
+			Arc::new(TaskExecutor::new()),
+			DenyUnsafe::No,
+		);
+
+		assert_eq!(rpc.get_asset_price(DOT, None, None).unwrap(), FixedU128::from(7));
+		assert_eq!(rpc.get_asset_price(DOT, None, Some(best_hash)).unwrap(), FixedU128::from(7));
+		assert_eq!(rpc.get_asset_price(DOT, None, Some(old_hash)).unwrap(), FixedU128::from(5));
+
+		// a block at which the price was not set yet
+		match rpc.get_asset_price(DOT, None, Some(genesis_hash)) {
+			Err(JsonRpseeError::Call(CallError::Custom(error))) => {
+				assert_eq!(error.code(), i32::from(Error::PriceNotSet));
+			},
+			_ => panic!("expected a PriceNotSet error"),
+		}
+	}
+
+	#[test]
+	fn test_query_a_version_1_runtime() {
+		let api = V1RuntimeApi;
+		let v1 = LendingApiAt::<_, Block> { api: &api, at: H256::repeat_byte(1), version: 1 };
+		let alice = AccountId::from([1u8; 32]);
+
+		// the annual rates of version 1 are reported as both APR and APY
+		let (pools, totals) = v1.get_lending_pools(None, None, None, None, false).unwrap();
+		assert_eq!(pools.len(), 1);
+		assert_eq!(pools[0].asset_id, DOT);
+		assert_eq!(pools[0].asset_display_name, b"Polkadot".to_vec());
+		assert_eq!(pools[0].borrow_apr, FixedU128::from_rational(1, 10));
+		assert_eq!(pools[0].borrow_apy, FixedU128::from_rational(1, 10));
+		assert_eq!(pools[0].supply_apr, FixedU128::from_rational(1, 50));
+		assert_eq!(pools[0].supply_apy, FixedU128::from_rational(1, 50));
+		assert_eq!(totals, AggregatedTotals { total_supply: 5_000, total_borrow: 1_250 });
+
+		// version 1 returns no pool instead of an error
+		assert_eq!(
+			error_code(v1.get_lending_pools(Some(KSM), None, None, None, false)),
+			i32::from(Error::PoolNotFound)
+		);
+		// nor can it paginate
+		assert_eq!(
+			error_code(v1.get_lending_pools(None, None, Some(DOT), Some(10), false)),
+			i32::from(Error::Unsupported)
+		);
+
+		let ltv = v1.get_user_ltv(alice.clone()).unwrap();
+		assert_eq!(ltv.current_ltv, FixedU128::from_rational(1, 2));
+		assert_eq!(ltv.liquidation_ltv, FixedU128::from_rational(4, 5));
+		assert_eq!(ltv.health_factor, Some(FixedU128::from_rational(8, 5)));
+		assert!(ltv.positions.is_empty());
+
+		let (supplies, total_deposit) = v1.get_asset_wise_supplies(alice).unwrap();
+		assert_eq!(supplies.len(), 1);
+		assert_eq!(supplies[0].asset_info.asset_display_name, b"Polkadot".to_vec());
+		assert_eq!(supplies[0].apr, FixedU128::from_rational(1, 50));
+		assert_eq!(supplies[0].supplied, 1_000);
+		assert_eq!(total_deposit, 5_000);
+
+		// version 1 returns no price instead of an error
+		assert_eq!(error_code(v1.get_asset_price(DOT, None)), i32::from(Error::PriceNotSet));
+	}
+
+	#[test]
+	fn test_subscriptions_are_unsafe() {
+		let client = TestClient { best_hash: H256::repeat_byte(1), states: Default::default() };
+		let rpc = LendingPoolApiImpl::<_, Block>::new(
+			Arc::new(client),
+			Arc::new(TaskExecutor::new()),
+			DenyUnsafe::Yes,
+		)
+		.into_rpc();
+
+		let pools = rpc.subscribe("subscribeLendingPools", EmptyServerParams::new());
+		assert!(futures::executor::block_on(pools).is_err());
+		let position = rpc.subscribe("subscribeUserPosition", [AccountId::from([1u8; 32])]);
+		assert!(futures::executor::block_on(position).is_err());
+	}
 }
@@ -1,12 +1,15 @@
 //! Service and ServiceFactory implementation. Specialized wrapper over substrate service.
 
+use crate::lending_history::LendingHistory;
 use futures::FutureExt;
 use kylix_runtime::{opaque::Block, RuntimeApi};
 use sc_client_api::{Backend, BlockBackend};
 use sc_consensus_aura::{ImportQueueParams, SlotProportion, StartAuraParams};
 use sc_consensus_grandpa::SharedVoterState;
 pub use sc_executor::NativeElseWasmExecutor;
-use sc_service::{error::Error as ServiceError, Configuration, TaskManager, WarpSyncParams};
+use sc_service::{
+	error::Error as ServiceError, Configuration, PruningMode, TaskManager, WarpSyncParams,
+};
 use sc_telemetry::{Telemetry, TelemetryWorker};
 use sc_transaction_pool_api::OffchainTransactionPoolFactory;
 use sp_consensus_aura::sr25519::AuthorityPair as AuraPair;
@@ -139,8 +142,17 @@ pub fn new_partial(
 	})
 }
 
-/// Builds a new service for a full client.
-pub fn new_full(config: Configuration) -> Result<TaskManager, ServiceError> {
+/// Builds a new service for a full client. With `lending_history`, the lending events of the
+/// finalized blocks are indexed into the history served by `lending_getAccountHistory`, which
+/// needs the state of every finalized block and so an archive node.
+pub fn new_full(config: Configuration, lending_history: bool) -> Result<TaskManager, ServiceError> {
+	if lending_history && !config.state_pruning.as_ref().map_or(false, PruningMode::is_archive) {
+		return Err(ServiceError::Other(
+			"The lending history needs the state of every block, run with --state-pruning archive"
+				.into(),
+		))
+	}
+
 	let sc_service::PartialComponents {
 		client,
 		backend,
@@ -201,6 +213,24 @@ pub fn new_full(config: Configuration) -> Result<TaskManager, ServiceError> {
 		);
 	}
 
+	let lending_history = if lending_history {
+		let storage = backend.offchain_storage().ok_or_else(|| {
+			ServiceError::Other("The lending history needs the offchain storage".into())
+		})?;
+		let history = LendingHistory::new(storage);
+		task_manager.spawn_handle().spawn_blocking(
+			"lending-history",
+			None,
+			crate::lending_history::run_indexer::<FullBackend, _, _>(
+				client.clone(),
+				history.clone(),
+			),
+		);
+		Some(history)
+	} else {
+		None
+	};
+
 	let role = config.role.clone();
 	let force_authoring = config.force_authoring;
 	let backoff_authoring_blocks: Option<()> = None;
@@ -212,9 +242,14 @@ pub fn new_full(config: Configuration) -> Result<TaskManager, ServiceError> {
 		let client = client.clone();
 		let pool = transaction_pool.clone();
 
-		Box::new(move |deny_unsafe, _| {
-			let deps =
-				crate::rpc::FullDeps { client: client.clone(), pool: pool.clone(), deny_unsafe };
+		Box::new(move |deny_unsafe, subscription_executor| {
+			let deps = crate::rpc::FullDeps {
+				client: client.clone(),
+				pool: pool.clone(),
+				deny_unsafe,
+				subscription_executor,
+				lending_history: lending_history.clone(),
+			};
 			crate::rpc::create_full(deps).map_err(Into::into)
 		})
 	};
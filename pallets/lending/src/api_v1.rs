@@ -0,0 +1,133 @@
+//! The responses of version 1 of the lending runtime API, before they were extended with the
+//! display metadata of the assets and the compounded yields. The node decodes the responses of
+//! blocks of older runtimes with them and converts them to the current responses, leaving the
+//! fields a version 1 runtime doesn't report at their defaults.
+use crate::*;
+
+#[derive(Encode, Decode, Clone, PartialEq, Serialize, Deserialize, Debug, TypeInfo)]
+pub struct LendingPoolInfoV1 {
+	pub id: u32,
+	pub asset_id: u32,
+	pub asset: Vec<u8>,
+	pub asset_decimals: u8,
+	pub asset_icon: Vec<u8>,
+	pub asset_symbol: Vec<u8>,
+	pub collateral_q: u64,
+	pub utilization: FixedU128,
+	/// The annual borrow rate, without compounding
+	pub borrow_apy: FixedU128,
+	pub borrow_apy_s: FixedU128,
+	/// The annual supply rate, without compounding
+	pub supply_apy: FixedU128,
+	pub supply_apy_s: FixedU128,
+	pub is_activated: bool,
+	pub user_supplied_balance: Option<u128>,
+	pub user_asset_balance: Option<u128>,
+}
+
+#[derive(Encode, Decode, Clone, PartialEq, Serialize, Deserialize, Debug, TypeInfo)]
+pub struct AssetInfoV1 {
+	pub asset_id: u32,
+	pub asset_symbol: Vec<u8>,
+	pub asset_name: Vec<u8>,
+	pub decimals: u8,
+	pub asset_icon: Vec<u8>,
+	pub balance: u128,
+	pub usdt_balance: u128,
+}
+
+#[derive(Encode, Decode, Clone, PartialEq, Serialize, Deserialize, Debug, TypeInfo)]
+pub struct SuppliedAssetV1 {
+	#[serde(flatten)]
+	pub asset_info: AssetInfoV1,
+	/// The annual supply rate, without compounding
+	pub apy: FixedU128,
+	pub supplied: u128,
+}
+
+#[derive(Encode, Decode, Clone, PartialEq, Serialize, Deserialize, Debug, TypeInfo)]
+pub struct BorrowedAssetV1 {
+	#[serde(flatten)]
+	pub asset_info: AssetInfoV1,
+	/// The annual borrow rate, without compounding
+	pub apy: FixedU128,
+	pub borrowed: u128,
+}
+
+#[derive(Encode, Decode, Clone, PartialEq, Serialize, Deserialize, Debug, TypeInfo)]
+pub struct CollateralAssetV1 {
+	#[serde(flatten)]
+	pub asset_info: AssetInfoV1,
+}
+
+/// Version 1 reported the annual rates as APY, so they are returned as both APR and APY
+impl From<LendingPoolInfoV1> for LendingPoolInfo {
+	fn from(info: LendingPoolInfoV1) -> Self {
+		LendingPoolInfo {
+			id: info.id,
+			asset_id: info.asset_id,
+			asset_display_name: info.asset.clone(),
+			asset: info.asset,
+			asset_decimals: info.asset_decimals,
+			asset_icon: info.asset_icon,
+			asset_symbol: info.asset_symbol,
+			asset_coingecko_id: Vec::new(),
+			collateral_q: info.collateral_q,
+			utilization: info.utilization,
+			borrow_apr: info.borrow_apy,
+			borrow_apy: info.borrow_apy,
+			borrow_apy_s: info.borrow_apy_s,
+			supply_apr: info.supply_apy,
+			supply_apy: info.supply_apy,
+			supply_apy_s: info.supply_apy_s,
+			exchange_rate: FixedU128::zero(),
+			is_activated: info.is_activated,
+			user_supplied_balance: info.user_supplied_balance,
+			user_asset_balance: info.user_asset_balance,
+		}
+	}
+}
+
+impl From<AssetInfoV1> for AssetInfo {
+	fn from(info: AssetInfoV1) -> Self {
+		AssetInfo {
+			asset_id: info.asset_id,
+			asset_symbol: info.asset_symbol,
+			asset_display_name: info.asset_name.clone(),
+			asset_name: info.asset_name,
+			decimals: info.decimals,
+			asset_icon: info.asset_icon,
+			asset_coingecko_id: Vec::new(),
+			balance: info.balance,
+			usdt_balance: info.usdt_balance,
+		}
+	}
+}
+
+impl From<SuppliedAssetV1> for SuppliedAsset {
+	fn from(asset: SuppliedAssetV1) -> Self {
+		SuppliedAsset {
+			asset_info: asset.asset_info.into(),
+			apr: asset.apy,
+			apy: asset.apy,
+			supplied: asset.supplied,
+		}
+	}
+}
+
+impl From<BorrowedAssetV1> for BorrowedAsset {
+	fn from(asset: BorrowedAssetV1) -> Self {
+		BorrowedAsset {
+			asset_info: asset.asset_info.into(),
+			apr: asset.apy,
+			apy: asset.apy,
+			borrowed: asset.borrowed,
+		}
+	}
+}
+
+impl From<CollateralAssetV1> for CollateralAsset {
+	fn from(asset: CollateralAssetV1) -> Self {
+		CollateralAsset { asset_info: asset.asset_info.into() }
+	}
+}
@@ -0,0 +1,49 @@
+use crate::*;
+
+/// Display metadata of an asset, returned with the asset by the runtime APIs.
+/// Set by `ManagerOrigin`, each field is at most `MaxAssetMetadataLength` bytes long.
+#[derive(
+	Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, MaxEncodedLen, TypeInfo, DefaultNoBound,
+)]
+#[scale_info(skip_type_params(T))]
+pub struct AssetDisplayMetadata<T: Config> {
+	/// URI of the icon of the asset
+	pub icon: BoundedVec<u8, T::MaxAssetMetadataLength>,
+	/// Name of the asset shown to users
+	pub display_name: BoundedVec<u8, T::MaxAssetMetadataLength>,
+	/// Id of the asset on price aggregators, e.g. `polkadot` on coingecko
+	pub coingecko_id: BoundedVec<u8, T::MaxAssetMetadataLength>,
+}
+
+impl<T: Config> AssetDisplayMetadata<T> {
+	pub fn new(
+		icon: Vec<u8>,
+		display_name: Vec<u8>,
+		coingecko_id: Vec<u8>,
+	) -> Result<Self, Error<T>> {
+		Ok(Self {
+			icon: icon.try_into().map_err(|_| Error::<T>::AssetMetadataTooLong)?,
+			display_name: display_name.try_into().map_err(|_| Error::<T>::AssetMetadataTooLong)?,
+			coingecko_id: coingecko_id.try_into().map_err(|_| Error::<T>::AssetMetadataTooLong)?,
+		})
+	}
+}
+
+impl<T: Config> Pallet<T> {
+	/// Returns the icon URI, display name and coingecko id of `asset`. If no metadata was set,
+	/// the icon and coingecko id are empty and the display name is the name of the asset.
+	pub fn get_display_metadata(asset: AssetIdOf<T>) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+		match AssetMetadata::<T>::get(asset) {
+			Some(metadata) => (
+				metadata.icon.into_inner(),
+				metadata.display_name.into_inner(),
+				metadata.coingecko_id.into_inner(),
+			),
+			None => (
+				Vec::new(),
+				<pallet_assets::Pallet<T> as MetadataInspect<_>>::name(asset),
+				Vec::new(),
+			),
+		}
+	}
+}
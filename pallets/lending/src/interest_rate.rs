@@ -88,3 +88,47 @@ impl Default for InterestRateModel {
 		}
 	}
 }
+
+/// The most points `get_rate_curve` samples
+pub const MAX_RATE_CURVE_POINTS: u32 = 1_000;
+
+/// The borrow and supply rates of a lending pool at a utilisation. Used as response for rpc
+#[derive(Encode, Decode, Clone, PartialEq, Serialize, Deserialize, Debug, TypeInfo)]
+pub struct RatePoint {
+	pub utilization: FixedU128,
+	pub borrow_rate: FixedU128,
+	pub supply_rate: FixedU128,
+}
+
+/// The interest rate curve of a lending pool. Used as response for rpc
+#[derive(Encode, Decode, Clone, PartialEq, Serialize, Deserialize, Debug, TypeInfo)]
+pub struct RateCurve {
+	/// The rates at evenly spaced utilisations, from 0% to 100%
+	pub points: Vec<RatePoint>,
+	/// The current utilisation of the pool and the rates of the curve at it
+	pub current: RatePoint,
+}
+
+impl<T: Config> Pallet<T> {
+	/// Samples the borrow and supply rates of the lending pool of `asset` at `points` evenly
+	/// spaced utilisations, with its `InterestRateModel` and `reserve_factor`. `points` is
+	/// clamped between 2 and `MAX_RATE_CURVE_POINTS`. The rates only depend on the balances of
+	/// the pool, not on its indexes.
+	pub fn get_rate_curve(asset: AssetIdOf<T>, points: u32) -> Result<RateCurve, Error<T>> {
+		let pool = LendingPoolStorage::<T>::get(AssetPool::<T>::from(asset))
+			.ok_or(Error::<T>::LendingPoolDoesNotExist)?;
+		let rate_point = |utilization: Rate| -> Result<RatePoint, Error<T>> {
+			let borrow_rate = pool.borrow_rate_at(utilization)?;
+			let supply_rate = pool.supply_rate_at(borrow_rate, utilization)?;
+			Ok(RatePoint { utilization, borrow_rate, supply_rate })
+		};
+
+		let intervals = points.clamp(2, MAX_RATE_CURVE_POINTS) - 1;
+		let points = (0..=intervals)
+			.map(|i| rate_point(Rate::from_rational(i.into(), intervals.into())))
+			.collect::<Result<Vec<_>, Error<T>>>()?;
+		let current = rate_point(pool.utilisation_ratio()?.into())?;
+
+		Ok(RateCurve { points, current })
+	}
+}
@@ -1,7 +1,10 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
+pub use api_v1::{
+	AssetInfoV1, BorrowedAssetV1, CollateralAssetV1, LendingPoolInfoV1, SuppliedAssetV1,
+};
+pub use asset_metadata::AssetDisplayMetadata;
 pub use borrow_repay::UserBorrow;
-use frame_support::traits::tokens::fungibles::metadata::Inspect as MetadataInspect;
 ///! # The Lending pallet of Kylix
 ///!
 ///! ## Overview
@@ -27,14 +30,29 @@ use frame_support::traits::tokens::fungibles::metadata::Inspect as MetadataInspe
 ///! 7. deactivate_lending_pool()
 ///! 8. update_pool_rate_model()
 ///! 9. update_pool_kink()
+///! 10. set_asset_price()
+///! 11. liquidate()
+///! 12. liquidate_unsigned_with_signed_payload()
+///! 13. set_asset_prices()
+///! 14. set_asset_metadata()
+///! 15. clear_asset_metadata()
+///! 16. set_reward_speed()
+///! 17. withdraw_all()
+///! 18. repay_all()
+///! 19. repay_on_behalf()
+///! 20. set_authorities()
 ///!
 pub use frame_support::{
 	pallet_prelude::*,
 	serde, sp_runtime,
 	sp_runtime::{
-		traits::{AccountIdConversion, CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, One, Zero},
-		FixedPointNumber, FixedU128, Permill, SaturatedConversion,
+		traits::{
+			AccountIdConversion, CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, IdentifyAccount,
+			One, Zero,
+		},
+		FixedPointNumber, FixedU128, PerThing, Permill, SaturatedConversion,
 	},
+	storage::{with_storage_layer, StoragePrefixedMap},
 	traits::{
 		fungible, fungibles,
 		fungibles::{Create, Inspect, Mutate},
@@ -43,11 +61,23 @@ pub use frame_support::{
 	},
 	DefaultNoBound, PalletId,
 };
-pub use frame_system::pallet_prelude::*;
-pub use interest_rate::InterestRateModel;
+use frame_support::{
+	sp_runtime::{helpers_128bit::multiply_by_rational_with_rounding, Rounding},
+	traits::tokens::fungibles::metadata::Inspect as MetadataInspect,
+};
+pub use frame_system::{
+	offchain::{AppCrypto, CreateSignedTransaction, SignedPayload},
+	pallet_prelude::*,
+};
+pub use interest_rate::{InterestRateModel, RateCurve, RatePoint, MAX_RATE_CURVE_POINTS};
+pub use offchain::{crypto, LiquidatablePosition, LiquidationPayload, LoanKey, KEY_TYPE};
 pub use pallet::*;
+pub use pool_stats::PoolStats;
+pub use price_feed::{LendingPoolAssets, PRICE_FEED_URL_KEY};
+pub use rewards::RewardSpeed;
 use scale_info::prelude::vec::Vec;
 use serde::{Deserialize, Serialize};
+pub use simulation::{SimulatedAction, SimulationResult};
 
 /// Account Type Definition
 pub type AccountOf<T> = <T as frame_system::Config>::AccountId;
@@ -79,8 +109,19 @@ pub type TotalCollateral = u128;
 
 pub const SECONDS_PER_YEAR: u64 = 365u64 * 24 * 60 * 60;
 
+/// The longest step, in seconds, the interest of a lending pool accrues over at once
+pub const MAX_ACCRUAL_PERIOD: u64 = SECONDS_PER_YEAR;
+
+mod api_v1;
+mod asset_metadata;
 mod borrow_repay;
 mod interest_rate;
+pub mod migrations;
+mod offchain;
+mod pool_stats;
+mod price_feed;
+mod rewards;
+mod simulation;
 
 #[cfg(test)]
 pub(crate) mod tests;
@@ -98,12 +139,22 @@ pub struct LendingPoolInfo {
 	pub asset_decimals: u8,
 	pub asset_icon: Vec<u8>,
 	pub asset_symbol: Vec<u8>,
+	pub asset_display_name: Vec<u8>,
+	pub asset_coingecko_id: Vec<u8>,
 	pub collateral_q: u64,
 	pub utilization: FixedU128,
+	/// The annual borrow rate, without compounding
+	pub borrow_apr: FixedU128,
+	/// The annual borrow yield, compounded like the borrow index
 	pub borrow_apy: FixedU128,
 	pub borrow_apy_s: FixedU128,
+	/// The annual supply rate, without compounding
+	pub supply_apr: FixedU128,
+	/// The annual supply yield if the supply interest compounded continuously, an upper bound of
+	/// the realised yield
 	pub supply_apy: FixedU128,
 	pub supply_apy_s: FixedU128,
+	pub exchange_rate: FixedU128,
 	pub is_activated: bool,
 	pub user_supplied_balance: Option<u128>,
 	pub user_asset_balance: Option<u128>,
@@ -122,6 +173,8 @@ pub struct AssetInfo {
 	pub asset_name: Vec<u8>,
 	pub decimals: u8,
 	pub asset_icon: Vec<u8>,
+	pub asset_display_name: Vec<u8>,
+	pub asset_coingecko_id: Vec<u8>,
 	pub balance: u128,
 	pub usdt_balance: u128,
 }
@@ -131,6 +184,7 @@ pub struct AssetInfo {
 pub struct SuppliedAsset {
 	#[serde(flatten)]
 	pub asset_info: AssetInfo,
+	pub apr: FixedU128,
 	pub apy: FixedU128,
 	pub supplied: u128,
 }
@@ -139,6 +193,7 @@ pub struct SuppliedAsset {
 pub struct BorrowedAsset {
 	#[serde(flatten)]
 	pub asset_info: AssetInfo,
+	pub apr: FixedU128,
 	pub apy: FixedU128,
 	pub borrowed: u128,
 }
@@ -149,15 +204,71 @@ pub struct CollateralAsset {
 	pub asset_info: AssetInfo,
 }
 
+/// A loan of an account, valued in USDT. Used as response for rpc
+#[derive(Encode, Decode, Clone, PartialEq, Serialize, Deserialize, Debug, TypeInfo)]
+pub struct LoanPosition {
+	pub borrowed_asset: u32,
+	pub collateral_asset: u32,
+	/// The debt of the loan, including interest, in the borrowed asset
+	pub debt: u128,
+	pub debt_usdt: u128,
+	pub collateral: u128,
+	pub collateral_usdt: u128,
+	pub ltv: FixedU128,
+	/// The collateral factor of the pool of the borrowed asset
+	pub sale_ltv: FixedU128,
+	/// The liquidation threshold of the pool of the borrowed asset
+	pub liquidation_ltv: FixedU128,
+	/// `liquidation_ltv / ltv`, the health factor of the loan on its own. The loans of an account
+	/// are liquidated on the `weighted_health_factor` of all of them. `None` without any debt
+	pub health_factor: Option<FixedU128>,
+	/// The USDT price of the collateral asset below which the health factor of the loan on its
+	/// own drops below one. `None` without any collateral
+	pub liquidation_price: Option<FixedU128>,
+}
+
+/// Why a call of the lending runtime API failed. Used as error of the rpc responses
+#[derive(Encode, Decode, Clone, PartialEq, Eq, Serialize, Deserialize, Debug, TypeInfo)]
+pub enum LendingApiError {
+	/// There is no lending pool for `asset`
+	PoolNotFound { asset: u32 },
+	/// The price of `asset` in `base_asset` is set neither directly nor through USDT
+	PriceNotSet { asset: u32, base_asset: u32 },
+	/// An arithmetic operation overflowed or divided by zero
+	Overflow,
+	/// Any other error of the pallet, by name
+	Other { error: Vec<u8> },
+}
+
+impl LendingApiError {
+	/// Describes `error`, raised while valuing `asset` in `base_asset`
+	pub fn from_pallet_error<T: Config>(error: Error<T>, asset: u32, base_asset: u32) -> Self {
+		match error {
+			Error::LendingPoolDoesNotExist => Self::PoolNotFound { asset },
+			Error::AssetPriceNotSet => Self::PriceNotSet { asset, base_asset },
+			Error::OverflowError | Error::DivisionByZero => Self::Overflow,
+			error => Self::Other { error: <&'static str>::from(error).as_bytes().to_vec() },
+		}
+	}
+}
+
 #[frame_support::pallet]
 pub mod pallet {
 	use super::*;
 
+	/// The current storage version.
+	const STORAGE_VERSION: StorageVersion = StorageVersion::new(2);
+
 	#[pallet::pallet]
+	#[pallet::storage_version(STORAGE_VERSION)]
 	pub struct Pallet<T>(_);
 
 	#[pallet::config]
-	pub trait Config: frame_system::Config + pallet_assets::Config<AssetId = u32> {
+	pub trait Config:
+		frame_system::Config
+		+ pallet_assets::Config<AssetId = u32>
+		+ CreateSignedTransaction<Call<Self>>
+	{
 		#[pallet::constant]
 		type PalletId: Get<PalletId>;
 
@@ -176,13 +287,62 @@ pub mod pallet {
 			+ fungibles::Mutate<Self::AccountId>
 			+ fungibles::Create<Self::AccountId>;
 
-		/// The origin which can add or remove LendingPools and update LendingPools TODO
-		// type ManagerOrigin: EnsureOrigin<Self::RuntimeOrigin>;
+		/// The origin which can set the prices and the display metadata of assets, and the
+		/// `Authorities`
+		type ManagerOrigin: EnsureOrigin<Self::RuntimeOrigin>;
+
+		/// Maximum length of each field of the display metadata of an asset
+		#[pallet::constant]
+		type MaxAssetMetadataLength: Get<u32>;
+
+		/// The asset the suppliers and borrowers of the lending pools are rewarded in
+		#[pallet::constant]
+		type RewardAsset: Get<AssetIdOf<Self>>;
 
 		/// Weight information for extrinsics in this pallet.
 		type WeightInfo: WeightInfo;
 
 		type Time: MomentTime;
+
+		/// The key used by the off-chain worker to sign liquidations
+		type AuthorityId: AppCrypto<Self::Public, Self::Signature>;
+
+		/// The share of the debt of a loan a liquidator repays in a single liquidation
+		#[pallet::constant]
+		type CloseFactor: Get<Permill>;
+
+		/// The share of the value of the repaid debt a liquidator seizes on top of it from the
+		/// collateral
+		#[pallet::constant]
+		type LiquidationBonus: Get<Permill>;
+
+		/// Maximum number of `Authorities`
+		#[pallet::constant]
+		type MaxAuthorities: Get<u32>;
+
+		/// Minimum number of blocks between two liquidation runs of the off-chain worker
+		#[pallet::constant]
+		type LiquidationInterval: Get<BlockNumberFor<Self>>;
+
+		/// Maximum number of loans the off-chain worker liquidates in a single run
+		#[pallet::constant]
+		type MaxLiquidationsPerRun: Get<u32>;
+
+		/// Whether the off-chain worker submits signed liquidations, paying their fees, rather
+		/// than unsigned ones with a signed payload
+		#[pallet::constant]
+		type SignedLiquidations: Get<bool>;
+
+		/// Priority of unsigned liquidation transactions
+		#[pallet::constant]
+		type UnsignedPriority: Get<TransactionPriority>;
+
+		/// The assets whose USDT price the off-chain worker fetches from the price feed
+		type PriceFeedAssets: Get<Vec<AssetIdOf<Self>>>;
+
+		/// Minimum number of blocks between two price updates of the off-chain worker
+		#[pallet::constant]
+		type PriceFeedInterval: Get<BlockNumberFor<Self>>;
 	}
 
 	/// The AssetPool definition. Used as the KEY in the lending pool storage
@@ -243,8 +403,6 @@ pub mod pallet {
 		// flows into Kylix's treasury.
 		pub reserve_factor: Ratio,
 
-		pub exchange_rate: Ratio, // defined by user, 20% Default exchange rate
-
 		pub collateral_factor: Ratio,     // The secure collateral ratio
 		pub liquidation_threshold: Ratio, // defined by user, 75% as default
 
@@ -280,12 +438,14 @@ pub mod pallet {
 				liquidation_threshold: Ratio::from_percent(80), // Default liquidation at 80%
 
 				supply_rate: Ratio::zero(),
-				exchange_rate: Ratio::zero(),
 				last_accrued_interest_at: Pallet::<T>::now_in_seconds(),
 				borrow_index: Rate::one(),
 				supply_index: Rate::one(),
 			};
 			pool.update_indexes()?;
+
+			// the initial exchange rate must lie within the configured market bounds
+			pool.ensure_valid_exchange_rate()?;
 			Ok(pool)
 		}
 
@@ -311,8 +471,11 @@ pub mod pallet {
 			}
 
 			let utilisation_ratio = self.utilisation_ratio()?;
-			let utilisation_ratio: Rate = utilisation_ratio.into();
+			self.borrow_rate_at(utilisation_ratio.into())
+		}
 
+		/// The borrow rate of the interest rate model of the pool at `utilisation_ratio`
+		pub fn borrow_rate_at(&self, utilisation_ratio: Rate) -> Result<Rate, Error<T>> {
 			self.interest_model
 				.calculate_cosine_interest(utilisation_ratio)
 				.map_err(|_| Error::<T>::OverflowError.into())
@@ -326,13 +489,22 @@ pub mod pallet {
 			//
 			let borrow_rate = self.borrow_interest_rate()?;
 			let utilisation_ratio = self.utilisation_ratio()?;
+			self.supply_rate_at(borrow_rate, utilisation_ratio.into())
+		}
 
+		/// The supply rate of the pool at `utilisation_ratio`, where the borrow rate is
+		/// `borrow_rate`
+		pub fn supply_rate_at(
+			&self,
+			borrow_rate: Rate,
+			utilisation_ratio: Rate,
+		) -> Result<Rate, Error<T>> {
 			let reserved = Permill::from_percent(100)
 				.checked_sub(&self.reserve_factor)
 				.ok_or(Error::<T>::OverflowError)?;
 
 			let res = borrow_rate
-				.checked_mul(&utilisation_ratio.into())
+				.checked_mul(&utilisation_ratio)
 				.ok_or(Error::<T>::OverflowError)?
 				.checked_mul(&reserved.into())
 				.ok_or(Error::<T>::OverflowError)?;
@@ -340,6 +512,24 @@ pub mod pallet {
 			Ok(res)
 		}
 
+		/// The yearly yield of borrowing at the current rate. The borrow index compounds every
+		/// second, `calculate_compunded_interest`, so the yield is `e^rate - 1`.
+		pub fn borrow_apy(&self) -> Result<Rate, Error<T>> {
+			Ok(self.exp_fixed_u128(self.borrow_interest_rate()?)?.saturating_sub(Rate::one()))
+		}
+
+		/// The yearly yield of supplying at the current rate, compounded continuously: `e^rate -
+		/// 1`.
+		///
+		/// The supply index grows linearly between accruals, `calculate_linear_interest`, and only
+		/// compounds when the accrued interest is stored: on a user action on the pool, or when
+		/// the `on_idle` rotation reaches the pool, which depends on the spare block weight. This
+		/// is an upper bound of the realised yield, which gets closer to `rate` the less often the
+		/// pool is accrued.
+		pub fn supply_apy(&self) -> Result<Rate, Error<T>> {
+			Ok(self.exp_fixed_u128(self.supply_interest_rate()?)?.saturating_sub(Rate::one()))
+		}
+
 		/// self-explanatory helper methods
 		pub fn is_empty(&self) -> bool {
 			self.reserve_balance.cmp(&BalanceOf::<T>::zero()).is_eq()
@@ -348,6 +538,25 @@ pub mod pallet {
 			self.activated == true
 		}
 
+		/// The exchange rate of the LP token, i.e. the amount of underlying asset one LP token
+		/// is worth. LP tokens are minted as scaled balances, so this is the supply index.
+		pub fn exchange_rate(&self) -> Rate {
+			self.supply_index
+		}
+
+		/// Ensures the exchange rate lies within the `MinMaxExchangeRate` bounds. Checked only
+		/// when a pool is created: the supply index keeps growing afterwards, and supplying or
+		/// withdrawing liquidity must never be locked by it.
+		pub fn ensure_valid_exchange_rate(&self) -> Result<(), Error<T>> {
+			let (min_exchange_rate, max_exchange_rate) = MinMaxExchangeRate::<T>::get();
+			let exchange_rate = self.exchange_rate();
+			ensure!(
+				exchange_rate >= min_exchange_rate && exchange_rate <= max_exchange_rate,
+				Error::<T>::ExchangeRateOutOfBounds
+			);
+			Ok(())
+		}
+
 		/// Calculates scaled balance as
 		/// scaled_balance = balance / supply_index
 		pub fn scaled_supply_balance(
@@ -364,31 +573,52 @@ pub mod pallet {
 
 		/// Calculates scaled balance for borrow case as
 		/// scaled_balance = balance / borrow_index
+		/// rounded up, so the debt recorded for a borrow is never less than the borrowed balance
 		pub fn scaled_borrow_balance(
 			&self,
 			borrowed_balance: AssetBalanceOf<T>,
 		) -> Result<AssetBalanceOf<T>, Error<T>> {
-			let scaled_balance = FixedU128::from_inner(borrowed_balance.saturated_into())
-				.checked_div(&self.borrow_index)
-				.ok_or(Error::<T>::OverflowError)?
-				.into_inner()
-				.saturated_into();
+			self.scale_by_borrow_index(borrowed_balance, Rounding::Up)
+		}
+
+		/// Calculates the scaled debt settled by a repayment as
+		/// scaled_balance = balance / borrow_index
+		/// rounded down, so a repayment never settles more debt than it pays
+		pub fn scaled_repaid_balance(
+			&self,
+			repaid_balance: AssetBalanceOf<T>,
+		) -> Result<AssetBalanceOf<T>, Error<T>> {
+			self.scale_by_borrow_index(repaid_balance, Rounding::Down)
+		}
+
+		fn scale_by_borrow_index(
+			&self,
+			balance: AssetBalanceOf<T>,
+			rounding: Rounding,
+		) -> Result<AssetBalanceOf<T>, Error<T>> {
+			let scaled_balance = multiply_by_rational_with_rounding(
+				balance.saturated_into(),
+				FixedU128::DIV,
+				self.borrow_index.into_inner(),
+				rounding,
+			)
+			.ok_or(Error::<T>::OverflowError)?
+			.saturated_into();
 			Ok(scaled_balance)
 		}
 
-		/// Calculates linear interest as follows
+		/// Calculates linear interest over `period` seconds as follows
 		/// 	rate_per_second = rate / SECONDS_PER_YEAR
-		/// 	duration = now - last_updated_timestamp
-		/// 	rate = 1 + rate_per_second * duration
+		/// 	rate = 1 + rate_per_second * period
 		/// # Arguments
 		/// rate: Annual supply interest rate
-		fn calculate_linear_interest(&self) -> Result<Rate, Error<T>> {
-			let dur: u64 = Pallet::<T>::now_in_seconds()
-				.checked_sub(self.last_accrued_interest_at)
-				.ok_or(Error::<T>::OverflowError)?;
-			let rate_factor = self
-				.supply_interest_rate()?
-				.checked_mul(&FixedU128::from(dur as u128))
+		pub(crate) fn calculate_linear_interest(
+			&self,
+			rate: Rate,
+			period: u64,
+		) -> Result<Rate, Error<T>> {
+			let rate_factor = rate
+				.checked_mul(&FixedU128::from(period as u128))
 				.ok_or(Error::<T>::OverflowError)?;
 			let accumulated_rate = rate_factor
 				.checked_div(&(SECONDS_PER_YEAR as u128).into())
@@ -398,46 +628,57 @@ pub mod pallet {
 			Ok(accumulated_rate)
 		}
 
-		fn exp_fixed_u128(&self, x: FixedU128) -> Result<FixedU128, Error<T>> {
+		/// Calculates `e^x` by argument reduction
+		/// 	e^x = (e^(x / 2^k))^(2^k)
+		/// `x` is halved until it is at most one, so the Taylor series of the reduced argument
+		/// converges to full precision, and the result is then squared `k` times. Errors only
+		/// when `e^x` does not fit into a `FixedU128`, i.e. for `x` above ~47.
+		pub(crate) fn exp_fixed_u128(&self, x: FixedU128) -> Result<FixedU128, Error<T>> {
+			let mut squarings = 0u32;
+			while x.into_inner() >> squarings > FixedU128::DIV {
+				squarings += 1;
+			}
+			let reduced = FixedU128::from_inner(x.into_inner() >> squarings);
+
 			let mut sum = FixedU128::one();
 			let mut term = FixedU128::one();
 			let mut n = 1u128;
 
-			loop {
+			// For a reduced argument of at most one the terms drop below the precision of
+			// `FixedU128` after 20 iterations (20! > 10^18)
+			while !term.is_zero() && n <= 20 {
 				term = term
-					.checked_mul(&x)
+					.checked_mul(&reduced)
 					.ok_or(Error::<T>::OverflowError)?
 					.checked_div(&FixedU128::from(n))
 					.ok_or(Error::<T>::OverflowError)?;
 				sum = sum.checked_add(&term).ok_or(Error::<T>::OverflowError)?;
-
-				if term < FixedU128::from_inner(1_000_000) {
-					break;
-				}
-
 				n += 1;
-				if n > 20 {
-					// Limit the number of iterations to prevent infinite loops
-					break;
-				}
+			}
+
+			for _ in 0..squarings {
+				sum = sum.checked_mul(&sum).ok_or(Error::<T>::OverflowError)?;
 			}
 			Ok(sum)
 		}
 
 		/// Calculate compounded interest
-		/// Borrow interest compounds every second. This is achieved by using Taylor Series
-		/// Approximation
-		fn calculate_compunded_interest(&self) -> Result<Rate, Error<T>> {
-			let rate = self
-				.borrow_interest_rate()?
+		/// Borrow interest compounds every second, which over `period` seconds is
+		/// 	interest = e^(rate * period / SECONDS_PER_YEAR)
+		/// # Arguments
+		/// rate: Annual borrow interest rate
+		pub(crate) fn calculate_compunded_interest(
+			&self,
+			rate: Rate,
+			period: u64,
+		) -> Result<Rate, Error<T>> {
+			// Compute x = rate * period / SECONDS_PER_YEAR, dividing last to keep the precision
+			// of small per second rates
+			let x = rate
+				.checked_mul(&FixedU128::from(period as u128))
+				.ok_or(Error::<T>::OverflowError)?
 				.checked_div(&(SECONDS_PER_YEAR as u128).into())
 				.ok_or(Error::<T>::OverflowError)?;
-			let t = Pallet::<T>::now_in_seconds()
-				.checked_sub(self.last_accrued_interest_at)
-				.ok_or(Error::<T>::OverflowError)?;
-			// Compute x = rate * t
-			let x =
-				rate.checked_mul(&FixedU128::from(t as u128)).ok_or(Error::<T>::OverflowError)?;
 
 			// Compute interest = exp(x)
 			let interest = self.exp_fixed_u128(x)?;
@@ -445,27 +686,72 @@ pub mod pallet {
 			Ok(interest)
 		}
 
-		fn update_supply_index(&mut self) -> Result<(), Error<T>> {
-			let incr = self.calculate_linear_interest()?;
-			let new_index =
-				self.supply_index.checked_mul(&incr).ok_or(Error::<T>::OverflowError)?;
-			self.supply_index = new_index;
-			Ok(())
+		/// Accrues the interest of the time elapsed since the last accrual into the indexes, in
+		/// steps of at most `MAX_ACCRUAL_PERIOD` seconds. A step is halved until the borrow index
+		/// it compounds to fits into a `FixedU128`. Both indexes always accrue over the same
+		/// steps, so that suppliers are never credited interest that borrowers don't owe: once the
+		/// borrow index can't grow for even a second, neither index accrues anymore and `true` is
+		/// returned.
+		fn accrue_indexes(&mut self) -> Result<bool, Error<T>> {
+			let now = Pallet::<T>::now_in_seconds();
+			if self.last_accrued_interest_at >= now {
+				return Ok(false)
+			}
+			let borrow_rate = self.borrow_interest_rate()?;
+			let supply_rate = self.supply_interest_rate()?;
+
+			let mut step = MAX_ACCRUAL_PERIOD;
+			while self.last_accrued_interest_at < now {
+				step = step.min(now - self.last_accrued_interest_at);
+				let borrow_index =
+					self.calculate_compunded_interest(borrow_rate, step).and_then(|interest| {
+						self.borrow_index.checked_mul(&interest).ok_or(Error::<T>::OverflowError)
+					});
+				match borrow_index {
+					Ok(borrow_index) => {
+						let interest = self.calculate_linear_interest(supply_rate, step)?;
+						self.supply_index = self
+							.supply_index
+							.checked_mul(&interest)
+							.ok_or(Error::<T>::OverflowError)?;
+						self.borrow_index = borrow_index;
+						self.last_accrued_interest_at += step;
+					},
+					Err(_) if step > 1 => step /= 2,
+					Err(_) => {
+						self.last_accrued_interest_at = now;
+						return Ok(true)
+					},
+				}
+			}
+			Ok(false)
 		}
 
-		fn update_borrow_index(&mut self) -> Result<(), Error<T>> {
-			let incr = self.calculate_compunded_interest()?;
-			let new_index =
-				self.borrow_index.checked_mul(&incr).ok_or(Error::<T>::OverflowError)?;
-			self.borrow_index = new_index;
-			Ok(())
+		pub fn update_indexes(&mut self) -> Result<(), Error<T>> {
+			self.accrue_indexes().map(|_| ())
 		}
 
-		pub fn update_indexes(&mut self) -> Result<(), Error<T>> {
-			if self.last_accrued_interest_at < Pallet::<T>::now_in_seconds() {
-				self.update_supply_index()?;
-				self.update_borrow_index()?;
-				self.last_accrued_interest_at = Pallet::<T>::now_in_seconds();
+		/// Updates the indexes like `update_indexes`, depositing an `InterestAccrued` event when
+		/// they move and an `InterestAccrualCapped` event when they can't. Used where the updated
+		/// pool is stored, so the events follow the stored indexes.
+		pub fn accrue_interest(&mut self) -> Result<(), Error<T>> {
+			let indexes = (self.supply_index, self.borrow_index);
+			let capped = self.accrue_indexes()?;
+			if (self.supply_index, self.borrow_index) != indexes {
+				Pallet::<T>::deposit_event(Event::InterestAccrued {
+					asset: self.lend_token_id,
+					pool_id: self.id,
+					supply_index: self.supply_index,
+					borrow_index: self.borrow_index,
+					accrued_at: self.last_accrued_interest_at,
+				});
+			}
+			if capped {
+				Pallet::<T>::deposit_event(Event::InterestAccrualCapped {
+					asset: self.lend_token_id,
+					pool_id: self.id,
+					borrow_index: self.borrow_index,
+				});
 			}
 			Ok(())
 		}
@@ -526,18 +812,19 @@ pub mod pallet {
 		/// Calculate the repayable amount including accrued interest
 		/// repayable_amount = borrowed_balance * (current_borrow_index /
 		/// borrow_index_at_borrow_time)
+		/// rounded up, so the debt is never less than the borrowed balance
 		pub fn repayable_amount(
 			&self,
 			loan: &UserBorrow<T>,
 		) -> Result<AssetBalanceOf<T>, Error<T>> {
-			let borrowed_balance_u128 = loan.borrowed_balance.saturated_into();
-
-			let repayable_amount_u128 = FixedU128::from_inner(borrowed_balance_u128)
-				.checked_mul(&self.borrow_index)
-				.ok_or(Error::<T>::OverflowError)?
-				.into_inner();
-
-			let repayable_amount = repayable_amount_u128.saturated_into();
+			let repayable_amount = multiply_by_rational_with_rounding(
+				loan.borrowed_balance.saturated_into(),
+				self.borrow_index.into_inner(),
+				FixedU128::DIV,
+				Rounding::Up,
+			)
+			.ok_or(Error::<T>::OverflowError)?
+			.saturated_into();
 
 			Ok(repayable_amount)
 		}
@@ -552,42 +839,6 @@ pub mod pallet {
 	#[pallet::getter(fn reserve_pools)]
 	pub type LendingPoolStorage<T> = StorageMap<_, Blake2_128Concat, AssetPool<T>, LendingPool<T>>;
 
-	// Now we need to define the properties of the underlying asset used in the lending pool
-	#[derive(
-		Clone,
-		Encode,
-		Decode,
-		Eq,
-		PartialEq,
-		RuntimeDebug,
-		MaxEncodedLen,
-		TypeInfo,
-		PartialOrd,
-		DefaultNoBound,
-	)]
-	#[scale_info(skip_type_params(T))]
-	pub struct UnderlyingAsset<T: Config> {
-		pub underlying_asset_id: AssetIdOf<T>, /* Mapping of lend_token id to underlying
-		                                        * currency id */
-		pub last_accrued_interest: Timestamp, /* the timestamp of the last calculation of
-		                                       * accrued interest */
-
-		pub total_borrowed: AssetBalanceOf<T>, // the total amount borrowed from the pool
-		pub total_supply: AssetBalanceOf<T>,   // the total amount supplied to the pool
-
-		pub borrow_index: Rate, // accumulator of the total earned interest rate
-		pub exchange_rate: Rate, /* the exchange rate from the associated lend token to the
-		                         * underlying asset */
-		pub borrow_rate: Rate, // the current borrow rate
-		pub supply_rate: Rate, // the current supply rate
-
-		pub utilization_rate: Rate, // the current utilization rate
-
-		pub reward_supply_speed: AssetBalanceOf<T>, // the current reward supply speed
-		pub reward_borrow_speed: AssetBalanceOf<T>, // the current reward borrow speed
-		pub reward_accrued: AssetBalanceOf<T>,      // the current reward accrued
-	}
-
 	//  The accrued supply_index of the supplier
 	#[derive(
 		Clone,
@@ -612,22 +863,20 @@ pub mod pallet {
 		}
 	}
 
-	/// Kylix runtime storage items
-	///
-	/// Lending pools Assets Properties
-	///
-	/// StorageMap CurrencyId<T> { AssetId } => LendingAsset { PoolId, Balance }
-	///
-	/// The timestamp of the last calculation of accrued interest
-	#[pallet::storage]
-	#[pallet::getter(fn last_accrued_interest_time)]
-	pub type UnderlyingAssetStorage<T: Config> =
-		StorageMap<_, Blake2_128Concat, AssetIdOf<T>, UnderlyingAsset<T>, ValueQuery>;
+	/// Default bounds for the exchange rate, (1, 10). The exchange rate is the supply index, which
+	/// starts at 1 and only grows.
+	#[pallet::type_value]
+	pub fn DefaultMinMaxExchangeRate() -> (Rate, Rate) {
+		(Rate::one(), Rate::saturating_from_integer(10))
+	}
 
 	/// The minimum (starting) and maximum exchange rate allowed for a market.
+	/// The exchange rate of a lending pool must lie within these bounds when it is created and
+	/// whenever liquidity is supplied or withdrawn.
 	#[pallet::storage]
 	#[pallet::getter(fn max_exchange_rate)]
-	pub type MinMaxExchangeRate<T: Config> = StorageValue<_, (Rate, Rate), ValueQuery>;
+	pub type MinMaxExchangeRate<T: Config> =
+		StorageValue<_, (Rate, Rate), ValueQuery, DefaultMinMaxExchangeRate>;
 
 	/// The accrued supply_index of accounts for assets
 	#[pallet::storage]
@@ -635,10 +884,17 @@ pub mod pallet {
 		StorageMap<_, Blake2_128Concat, (AccountOf<T>, AssetIdOf<T>), SupplyIndex, ValueQuery>;
 
 	/// The borrow status of accounts
-	/// (AccountId, borrowed_asset_id, collateral_asset_id) => UserBorrow details
+	/// AccountId => (borrowed_asset_id, collateral_asset_id) => UserBorrow details
+	/// Keyed by account first, so that the loans of one account can be iterated directly.
 	#[pallet::storage]
-	pub type Borrows<T: Config> =
-		StorageMap<_, Blake2_128Concat, (AccountOf<T>, AssetIdOf<T>, AssetIdOf<T>), UserBorrow<T>>;
+	pub type Borrows<T: Config> = StorageDoubleMap<
+		_,
+		Blake2_128Concat,
+		AccountOf<T>,
+		Blake2_128Concat,
+		(AssetIdOf<T>, AssetIdOf<T>),
+		UserBorrow<T>,
+	>;
 
 	/// The storage to hold prices of assets w.r.t. other assets
 	/// This is the dummy storage, ideally this functionality would be implemented in a dedicated
@@ -647,6 +903,38 @@ pub mod pallet {
 	pub type AssetPrices<T: Config> =
 		StorageMap<_, Blake2_128Concat, (AssetIdOf<T>, AssetIdOf<T>), FixedU128, OptionQuery>;
 
+	/// The asset of the lending pool whose interest was last accrued in `on_idle`.
+	/// The next `on_idle` continues with the pool stored after it.
+	#[pallet::storage]
+	pub type LastIdleAccruedPool<T: Config> = StorageValue<_, AssetIdOf<T>, OptionQuery>;
+
+	/// The block from which the next unsigned liquidation is accepted
+	#[pallet::storage]
+	pub type NextUnsignedLiquidationAt<T: Config> = StorageValue<_, BlockNumberFor<T>, ValueQuery>;
+
+	/// The icon URI, display name and coingecko id of assets
+	#[pallet::storage]
+	pub type AssetMetadata<T: Config> =
+		StorageMap<_, Blake2_128Concat, AssetIdOf<T>, AssetDisplayMetadata<T>, OptionQuery>;
+
+	/// The rewards distributed every second to the suppliers and borrowers of the lending pool
+	/// of an asset
+	#[pallet::storage]
+	pub type RewardSpeeds<T: Config> =
+		StorageMap<_, Blake2_128Concat, AssetIdOf<T>, RewardSpeed<T>, ValueQuery>;
+
+	/// The accounts of the off-chain worker keys allowed to feed asset prices and to sign
+	/// unsigned liquidations
+	#[pallet::storage]
+	pub type Authorities<T: Config> =
+		StorageValue<_, BoundedVec<T::AccountId, T::MaxAuthorities>, ValueQuery>;
+
+	/// The debt of the loans closed by a liquidation that their collateral did not cover, by
+	/// borrowed asset. It is lost to the suppliers of the lending pool.
+	#[pallet::storage]
+	pub type BadDebt<T: Config> =
+		StorageMap<_, Blake2_128Concat, AssetIdOf<T>, AssetBalanceOf<T>, ValueQuery>;
+
 	#[pallet::genesis_config]
 	#[derive(frame_support::DefaultNoBound)]
 	pub struct GenesisConfig<T: Config> {
@@ -656,13 +944,7 @@ pub mod pallet {
 	#[pallet::genesis_build]
 	impl<T: Config> BuildGenesisConfig for GenesisConfig<T> {
 		fn build(&self) {
-			let max_exchange_rate: u128 = 1_000_000_000_000_000_000; // 1
-			let min_exchange_rate: u128 = 20_000_000_000_000_000; // 0.02
-
-			let rmax = Rate::from_inner(max_exchange_rate);
-			let rmin = Rate::from_inner(min_exchange_rate);
-
-			MinMaxExchangeRate::<T>::put((rmin, rmax));
+			MinMaxExchangeRate::<T>::put(DefaultMinMaxExchangeRate());
 		}
 	}
 
@@ -673,24 +955,47 @@ pub mod pallet {
 		LiquiditySupplied {
 			who: T::AccountId,
 			asset: AssetIdOf<T>,
+			pool_id: LendingPoolId,
 			balance: BalanceOf<T>,
+			scaled_balance: AssetBalanceOf<T>,
+			supply_index: Rate,
 		},
 		LiquidityWithdrawn {
 			who: T::AccountId,
 			asset: AssetIdOf<T>,
+			pool_id: LendingPoolId,
 			balance: BalanceOf<T>,
+			scaled_balance: AssetBalanceOf<T>,
+			supply_index: Rate,
 		},
 		Borrowed {
 			who: T::AccountId,
+			pool_id: LendingPoolId,
 			borrowed_asset_id: AssetIdOf<T>,
 			borrowed_balance: BalanceOf<T>,
+			scaled_balance: AssetBalanceOf<T>,
+			borrow_index: Rate,
 			collateral_asset_id: AssetIdOf<T>,
 			collateral_balance: BalanceOf<T>,
 		},
 		Repaid {
 			who: T::AccountId,
+			pool_id: LendingPoolId,
+			repaid_asset_id: AssetIdOf<T>,
+			repaid_balance: BalanceOf<T>,
+			scaled_balance: AssetBalanceOf<T>,
+			borrow_index: Rate,
+			collateral_asset_id: AssetIdOf<T>,
+			collateral_balance: BalanceOf<T>,
+		},
+		RepaidOnBehalf {
+			payer: T::AccountId,
+			borrower: T::AccountId,
+			pool_id: LendingPoolId,
 			repaid_asset_id: AssetIdOf<T>,
 			repaid_balance: BalanceOf<T>,
+			scaled_balance: AssetBalanceOf<T>,
+			borrow_index: Rate,
 			collateral_asset_id: AssetIdOf<T>,
 			collateral_balance: BalanceOf<T>,
 		},
@@ -725,13 +1030,58 @@ pub mod pallet {
 		LPTokenMinted {
 			who: T::AccountId,
 			asset: AssetIdOf<T>,
+			pool_id: LendingPoolId,
 			balance: AssetBalanceOf<T>,
+			supply_index: Rate,
 		},
 		AssetPriceAdded {
 			asset: AssetIdOf<T>,
 			base_asset: AssetIdOf<T>,
 			price: FixedU128,
 		},
+		Liquidated {
+			liquidator: T::AccountId,
+			borrower: T::AccountId,
+			borrowed_asset_id: AssetIdOf<T>,
+			repaid_balance: AssetBalanceOf<T>,
+			collateral_asset_id: AssetIdOf<T>,
+			collateral_balance: AssetBalanceOf<T>,
+		},
+		AssetMetadataSet {
+			asset: AssetIdOf<T>,
+		},
+		AssetMetadataCleared {
+			asset: AssetIdOf<T>,
+		},
+		RewardSpeedUpdated {
+			asset: AssetIdOf<T>,
+			supply_speed: AssetBalanceOf<T>,
+			borrow_speed: AssetBalanceOf<T>,
+		},
+		InterestAccrued {
+			asset: AssetIdOf<T>,
+			pool_id: LendingPoolId,
+			supply_index: Rate,
+			borrow_index: Rate,
+			accrued_at: Timestamp,
+		},
+		/// A liquidation seized all the collateral of a loan without repaying all of its debt.
+		/// The loan is closed and the rest of its debt recorded in `BadDebt`.
+		BadDebtRecorded {
+			borrower: T::AccountId,
+			borrowed_asset_id: AssetIdOf<T>,
+			collateral_asset_id: AssetIdOf<T>,
+			bad_debt: AssetBalanceOf<T>,
+		},
+		/// The borrow index of the pool can't grow anymore, so neither index accrues interest
+		InterestAccrualCapped {
+			asset: AssetIdOf<T>,
+			pool_id: LendingPoolId,
+			borrow_index: Rate,
+		},
+		AuthoritiesSet {
+			authorities: Vec<T::AccountId>,
+		},
 	}
 
 	// Errors inform users that something went wrong.
@@ -771,6 +1121,47 @@ pub mod pallet {
 		AssetPriceNotSet,
 		/// Division by zero
 		DivisionByZero,
+		/// The exchange rate is outside the allowed `MinMaxExchangeRate` bounds
+		ExchangeRateOutOfBounds,
+		/// The borrower of the loan has a weighted health factor of at least one
+		PositionNotLiquidatable,
+		/// More loans than `MaxLiquidationsPerRun` are liquidated at once
+		TooManyLiquidations,
+		/// A field of the asset metadata is longer than `MaxAssetMetadataLength`
+		AssetMetadataTooLong,
+		/// No metadata is set for the asset
+		AssetMetadataNotFound,
+		/// The borrow would leave the account with a weighted health factor below one
+		HealthFactorTooLow,
+	}
+
+	#[pallet::hooks]
+	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+		/// Accrues interest on as many lending pools as the remaining weight allows, so the
+		/// indexes stay fresh even if no user touches a pool
+		fn on_idle(_n: BlockNumberFor<T>, remaining_weight: Weight) -> Weight {
+			Self::accrue_interest_on_idle(remaining_weight)
+		}
+
+		/// Feeds the asset prices and liquidates the loans of the accounts with a weighted health
+		/// factor below one
+		fn offchain_worker(block_number: BlockNumberFor<T>) {
+			match Self::feed_prices_offchain(block_number) {
+				// a signed liquidation would reuse the nonce of the price update
+				Ok(true) if T::SignedLiquidations::get() => return,
+				Ok(_) => {},
+				Err(e) =>
+					log::debug!(target: "runtime::lending", "Price feed off-chain worker: {}", e),
+			}
+			if let Err(e) = Self::liquidate_offchain(block_number) {
+				log::debug!(target: "runtime::lending", "Liquidation off-chain worker: {}", e);
+			}
+		}
+
+		#[cfg(feature = "try-runtime")]
+		fn try_state(_n: BlockNumberFor<T>) -> Result<(), sp_runtime::TryRuntimeError> {
+			Self::do_try_state()
+		}
 	}
 
 	#[pallet::call]
@@ -804,8 +1195,8 @@ pub mod pallet {
 		/// If the function succeeds, it triggers two events:
 		///
 		/// * `LendingPoolAdded(who, asset)` if a new lending pool was created.
-		/// * `LiquiditySupplied(who, asset, balance)` after the liquidity has been successfully
-		///   added.
+		/// * `LiquiditySupplied(who, asset, pool_id, balance, scaled_balance, supply_index)` after
+		///   the liquidity has been successfully added.
 		#[pallet::call_index(0)]
 		#[pallet::weight(Weight::default())]
 		pub fn create_lending_pool(
@@ -816,8 +1207,6 @@ pub mod pallet {
 		) -> DispatchResult {
 			let who = ensure_signed(origin)?;
 			Self::do_create_lending_pool(&who, id, asset, balance)?;
-			Self::deposit_event(Event::LendingPoolAdded { who: who.clone(), asset });
-			Self::deposit_event(Event::LiquiditySupplied { who, asset, balance });
 			Ok(())
 		}
 
@@ -880,7 +1269,8 @@ pub mod pallet {
 		///
 		/// If the function succeeds, it triggers an event:
 		///
-		/// * `LiquiditySupplied(who, asset, balance)` if the lending pool has been supplied.
+		/// * `LiquiditySupplied(who, asset, pool_id, balance, scaled_balance, supply_index)` if the
+		///   lending pool has been supplied.
 		#[pallet::call_index(2)]
 		#[pallet::weight(Weight::default())]
 		pub fn supply(
@@ -890,7 +1280,6 @@ pub mod pallet {
 		) -> DispatchResult {
 			let who = ensure_signed(origin)?;
 			Self::do_supply(&who, asset, balance)?;
-			Self::deposit_event(Event::LiquiditySupplied { who, asset, balance });
 			Ok(())
 		}
 
@@ -920,7 +1309,8 @@ pub mod pallet {
 		///
 		/// If the function succeeds, it triggers an event:
 		///
-		/// * `LiquidityWithdrawn(who, asset, balance)` if the lending pool was activated.
+		/// * `LiquidityWithdrawn(who, asset, pool_id, balance, scaled_balance, supply_index)` if
+		///   the liquidity was withdrawn.
 		#[pallet::call_index(3)]
 		#[pallet::weight(Weight::default())]
 		pub fn withdraw(
@@ -930,7 +1320,6 @@ pub mod pallet {
 		) -> DispatchResult {
 			let who = ensure_signed(origin)?;
 			Self::do_withdrawal(&who, asset, balance)?;
-			Self::deposit_event(Event::LiquidityWithdrawn { who, asset, balance });
 			Ok(())
 		}
 
@@ -960,8 +1349,8 @@ pub mod pallet {
 		///
 		/// If the function succeeds, it triggers an event:
 		///
-		/// * `Borrowed(who, borrowed_asset_id, borrowed_balance, collateral_asset_id,
-		///   collateral_balance)`.
+		/// * `Borrowed(who, pool_id, borrowed_asset_id, borrowed_balance, scaled_balance,
+		///   borrow_index, collateral_asset_id, collateral_balance)`.
 		#[pallet::call_index(4)]
 		#[pallet::weight(Weight::default())]
 		pub fn borrow(
@@ -1001,8 +1390,8 @@ pub mod pallet {
 		///
 		/// If the function succeeds, it triggers an event:
 		///
-		/// * `Repaid(who, repaid_asset_id, repaid_balance, collateral_asset_id, collateral_balance
-		///   )`.
+		/// * `Repaid(who, pool_id, repaid_asset_id, repaid_balance, scaled_balance, borrow_index,
+		///   collateral_asset_id, collateral_balance)`.
 		#[pallet::call_index(5)]
 		#[pallet::weight(Weight::default())]
 		pub fn repay(
@@ -1139,11 +1528,13 @@ pub mod pallet {
 
 		/// Sets the price of one asset in terms of another asset.
 		///
-		/// The `set_asset_price` extrinsic allows a user to specify the relative price of one asset
-		/// (`asset`) in terms of another asset (`base_asset`).
+		/// The `set_asset_price` extrinsic allows the `ManagerOrigin`, or one of the `Authorities`,
+		/// to specify the relative price of one asset (`asset`) in terms of another asset
+		/// (`base_asset`).
 		///
 		/// # Parameters
-		/// - `origin`: The transaction origin. This must be a signed extrinsic.
+		/// - `origin`: The transaction origin. This must be the `ManagerOrigin` or signed by one of
+		///   the `Authorities`.
 		/// - `asset`: The identifier for the first asset. This is the asset whose price is being
 		///   set.
 		/// - `base_asset`: The identifier for the second asset. This is the asset relative to which
@@ -1156,6 +1547,7 @@ pub mod pallet {
 		///   is successfully set. It contains the asset identifiers and the new price.
 		///
 		/// # Errors
+		/// - `BadOrigin`: The origin is neither the `ManagerOrigin` nor one of the `Authorities`.
 		/// - `InvalidAssetPrice`: This error is thrown if the `price` parameter is zero.
 		///
 		/// # Note this should be moved to a new pallet `prices`
@@ -1167,56 +1559,428 @@ pub mod pallet {
 			base_asset: AssetIdOf<T>,
 			price: FixedU128,
 		) -> DispatchResult {
-			let _who = ensure_signed(origin)?;
-			// price should not be zero
-			ensure!(price > FixedU128::zero(), Error::<T>::InvalidAssetPrice);
-
-			AssetPrices::<T>::set((asset, base_asset), Some(price));
-
-			// Emit an event.
-			Self::deposit_event(Event::AssetPriceAdded { asset, base_asset, price });
-
-			Ok(())
+			Self::ensure_price_origin(origin)?;
+			Self::do_set_asset_price(asset, base_asset, price)
 		}
-	}
 
-	// the main logic of the pallet
-	impl<T: Config> Pallet<T> {
-		// This method creates a NEW lending pool and mints LP tokens back to the user.
-		// At this moment, the user is the first liquidity provider
-		// The pool must not exist and the user must have enough liquidity to supply.
-		pub fn do_create_lending_pool(
-			who: &T::AccountId,
-			id: LendingPoolId,
-			asset: AssetIdOf<T>,
-			balance: BalanceOf<T>,
+		/// The `liquidate` function allows a user to liquidate a loan of a borrower whose
+		/// `weighted_health_factor` is below one, the health factor borrows are checked against.
+		/// The liquidator repays
+		/// `CloseFactor` of the debt, including interest, and receives its value in collateral
+		/// plus the `LiquidationBonus`, at most all the collateral of the loan. A loan whose
+		/// collateral is all seized is closed, and the rest of its debt recorded as `BadDebt`.
+		///
+		/// # Arguments
+		///
+		/// * `origin` - The origin caller of this function. This should be signed by the
+		///   liquidator.
+		/// * `borrower` - The account owning the loan.
+		/// * `borrowed_asset` - The borrowed asset of the loan.
+		/// * `collateral_asset` - The collateral asset of the loan.
+		///
+		/// # Errors
+		///
+		/// This function will return an error in the following scenarios:
+		///
+		/// * If the origin is not signed (i.e., the function was not called by a user).
+		/// * If the pool or the loan does not exist.
+		/// * If the weighted health factor of the borrower is not below one.
+		/// * If the liquidator has not enough of `borrowed_asset` to repay their share of the debt.
+		///
+		/// # Events
+		///
+		/// If the function succeeds, it triggers an event:
+		///
+		/// * `Liquidated(liquidator, borrower, borrowed_asset_id, repaid_balance,
+		///   collateral_asset_id, collateral_balance)`.
+		#[pallet::call_index(11)]
+		#[pallet::weight(Weight::default())]
+		pub fn liquidate(
+			origin: OriginFor<T>,
+			borrower: T::AccountId,
+			borrowed_asset: AssetIdOf<T>,
+			collateral_asset: AssetIdOf<T>,
 		) -> DispatchResult {
-			// First, let's check the balance amount is valid
-			ensure!(balance > BalanceOf::<T>::zero(), Error::<T>::InvalidLiquiditySupply);
-
-			// Second, let's check if the user has enough liquidity
-			let user_balance = T::Fungibles::balance(asset.clone(), who);
-			ensure!(user_balance >= balance, Error::<T>::NotEnoughLiquiditySupply);
+			let who = ensure_signed(origin)?;
+			Self::do_liquidate(&who, &borrower, borrowed_asset, collateral_asset)
+		}
 
-			// Now let's check if the pool is already existing, before creating a new one.
-			let asset_pool = AssetPool::<T>::from(asset);
+		/// Liquidates the loans found by the off-chain worker, on behalf of the account of the
+		/// key that signed `liquidation_payload`. Loans that can not be liquidated anymore are
+		/// skipped. Only one such transaction is accepted every `LiquidationInterval` blocks.
+		///
+		/// # Errors
+		///
+		/// * If the origin is not none.
+		/// * If the payload carries more than `MaxLiquidationsPerRun` loans.
+		///
+		/// # Events
+		///
+		/// * `Liquidated` for every liquidated loan.
+		#[pallet::call_index(12)]
+		#[pallet::weight(Weight::default())]
+		pub fn liquidate_unsigned_with_signed_payload(
+			origin: OriginFor<T>,
+			liquidation_payload: LiquidationPayload<T::Public, BlockNumberFor<T>, LoanKey<T>>,
+			_signature: T::Signature,
+		) -> DispatchResult {
+			ensure_none(origin)?;
 			ensure!(
-				!LendingPoolStorage::<T>::contains_key(&asset_pool),
-				Error::<T>::LendingPoolAlreadyExists
+				liquidation_payload.positions.len() <= T::MaxLiquidationsPerRun::get() as usize,
+				Error::<T>::TooManyLiquidations
 			);
 
-			// make sure id does not exist already
-			ensure!(!T::Fungibles::asset_exists(id.clone()), Error::<T>::IdAlreadyExists);
-
-			// Now we can safely create and store our lending pool with an initial balance...
-			let asset_pool = AssetPool::from(asset);
-			let lending_pool = LendingPool::<T>::from(id, asset, balance)?;
+			let liquidator = liquidation_payload.public.into_account();
+			for (borrower, borrowed_asset, collateral_asset) in liquidation_payload.positions {
+				// a failed liquidation is rolled back without affecting the others
+				let res = with_storage_layer(|| {
+					Self::do_liquidate(&liquidator, &borrower, borrowed_asset, collateral_asset)
+				});
+				if let Err(e) = res {
+					log::debug!(
+						target: "runtime::lending",
+						"Skipped the liquidation of {:?}: {:?}",
+						(borrower, borrowed_asset, collateral_asset),
+						e
+					);
+				}
+			}
 
-			LendingPoolStorage::<T>::set(&asset_pool, Some(lending_pool.clone()));
+			NextUnsignedLiquidationAt::<T>::put(
+				frame_system::Pallet::<T>::block_number()
+					.saturating_add(T::LiquidationInterval::get()),
+			);
+			Ok(())
+		}
 
-			// Transfer the tokens (asset) from the users account into pallet account
-			T::Fungibles::transfer(
-				asset.clone(),
+		/// Sets the prices of several assets in terms of the same base asset.
+		///
+		/// The `set_asset_prices` extrinsic is what the off-chain price feed submits, signed with
+		/// the key of one of the `Authorities`. Like `set_asset_price`, the `ManagerOrigin` can
+		/// call it too.
+		///
+		/// # Parameters
+		/// - `origin`: The transaction origin. This must be the `ManagerOrigin` or signed by one of
+		///   the `Authorities`.
+		/// - `base_asset`: The identifier of the asset relative to which the prices are measured.
+		/// - `prices`: The `(asset, price)` pairs to set. Every price must be non-zero.
+		///
+		/// # Events
+		/// - `AssetPriceAdded { asset, base_asset, price }`: Emitted for every price set.
+		///
+		/// # Errors
+		/// - `BadOrigin`: The origin is neither the `ManagerOrigin` nor one of the `Authorities`.
+		/// - `InvalidAssetPrice`: This error is thrown if any price is zero, in which case no price
+		///   is set.
+		#[pallet::call_index(13)]
+		#[pallet::weight(Weight::default())]
+		pub fn set_asset_prices(
+			origin: OriginFor<T>,
+			base_asset: AssetIdOf<T>,
+			prices: Vec<(AssetIdOf<T>, FixedU128)>,
+		) -> DispatchResult {
+			Self::ensure_price_origin(origin)?;
+			ensure!(
+				prices.iter().all(|(_, price)| *price > FixedU128::zero()),
+				Error::<T>::InvalidAssetPrice
+			);
+			for (asset, price) in prices {
+				Self::do_set_asset_price(asset, base_asset, price)?;
+			}
+			Ok(())
+		}
+
+		/// Sets the display metadata of an asset, returned with the asset by the runtime APIs.
+		///
+		/// # Parameters
+		/// - `origin`: The transaction origin. This must be the `ManagerOrigin`.
+		/// - `asset`: The identifier of the asset.
+		/// - `icon`: The URI of the icon of the asset.
+		/// - `display_name`: The name of the asset shown to users.
+		/// - `coingecko_id`: The id of the asset on price aggregators, e.g. `polkadot`.
+		///
+		/// # Events
+		/// - `AssetMetadataSet { asset }`: Emitted after the metadata is set.
+		///
+		/// # Errors
+		/// - `BadOrigin`: The origin is not the `ManagerOrigin`.
+		/// - `AssetMetadataTooLong`: A field is longer than `MaxAssetMetadataLength`.
+		#[pallet::call_index(14)]
+		#[pallet::weight(Weight::default())]
+		pub fn set_asset_metadata(
+			origin: OriginFor<T>,
+			asset: AssetIdOf<T>,
+			icon: Vec<u8>,
+			display_name: Vec<u8>,
+			coingecko_id: Vec<u8>,
+		) -> DispatchResult {
+			T::ManagerOrigin::ensure_origin(origin)?;
+			let metadata = AssetDisplayMetadata::<T>::new(icon, display_name, coingecko_id)?;
+			AssetMetadata::<T>::insert(asset, metadata);
+			Self::deposit_event(Event::AssetMetadataSet { asset });
+			Ok(())
+		}
+
+		/// Removes the display metadata of an asset.
+		///
+		/// # Parameters
+		/// - `origin`: The transaction origin. This must be the `ManagerOrigin`.
+		/// - `asset`: The identifier of the asset.
+		///
+		/// # Events
+		/// - `AssetMetadataCleared { asset }`: Emitted after the metadata is removed.
+		///
+		/// # Errors
+		/// - `BadOrigin`: The origin is not the `ManagerOrigin`.
+		/// - `AssetMetadataNotFound`: No metadata is set for the asset.
+		#[pallet::call_index(15)]
+		#[pallet::weight(Weight::default())]
+		pub fn clear_asset_metadata(origin: OriginFor<T>, asset: AssetIdOf<T>) -> DispatchResult {
+			T::ManagerOrigin::ensure_origin(origin)?;
+			AssetMetadata::<T>::take(asset).ok_or(Error::<T>::AssetMetadataNotFound)?;
+			Self::deposit_event(Event::AssetMetadataCleared { asset });
+			Ok(())
+		}
+
+		/// Sets the amounts of `RewardAsset` distributed every second to the suppliers and to the
+		/// borrowers of a lending pool. They give the incentive APYs of the pool.
+		///
+		/// # Parameters
+		/// - `origin`: The transaction origin. This must be the `ManagerOrigin`.
+		/// - `asset`: The asset of the lending pool.
+		/// - `supply_speed`: The rewards of the suppliers per second.
+		/// - `borrow_speed`: The rewards of the borrowers per second.
+		///
+		/// # Events
+		/// - `RewardSpeedUpdated { asset, supply_speed, borrow_speed }`: Emitted after the speeds
+		///   are set.
+		///
+		/// # Errors
+		/// - `BadOrigin`: The origin is not the `ManagerOrigin`.
+		/// - `LendingPoolDoesNotExist`: There is no lending pool for `asset`.
+		#[pallet::call_index(16)]
+		#[pallet::weight(Weight::default())]
+		pub fn set_reward_speed(
+			origin: OriginFor<T>,
+			asset: AssetIdOf<T>,
+			supply_speed: AssetBalanceOf<T>,
+			borrow_speed: AssetBalanceOf<T>,
+		) -> DispatchResult {
+			T::ManagerOrigin::ensure_origin(origin)?;
+			ensure!(
+				LendingPoolStorage::<T>::contains_key(AssetPool::<T>::from(asset)),
+				Error::<T>::LendingPoolDoesNotExist
+			);
+			RewardSpeeds::<T>::insert(asset, RewardSpeed { supply_speed, borrow_speed });
+			Self::deposit_event(Event::RewardSpeedUpdated { asset, supply_speed, borrow_speed });
+			Ok(())
+		}
+
+		/// Withdraws all the liquidity of the caller from a lending pool. The withdrawn balance is
+		/// computed at execution time from the LP tokens of the caller, including the interest
+		/// accrued up to now, and all the LP tokens are burned.
+		///
+		/// # Parameters
+		/// - `origin`: The transaction origin. This must be signed by the supplier.
+		/// - `asset`: The asset of the lending pool.
+		///
+		/// # Events
+		/// - `LiquidityWithdrawn { who, asset, pool_id, balance, scaled_balance, supply_index }`:
+		///   Emitted with the withdrawn balance and the burned LP tokens.
+		///
+		/// # Errors
+		/// - `LendingPoolDoesNotExist`: There is no lending pool for `asset`.
+		/// - `InvalidLiquidityWithdrawal`: The caller has no liquidity to withdraw.
+		/// - `NotEnoughLiquiditySupply`: The pool does not hold enough liquidity.
+		#[pallet::call_index(17)]
+		#[pallet::weight(Weight::default())]
+		pub fn withdraw_all(origin: OriginFor<T>, asset: AssetIdOf<T>) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::do_withdraw_all(&who, asset)?;
+			Ok(())
+		}
+
+		/// Repays the whole debt of a loan of the caller. The repaid balance is computed at
+		/// execution time, including the interest accrued up to now, so the loan is closed and all
+		/// its collateral is released.
+		///
+		/// # Parameters
+		/// - `origin`: The transaction origin. This must be signed by the borrower.
+		/// - `asset`: The borrowed asset.
+		/// - `collateral_asset`: The collateral asset of the loan.
+		///
+		/// # Events
+		/// - `Repaid { who, pool_id, repaid_asset_id, repaid_balance, scaled_balance, borrow_index,
+		///   collateral_asset_id, collateral_balance }`: Emitted with the repaid debt and the
+		///   released collateral.
+		///
+		/// # Errors
+		/// - `LendingPoolDoesNotExist`: There is no lending pool for `asset`.
+		/// - `LoanDoesNotExists`: The caller has no loan of `asset` against `collateral_asset`.
+		#[pallet::call_index(18)]
+		#[pallet::weight(Weight::default())]
+		pub fn repay_all(
+			origin: OriginFor<T>,
+			asset: AssetIdOf<T>,
+			collateral_asset: AssetIdOf<T>,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::do_repay_all(&who, asset, collateral_asset)?;
+			Ok(())
+		}
+
+		/// Repays `balance` of the loan of `borrower`, capped at its debt, from the account of the
+		/// caller. Any account can pay down the loan of another account, and the released
+		/// collateral always goes back to the borrower.
+		///
+		/// # Parameters
+		/// - `origin`: The transaction origin. This must be signed by the payer.
+		/// - `borrower`: The account of the loan.
+		/// - `asset`: The borrowed asset.
+		/// - `balance`: The amount of `asset` to repay.
+		/// - `collateral_asset`: The collateral asset of the loan.
+		///
+		/// # Events
+		/// - `RepaidOnBehalf { payer, borrower, pool_id, repaid_asset_id, repaid_balance,
+		///   scaled_balance, borrow_index, collateral_asset_id, collateral_balance }`: Emitted with
+		///   the repaid debt and the collateral released to the borrower, or `Repaid` if the caller
+		///   is the borrower.
+		///
+		/// # Errors
+		/// - `InvalidLiquidityWithdrawal`: `balance` is zero.
+		/// - `LendingPoolDoesNotExist`: There is no lending pool for `asset`.
+		/// - `LoanDoesNotExists`: `borrower` has no loan of `asset` against `collateral_asset`.
+		#[pallet::call_index(19)]
+		#[pallet::weight(Weight::default())]
+		pub fn repay_on_behalf(
+			origin: OriginFor<T>,
+			borrower: T::AccountId,
+			asset: AssetIdOf<T>,
+			balance: BalanceOf<T>,
+			collateral_asset: AssetIdOf<T>,
+		) -> DispatchResult {
+			let payer = ensure_signed(origin)?;
+			Self::do_repay_on_behalf(&payer, &borrower, asset, balance, collateral_asset)?;
+			Ok(())
+		}
+
+		/// Sets the `Authorities`, the accounts of the off-chain worker keys allowed to feed asset
+		/// prices and to sign unsigned liquidations. Replaces the previous authorities.
+		///
+		/// # Parameters
+		/// - `origin`: The transaction origin. This must be the `ManagerOrigin`.
+		/// - `authorities`: The accounts of the authorities.
+		///
+		/// # Events
+		/// - `AuthoritiesSet { authorities }`: Emitted after the authorities are set.
+		///
+		/// # Errors
+		/// - `BadOrigin`: The origin is not the `ManagerOrigin`.
+		#[pallet::call_index(20)]
+		#[pallet::weight(Weight::default())]
+		pub fn set_authorities(
+			origin: OriginFor<T>,
+			authorities: BoundedVec<T::AccountId, T::MaxAuthorities>,
+		) -> DispatchResult {
+			T::ManagerOrigin::ensure_origin(origin)?;
+			Authorities::<T>::put(&authorities);
+			Self::deposit_event(Event::AuthoritiesSet { authorities: authorities.into_inner() });
+			Ok(())
+		}
+	}
+
+	#[pallet::validate_unsigned]
+	impl<T: Config> ValidateUnsigned for Pallet<T> {
+		type Call = Call<T>;
+
+		fn validate_unsigned(_source: TransactionSource, call: &Self::Call) -> TransactionValidity {
+			if let Call::liquidate_unsigned_with_signed_payload {
+				liquidation_payload: ref payload,
+				ref signature,
+			} = call
+			{
+				if !SignedPayload::<T>::verify::<T::AuthorityId>(payload, signature.clone()) {
+					return InvalidTransaction::BadProof.into();
+				}
+				// any key can sign a payload, only the ones of the authorities are trusted
+				if !Self::is_authority(&payload.public.clone().into_account()) {
+					return InvalidTransaction::BadSigner.into();
+				}
+				Self::validate_liquidation_payload(payload)
+			} else {
+				InvalidTransaction::Call.into()
+			}
+		}
+	}
+
+	// the main logic of the pallet
+	impl<T: Config> Pallet<T> {
+		/// Whether `who` is one of the `Authorities`
+		pub fn is_authority(who: &T::AccountId) -> bool {
+			Authorities::<T>::get().contains(who)
+		}
+
+		/// Ensures `origin` is the `ManagerOrigin` or signed by one of the `Authorities`, the
+		/// origins allowed to set asset prices
+		fn ensure_price_origin(origin: OriginFor<T>) -> DispatchResult {
+			if let Err(origin) = T::ManagerOrigin::try_origin(origin) {
+				let who = ensure_signed(origin)?;
+				ensure!(Self::is_authority(&who), DispatchError::BadOrigin);
+			}
+			Ok(())
+		}
+
+		pub fn do_set_asset_price(
+			asset: AssetIdOf<T>,
+			base_asset: AssetIdOf<T>,
+			price: FixedU128,
+		) -> DispatchResult {
+			// price should not be zero
+			ensure!(price > FixedU128::zero(), Error::<T>::InvalidAssetPrice);
+
+			AssetPrices::<T>::set((asset, base_asset), Some(price));
+
+			// Emit an event.
+			Self::deposit_event(Event::AssetPriceAdded { asset, base_asset, price });
+
+			Ok(())
+		}
+
+		// This method creates a NEW lending pool and mints LP tokens back to the user.
+		// At this moment, the user is the first liquidity provider
+		// The pool must not exist and the user must have enough liquidity to supply.
+		pub fn do_create_lending_pool(
+			who: &T::AccountId,
+			id: LendingPoolId,
+			asset: AssetIdOf<T>,
+			balance: BalanceOf<T>,
+		) -> DispatchResult {
+			// First, let's check the balance amount is valid
+			ensure!(balance > BalanceOf::<T>::zero(), Error::<T>::InvalidLiquiditySupply);
+
+			// Second, let's check if the user has enough liquidity
+			let user_balance = T::Fungibles::balance(asset.clone(), who);
+			ensure!(user_balance >= balance, Error::<T>::NotEnoughLiquiditySupply);
+
+			// Now let's check if the pool is already existing, before creating a new one.
+			let asset_pool = AssetPool::<T>::from(asset);
+			ensure!(
+				!LendingPoolStorage::<T>::contains_key(&asset_pool),
+				Error::<T>::LendingPoolAlreadyExists
+			);
+
+			// make sure id does not exist already
+			ensure!(!T::Fungibles::asset_exists(id.clone()), Error::<T>::IdAlreadyExists);
+
+			// Now we can safely create and store our lending pool with an initial balance...
+			let asset_pool = AssetPool::from(asset);
+			let lending_pool = LendingPool::<T>::from(id, asset, balance)?;
+
+			LendingPoolStorage::<T>::set(&asset_pool, Some(lending_pool.clone()));
+
+			// Transfer the tokens (asset) from the users account into pallet account
+			T::Fungibles::transfer(
+				asset.clone(),
 				who,
 				&Self::account_id(),
 				balance,
@@ -1230,10 +1994,14 @@ pub mod pallet {
 			// mints the lp tokens into the users account
 			Self::update_and_mint(who, asset, id, scaled_minted_tokens, lending_pool.supply_index)?;
 
-			Self::deposit_event(Event::LPTokenMinted {
+			Self::deposit_event(Event::LendingPoolAdded { who: who.clone(), asset });
+			Self::deposit_event(Event::LiquiditySupplied {
 				who: who.clone(),
-				asset: id,
-				balance: scaled_minted_tokens,
+				asset,
+				pool_id: id,
+				balance,
+				scaled_balance: scaled_minted_tokens,
+				supply_index: lending_pool.supply_index,
 			});
 			Ok(())
 		}
@@ -1280,7 +2048,7 @@ pub mod pallet {
 			ensure!(pool.is_active() == true, Error::<T>::LendingPoolNotActive);
 
 			// Update pool supply index
-			pool.update_indexes()?;
+			pool.accrue_interest()?;
 			pool.reserve_balance =
 				pool.reserve_balance.checked_add(&balance).ok_or(Error::<T>::OverflowError)?;
 
@@ -1297,6 +2065,14 @@ pub mod pallet {
 			let current_supply_index = pool.supply_index;
 			Self::update_and_mint(who, asset, pool.id, scaled_minted_tokens, current_supply_index)?;
 
+			Self::deposit_event(Event::LiquiditySupplied {
+				who: who.clone(),
+				asset,
+				pool_id: pool.id,
+				balance,
+				scaled_balance: scaled_minted_tokens,
+				supply_index: current_supply_index,
+			});
 			// let's update the balances of the pool now
 			LendingPoolStorage::<T>::set(&asset_pool, Some(pool));
 
@@ -1313,21 +2089,41 @@ pub mod pallet {
 		) -> DispatchResult {
 			// First, let's check the balance amount to supply is valid
 			ensure!(balance > BalanceOf::<T>::zero(), Error::<T>::InvalidLiquidityWithdrawal);
+			Self::withdraw_liquidity(who, asset, Some(balance))
+		}
+
+		/// Withdraws all the liquidity of the user, including the interest accrued up to now, and
+		/// burns all its LP tokens, so that no dust is left.
+		pub fn do_withdraw_all(who: &T::AccountId, asset: AssetIdOf<T>) -> DispatchResult {
+			Self::withdraw_liquidity(who, asset, None)
+		}
 
+		/// Withdraws `balance` of liquidity, or all the liquidity of the user if `None`
+		fn withdraw_liquidity(
+			who: &T::AccountId,
+			asset: AssetIdOf<T>,
+			balance: Option<BalanceOf<T>>,
+		) -> DispatchResult {
 			// let's check if our pool does exist
 			let asset_pool = AssetPool::<T>::from(asset);
 			let mut pool = LendingPoolStorage::<T>::get(&asset_pool)
 				.ok_or_else(|| DispatchError::from(Error::<T>::LendingPoolDoesNotExist))?;
 
-			// let's check if the pool has enough liquidity
-			ensure!(pool.reserve_balance >= balance, Error::<T>::NotEnoughLiquiditySupply);
-
 			// Update pool's indexes
-			pool.update_indexes()?;
+			pool.accrue_interest()?;
 
-			// let's check if the user is actually eligible to withdraw!
 			let scaled_lp_tokens = T::Fungibles::balance(pool.id.clone(), &who);
 			let eligible_lp_tokens = pool.accrued_deposit(scaled_lp_tokens)?;
+			let (balance, burnable_amount) = match balance {
+				Some(balance) => (balance, pool.scaled_supply_balance(balance)?),
+				None => (eligible_lp_tokens, scaled_lp_tokens),
+			};
+			ensure!(balance > BalanceOf::<T>::zero(), Error::<T>::InvalidLiquidityWithdrawal);
+
+			// let's check if the pool has enough liquidity
+			ensure!(pool.reserve_balance >= balance, Error::<T>::NotEnoughLiquiditySupply);
+
+			// let's check if the user is actually eligible to withdraw!
 			ensure!(
 				eligible_lp_tokens >= balance,
 				Error::<T>::NotEnoughEligibleLiquidityToWithdraw
@@ -1343,7 +2139,6 @@ pub mod pallet {
 			)?;
 
 			// burn the LP asset
-			let burnable_amount = pool.scaled_supply_balance(balance)?;
 			T::Fungibles::burn_from(
 				pool.id,
 				who,
@@ -1354,6 +2149,14 @@ pub mod pallet {
 			pool.reserve_balance =
 				pool.reserve_balance.checked_sub(&balance).ok_or(Error::<T>::OverflowError)?;
 
+			Self::deposit_event(Event::LiquidityWithdrawn {
+				who: who.clone(),
+				asset,
+				pool_id: pool.id,
+				balance,
+				scaled_balance: burnable_amount,
+				supply_index: pool.supply_index,
+			});
 			// let's update the balances of the pool now
 			LendingPoolStorage::<T>::set(&asset_pool, Some(pool));
 
@@ -1361,7 +2164,7 @@ pub mod pallet {
 		}
 
 		///
-		fn do_borrow(
+		pub(crate) fn do_borrow(
 			who: &T::AccountId,
 			asset: AssetIdOf<T>,
 			balance: AssetBalanceOf<T>,
@@ -1381,7 +2184,7 @@ pub mod pallet {
 			ensure!(pool.reserve_balance >= balance, Error::<T>::NotEnoughLiquiditySupply);
 
 			// Update pool's indexes
-			pool.update_indexes()?;
+			pool.accrue_interest()?;
 
 			let estimate_collateral_amount =
 				Self::estimate_collateral_amount(asset, balance, collateral_asset)?;
@@ -1398,6 +2201,15 @@ pub mod pallet {
 
 			// Save scaled balance as per current borrow_index
 			let scaled_balance = pool.scaled_borrow_balance(balance)?;
+			let borrow: UserBorrow<T> = UserBorrow {
+				borrowed_asset: asset,
+				borrowed_balance: scaled_balance,
+				collateral_asset,
+				collateral_balance: estimate_collateral_amount,
+				principal_balance: balance,
+			};
+			// loans whose value can not be computed are not part of the health factor
+			Self::ensure_healthy_account(who, Self::loan_position(&pool, &borrow).ok())?;
 
 			// Transfer the collateral to the pallet
 			T::Fungibles::transfer(
@@ -1407,16 +2219,10 @@ pub mod pallet {
 				estimate_collateral_amount,
 				Preservation::Preserve,
 			)?;
-			let borrow: UserBorrow<T> = UserBorrow {
-				borrowed_asset: asset,
-				borrowed_balance: scaled_balance,
-				collateral_asset,
-				collateral_balance: estimate_collateral_amount,
-				principal_balance: balance,
-			};
 
 			Borrows::<T>::try_mutate(
-				(who, asset, collateral_asset),
+				who,
+				(asset, collateral_asset),
 				|maybe_borrow| -> DispatchResult {
 					if let Some(borrow_record) = maybe_borrow {
 						// Update the existing record.
@@ -1433,6 +2239,7 @@ pub mod pallet {
 			// Update pool: transfer asset from reserved_balance to borrowed_balance
 			pool.move_asset_on_borrow(balance)?;
 
+			let (pool_id, borrow_index) = (pool.id, pool.borrow_index);
 			LendingPoolStorage::<T>::set(&asset_pool, Some(pool));
 
 			// Transfer the asset to the user
@@ -1446,52 +2253,94 @@ pub mod pallet {
 
 			Self::deposit_event(Event::Borrowed {
 				who: who.clone(),
+				pool_id,
 				borrowed_asset_id: asset,
 				borrowed_balance: balance,
+				scaled_balance,
+				borrow_index,
 				collateral_asset_id: collateral_asset,
 				collateral_balance: estimate_collateral_amount,
 			});
 			Ok(())
 		}
 
-		fn do_repay(
+		pub(crate) fn do_repay(
 			who: &T::AccountId,
 			asset: AssetIdOf<T>,
 			balance: AssetBalanceOf<T>,
 			collateral_asset: AssetIdOf<T>,
 		) -> DispatchResult {
 			ensure!(balance > BalanceOf::<T>::zero(), Error::<T>::InvalidLiquidityWithdrawal);
+			Self::repay_loan(who, who, asset, Some(balance), collateral_asset)
+		}
 
+		/// Repays `balance` of the loan of `borrower` from the account of `payer`. The released
+		/// collateral goes back to `borrower`.
+		pub(crate) fn do_repay_on_behalf(
+			payer: &T::AccountId,
+			borrower: &T::AccountId,
+			asset: AssetIdOf<T>,
+			balance: AssetBalanceOf<T>,
+			collateral_asset: AssetIdOf<T>,
+		) -> DispatchResult {
+			ensure!(balance > BalanceOf::<T>::zero(), Error::<T>::InvalidLiquidityWithdrawal);
+			Self::repay_loan(payer, borrower, asset, Some(balance), collateral_asset)
+		}
+
+		/// Repays the whole debt of the loan, including the interest accrued up to now, which
+		/// closes the loan and releases all its collateral.
+		pub(crate) fn do_repay_all(
+			who: &T::AccountId,
+			asset: AssetIdOf<T>,
+			collateral_asset: AssetIdOf<T>,
+		) -> DispatchResult {
+			Self::repay_loan(who, who, asset, None, collateral_asset)
+		}
+
+		/// Repays `balance` of the loan of `borrower`, capped at its debt, or the whole debt if
+		/// `None`. `payer` pays the debt and `borrower` receives the released collateral.
+		fn repay_loan(
+			payer: &T::AccountId,
+			borrower: &T::AccountId,
+			asset: AssetIdOf<T>,
+			balance: Option<AssetBalanceOf<T>>,
+			collateral_asset: AssetIdOf<T>,
+		) -> DispatchResult {
 			// Retrieve the lending pool and update the indexes
 			let asset_pool = AssetPool::<T>::from(asset);
 			let mut pool = LendingPoolStorage::<T>::get(&asset_pool)
 				.ok_or_else(|| DispatchError::from(Error::<T>::LendingPoolDoesNotExist))?;
-			pool.update_indexes()?;
+			pool.accrue_interest()?;
 
 			// Retrieve the loan and calculate the repayable amount
-			let mut loan = Borrows::<T>::get((who, asset, collateral_asset))
+			let mut loan = Borrows::<T>::get(borrower, (asset, collateral_asset))
 				.ok_or(Error::<T>::LoanDoesNotExists)?;
 			let repayable_balance = pool.repayable_amount(&loan)?;
 
 			// Determine the payment amount and whether it's a full payment
-			let (pay, is_full_payment) = if balance < repayable_balance {
-				(balance, false)
-			} else {
-				(repayable_balance, true)
+			let (pay, is_full_payment) = match balance {
+				Some(balance) if balance < repayable_balance => (balance, false),
+				_ => (repayable_balance, true),
 			};
 
 			// transfer repay amount to the market
 			T::Fungibles::transfer(
 				asset.clone(),
-				who,
+				payer,
 				&Self::account_id(),
 				pay,
 				Preservation::Expendable,
 			)?;
 
+			// the scaled debt the payment settles
+			let scaled_pay = if is_full_payment {
+				loan.borrowed_balance
+			} else {
+				pool.scaled_repaid_balance(pay)?
+			};
 			let (release_collateral_amount, borrowed_balance_reduction) = if is_full_payment {
 				// clear the borrow
-				Borrows::<T>::remove((who, asset, collateral_asset));
+				Borrows::<T>::remove(borrower, (asset, collateral_asset));
 				// release all the collateral
 				(loan.collateral_balance, loan.principal_balance)
 			} else {
@@ -1506,14 +2355,13 @@ pub mod pallet {
 				// repay_ratio * principal_balance
 				let borrowed_balance_reduction: AssetBalanceOf<T> =
 					Self::get_release_amount(repay_ratio, loan.principal_balance)?;
-				let scaled_pay = pool.scaled_borrow_balance(pay)?;
 				// repay partially
 				loan.repay_partial(
 					scaled_pay,
 					release_collateral_amount,
 					borrowed_balance_reduction,
 				)?;
-				Borrows::<T>::set((who, asset, collateral_asset), Some(loan));
+				Borrows::<T>::set(borrower, (asset, collateral_asset), Some(loan));
 				(release_collateral_amount, borrowed_balance_reduction)
 			};
 
@@ -1521,27 +2369,208 @@ pub mod pallet {
 			T::Fungibles::transfer(
 				collateral_asset.clone(),
 				&Self::account_id(),
-				who,
+				borrower,
 				release_collateral_amount,
 				Preservation::Expendable,
 			)?;
 
 			// Update pool: transfer asset from reserved_balance to borrowed_balance
 			pool.move_asset_on_repay(pay, borrowed_balance_reduction)?;
+			let (pool_id, borrow_index) = (pool.id, pool.borrow_index);
 			// Update the storage with the new pool state
 			LendingPoolStorage::<T>::insert(&asset_pool, pool);
 
 			// Emit event with the  actual repay amount
-			Self::deposit_event(Event::Repaid {
-				who: who.clone(),
-				repaid_asset_id: asset,
+			if payer == borrower {
+				Self::deposit_event(Event::Repaid {
+					who: borrower.clone(),
+					pool_id,
+					repaid_asset_id: asset,
+					repaid_balance: pay,
+					scaled_balance: scaled_pay,
+					borrow_index,
+					collateral_asset_id: collateral_asset,
+					collateral_balance: release_collateral_amount,
+				});
+			} else {
+				Self::deposit_event(Event::RepaidOnBehalf {
+					payer: payer.clone(),
+					borrower: borrower.clone(),
+					pool_id,
+					repaid_asset_id: asset,
+					repaid_balance: pay,
+					scaled_balance: scaled_pay,
+					borrow_index,
+					collateral_asset_id: collateral_asset,
+					collateral_balance: release_collateral_amount,
+				});
+			}
+			Ok(())
+		}
+
+		/// Liquidates a loan of a borrower whose weighted health factor is below one. `liquidator`
+		/// repays
+		/// `CloseFactor` of the debt, including interest, and seizes its value in collateral plus
+		/// the `LiquidationBonus`, see `liquidation_amounts`. Once the whole debt is repaid the
+		/// loan is closed and the rest of its collateral released to the borrower. Once all the
+		/// collateral is seized the loan is closed too, and the rest of its debt is `BadDebt`.
+		pub fn do_liquidate(
+			liquidator: &T::AccountId,
+			borrower: &T::AccountId,
+			asset: AssetIdOf<T>,
+			collateral_asset: AssetIdOf<T>,
+		) -> DispatchResult {
+			let asset_pool = AssetPool::<T>::from(asset);
+			let mut pool = LendingPoolStorage::<T>::get(&asset_pool)
+				.ok_or_else(|| DispatchError::from(Error::<T>::LendingPoolDoesNotExist))?;
+			pool.accrue_interest()?;
+
+			let mut loan = Borrows::<T>::get(borrower, (asset, collateral_asset))
+				.ok_or(Error::<T>::LoanDoesNotExists)?;
+			ensure!(
+				Self::loan_is_liquidatable(borrower, &pool, &loan)?,
+				Error::<T>::PositionNotLiquidatable
+			);
+			let repayable_balance = pool.repayable_amount(&loan)?;
+			let (pay, seized_collateral) = Self::liquidation_amounts(&pool, &loan)?;
+
+			// the liquidator repays part of the debt to the market
+			T::Fungibles::transfer(
+				asset.clone(),
+				liquidator,
+				&Self::account_id(),
+				pay,
+				Preservation::Expendable,
+			)?;
+
+			// and seizes its value in collateral, plus the bonus
+			T::Fungibles::transfer(
+				collateral_asset.clone(),
+				&Self::account_id(),
+				liquidator,
+				seized_collateral,
+				Preservation::Expendable,
+			)?;
+
+			let mut bad_debt = None;
+			let borrowed_balance_reduction = if pay == repayable_balance {
+				// the debt is repaid, the rest of the collateral goes back to the borrower
+				Borrows::<T>::remove(borrower, (asset, collateral_asset));
+				T::Fungibles::transfer(
+					collateral_asset.clone(),
+					&Self::account_id(),
+					borrower,
+					loan.collateral_balance.saturating_sub(seized_collateral),
+					Preservation::Expendable,
+				)?;
+				loan.principal_balance
+			} else if seized_collateral == loan.collateral_balance {
+				// the collateral is gone but not the debt, which no later liquidation could
+				// repay: the loan is closed and the rest of its debt recorded as bad debt
+				Borrows::<T>::remove(borrower, (asset, collateral_asset));
+				let shortfall = repayable_balance.saturating_sub(pay);
+				BadDebt::<T>::mutate(asset, |debt| *debt = debt.saturating_add(shortfall));
+				bad_debt = Some(shortfall);
+				loan.principal_balance
+			} else {
+				let repay_ratio = Self::get_ratio(pay, repayable_balance)?;
+				let borrowed_balance_reduction =
+					Self::get_release_amount(repay_ratio, loan.principal_balance)?;
+				loan.repay_partial(
+					pool.scaled_repaid_balance(pay)?,
+					seized_collateral,
+					borrowed_balance_reduction,
+				)?;
+				Borrows::<T>::insert(borrower, (asset, collateral_asset), loan);
+				borrowed_balance_reduction
+			};
+
+			pool.move_asset_on_repay(pay, borrowed_balance_reduction)?;
+			LendingPoolStorage::<T>::insert(&asset_pool, pool);
+
+			Self::deposit_event(Event::Liquidated {
+				liquidator: liquidator.clone(),
+				borrower: borrower.clone(),
+				borrowed_asset_id: asset,
 				repaid_balance: pay,
 				collateral_asset_id: collateral_asset,
-				collateral_balance: release_collateral_amount,
+				collateral_balance: seized_collateral,
 			});
+			if let Some(bad_debt) = bad_debt {
+				Self::deposit_event(Event::BadDebtRecorded {
+					borrower: borrower.clone(),
+					borrowed_asset_id: asset,
+					collateral_asset_id: collateral_asset,
+					bad_debt,
+				});
+			}
 			Ok(())
 		}
 
+		/// The debt a liquidator repays, and the collateral they seize, in a single liquidation of
+		/// `loan`: `CloseFactor` of its debt, and the value of that debt in the collateral asset
+		/// plus the `LiquidationBonus`. When that is more than all the collateral of the loan,
+		/// they seize all of it and repay the debt it is worth without the bonus. `pool` must
+		/// have up to date indexes.
+		pub(crate) fn liquidation_amounts(
+			pool: &LendingPool<T>,
+			loan: &UserBorrow<T>,
+		) -> Result<(AssetBalanceOf<T>, AssetBalanceOf<T>), Error<T>> {
+			let pay = T::CloseFactor::get().mul_ceil(pool.repayable_amount(loan)?);
+			let pay_in_collateral =
+				Self::get_equivalent_asset_amount(loan.collateral_asset, loan.borrowed_asset, pay)?;
+			let seized_collateral = pay_in_collateral
+				.saturating_add(T::LiquidationBonus::get().mul_floor(pay_in_collateral));
+			if seized_collateral <= loan.collateral_balance {
+				return Ok((pay, seized_collateral))
+			}
+
+			// repay the debt covered by the collateral: its value over `1 + LiquidationBonus`
+			let collateral_value = Self::get_equivalent_asset_amount(
+				loan.borrowed_asset,
+				loan.collateral_asset,
+				loan.collateral_balance,
+			)?;
+			let bonus_factor = Rate::one().saturating_add(T::LiquidationBonus::get().into());
+			let covered_pay = FixedU128::from_inner(collateral_value.saturated_into())
+				.checked_div(&bonus_factor)
+				.ok_or(Error::<T>::DivisionByZero)?
+				.into_inner()
+				.saturated_into();
+			Ok((pay.min(covered_pay), loan.collateral_balance))
+		}
+
+		/// Whether the loan `(borrower, asset, collateral_asset)` can be liquidated, see
+		/// `loan_is_liquidatable`
+		pub fn is_liquidatable(
+			borrower: &T::AccountId,
+			asset: AssetIdOf<T>,
+			collateral_asset: AssetIdOf<T>,
+		) -> Result<bool, Error<T>> {
+			let mut pool = LendingPoolStorage::<T>::get(AssetPool::<T>::from(asset))
+				.ok_or(Error::<T>::LendingPoolDoesNotExist)?;
+			pool.update_indexes()?;
+			let loan = Borrows::<T>::get(borrower, (asset, collateral_asset))
+				.ok_or(Error::<T>::LoanDoesNotExists)?;
+			Self::loan_is_liquidatable(borrower, &pool, &loan)
+		}
+
+		/// A loan with debt is liquidatable when its borrower is: the `weighted_health_factor` of
+		/// all the loans of `borrower`, the health factor borrows are checked against, is below
+		/// one. Loans whose value can not be computed are not part of it. `pool` must have up to
+		/// date indexes.
+		pub(crate) fn loan_is_liquidatable(
+			borrower: &T::AccountId,
+			pool: &LendingPool<T>,
+			loan: &UserBorrow<T>,
+		) -> Result<bool, Error<T>> {
+			if pool.repayable_amount(loan)?.is_zero() {
+				return Ok(false);
+			}
+			Ok(Self::account_health_factor(borrower)
+				.map_or(false, |health| health < FixedU128::one()))
+		}
+
 		/// This method de-activates an existing lending pool
 		pub fn do_deactivate_lending_pool(asset: AssetIdOf<T>) -> DispatchResult {
 			// let's check if our pool does exist before de-activating it
@@ -1599,12 +2628,63 @@ pub mod pallet {
 			T::Fungibles::mint_into(lp_id, who, scaled_balance)?;
 			Self::deposit_event(Event::LPTokenMinted {
 				who: who.clone(),
-				asset: lp_id,
+				asset,
+				pool_id: lp_id,
 				balance: total_new_mint,
+				supply_index: current_supply_index,
 			});
 			Ok(())
 		}
 
+		/// Accrues interest on the lending pools in storage order, starting after the pool
+		/// accrued last, until `remaining_weight` is used up or every pool has been visited once.
+		/// Returns the consumed weight.
+		pub fn accrue_interest_on_idle(remaining_weight: Weight) -> Weight {
+			// reading and writing the cursor
+			let mut consumed = T::DbWeight::get().reads_writes(1, 1);
+			let per_pool = T::WeightInfo::accrue_interest();
+			if !consumed.saturating_add(per_pool).all_lte(remaining_weight) {
+				return Weight::zero();
+			}
+
+			let last = LastIdleAccruedPool::<T>::get();
+			let after_last = match last {
+				Some(asset) => LendingPoolStorage::<T>::iter_from(
+					LendingPoolStorage::<T>::hashed_key_for(AssetPool::<T>::from(asset)),
+				),
+				None => LendingPoolStorage::<T>::iter(),
+			};
+			// wrap around to the first pool once the end of the map is reached
+			let mut pools = after_last.chain(LendingPoolStorage::<T>::iter());
+
+			let mut first_accrued = None;
+			while consumed.saturating_add(per_pool).all_lte(remaining_weight) {
+				let (asset_pool, mut pool) = match pools.next() {
+					Some(entry) => entry,
+					None => break,
+				};
+				if first_accrued.as_ref() == Some(&asset_pool.asset) {
+					break;
+				}
+				consumed = consumed.saturating_add(per_pool);
+
+				if pool.last_accrued_interest_at < Self::now_in_seconds() {
+					match pool.accrue_interest() {
+						Ok(()) => LendingPoolStorage::<T>::insert(&asset_pool, pool),
+						Err(e) => log::warn!(
+							target: "runtime::lending",
+							"Failed to accrue interest on the lending pool of {:?}: {:?}",
+							asset_pool.asset,
+							e
+						),
+					}
+				}
+				LastIdleAccruedPool::<T>::put(&asset_pool.asset);
+				first_accrued.get_or_insert(asset_pool.asset);
+			}
+			consumed
+		}
+
 		/// Returns the block's timestamp in seconds as u64
 		fn now_in_seconds() -> u64 {
 			core::time::Duration::from_millis(T::Time::now().saturated_into::<u64>())
@@ -1612,84 +2692,161 @@ pub mod pallet {
 				.saturated_into::<u64>()
 		}
 
+		/// Returns the current loan to value of `account` over all its loans, with its collateral
+		/// factor and liquidation threshold as weighted by `weighted_thresholds`. Only the loans
+		/// count: the supplied liquidity is never collateral, so withdrawals can't change it nor
+		/// the health factor, and borrowing is the only action checked against it.
 		pub fn compute_user_ltv(account: &T::AccountId) -> (FixedU128, FixedU128, FixedU128) {
+			let positions = Self::get_loan_positions(account);
 			let mut total_borrowed_usdt: u128 = 0;
 			let mut total_collateral_usdt: u128 = 0;
-			let mut min_collateral_factor = Permill::from_percent(100);
-			let mut min_liquidation_threshold = Permill::from_percent(100);
+			for position in &positions {
+				total_borrowed_usdt = total_borrowed_usdt.saturating_add(position.debt_usdt);
+				total_collateral_usdt =
+					total_collateral_usdt.saturating_add(position.collateral_usdt);
+			}
 
-			// Iterate over all borrows for the account
-			for ((borrower, borrowed_asset, collateral_asset), loan) in Borrows::<T>::iter() {
-				if borrower != *account {
-					continue;
-				}
-				// Get the lending pool for the borrowed asset
-				let asset_pool = AssetPool::<T>::from(borrowed_asset);
-				let mut pool = match LendingPoolStorage::<T>::get(&asset_pool) {
-					Some(p) => p,
-					None => continue, // Skip if no pool found
-				};
+			// Calculate current LTV
+			let current_ltv = if !total_collateral_usdt.is_zero() {
+				FixedU128::checked_from_rational(total_borrowed_usdt, total_collateral_usdt)
+					.unwrap_or_else(|| FixedU128::zero())
+			} else {
+				FixedU128::zero()
+			};
+			let (sale_ltv, liquidation_ltv) = Self::weighted_thresholds(&positions);
 
-				// Update pool indexes
-				if pool.update_indexes().is_err() {
-					continue;
-				}
+			(current_ltv, sale_ltv, liquidation_ltv)
+		}
 
-				// Compute the repayable amount (current borrowed balance with interest)
-				let repayable_amount = match pool.repayable_amount(&loan) {
-					Ok(amount) => amount,
-					Err(_) => continue,
-				};
+		/// Returns the collateral factor and the liquidation threshold of the loans `positions`
+		/// of an account: the factors of the pools of the loans, weighted by the value of the
+		/// collateral of each loan. Both are one without any collateral.
+		pub fn weighted_thresholds(positions: &[LoanPosition]) -> (FixedU128, FixedU128) {
+			let total_collateral_usdt = positions
+				.iter()
+				.fold(0u128, |total, position| total.saturating_add(position.collateral_usdt));
+			if total_collateral_usdt.is_zero() {
+				return (FixedU128::one(), FixedU128::one());
+			}
 
-				// Convert repayable_amount to USDT equivalent
-				let borrowed_usdt = match Self::get_equivalent_asset_amount(
-					1u32.into(), // Assuming 1 is the asset ID for USDT
-					borrowed_asset,
-					repayable_amount,
-				) {
-					Ok(amount) => amount,
-					Err(_) => continue,
-				};
+			let weighted = |factor: fn(&LoanPosition) -> FixedU128| {
+				positions.iter().fold(FixedU128::zero(), |sum, position| {
+					FixedU128::checked_from_rational(
+						position.collateral_usdt,
+						total_collateral_usdt,
+					)
+					.and_then(|weight| factor(position).checked_mul(&weight))
+					.and_then(|weighted| sum.checked_add(&weighted))
+					.unwrap_or(sum)
+				})
+			};
+			(weighted(|position| position.sale_ltv), weighted(|position| position.liquidation_ltv))
+		}
 
-				total_borrowed_usdt =
-					total_borrowed_usdt.saturating_add(borrowed_usdt.saturated_into::<u128>());
+		/// Ensures a borrow of `account` that adds `new_loan` to its loans does not leave it
+		/// liquidatable, with a `weighted_health_factor` below one, unless the borrow does not
+		/// lower its health factor. Withdrawals are not checked: the supplied liquidity is not
+		/// collateral, the collateral of a loan is locked in the loan until it is repaid.
+		pub(crate) fn ensure_healthy_account(
+			account: &T::AccountId,
+			new_loan: Option<LoanPosition>,
+		) -> Result<(), Error<T>> {
+			let mut positions = Self::get_loan_positions(account);
+			let health_before = Self::weighted_health_factor(&positions);
+			positions.extend(new_loan);
+			match Self::weighted_health_factor(&positions) {
+				Some(health)
+					if health < FixedU128::one() &&
+						health_before.map_or(true, |before| health < before) =>
+					Err(Error::<T>::HealthFactorTooLow),
+				_ => Ok(()),
+			}
+		}
 
-				// Convert collateral to USDT equivalent
-				let collateral_usdt = match Self::get_equivalent_asset_amount(
-					1u32.into(), // Assuming 1 is the asset ID for USDT
-					collateral_asset,
-					loan.collateral_balance,
-				) {
-					Ok(amount) => amount,
-					Err(_) => continue,
-				};
+		/// Returns the `weighted_health_factor` of the loans of `account`, valued at now
+		pub fn account_health_factor(account: &T::AccountId) -> Option<FixedU128> {
+			Self::weighted_health_factor(&Self::get_loan_positions(account))
+		}
 
-				total_collateral_usdt =
-					total_collateral_usdt.saturating_add(collateral_usdt.saturated_into::<u128>());
+		/// Returns the health factor of the loans `positions` of an account: the value of the
+		/// collateral of each loan weighted by the liquidation threshold of its pool, over the
+		/// value of the debt. Every loan of the account can be liquidated below one. `None`
+		/// without any debt.
+		pub fn weighted_health_factor(positions: &[LoanPosition]) -> Option<FixedU128> {
+			let mut total_debt_usdt: u128 = 0;
+			let mut liquidation_collateral_usdt: u128 = 0;
+			for position in positions {
+				total_debt_usdt = total_debt_usdt.saturating_add(position.debt_usdt);
+				liquidation_collateral_usdt = liquidation_collateral_usdt.saturating_add(
+					position.liquidation_ltv.saturating_mul_int(position.collateral_usdt),
+				);
+			}
+			if total_debt_usdt.is_zero() {
+				return None;
+			}
+			FixedU128::checked_from_rational(liquidation_collateral_usdt, total_debt_usdt)
+		}
 
-				// Update minimum collateral factors
-				if pool.collateral_factor < min_collateral_factor {
-					min_collateral_factor = pool.collateral_factor;
-				}
+		/// Returns the loans of `account`, valued in USDT. Loans whose value can not be computed,
+		/// e.g. because a price is missing, are skipped.
+		pub fn get_loan_positions(account: &T::AccountId) -> Vec<LoanPosition> {
+			Borrows::<T>::iter_prefix(account)
+				.filter_map(|(_, loan)| {
+					let mut pool =
+						LendingPoolStorage::<T>::get(AssetPool::<T>::from(loan.borrowed_asset))?;
+					pool.update_indexes().ok()?;
+					Self::loan_position(&pool, &loan).ok()
+				})
+				.collect()
+		}
 
-				if pool.liquidation_threshold < min_liquidation_threshold {
-					min_liquidation_threshold = pool.liquidation_threshold;
-				}
-			}
+		/// Values `loan` in USDT (asset 1). `pool` must have up to date indexes.
+		pub(crate) fn loan_position(
+			pool: &LendingPool<T>,
+			loan: &UserBorrow<T>,
+		) -> Result<LoanPosition, Error<T>> {
+			// the current borrowed balance, with interest
+			let debt = pool.repayable_amount(loan)?;
+			let debt_usdt =
+				Self::get_equivalent_asset_amount(1u32.into(), loan.borrowed_asset, debt)?;
+			let collateral_usdt = Self::get_equivalent_asset_amount(
+				1u32.into(),
+				loan.collateral_asset,
+				loan.collateral_balance,
+			)?;
 
-			// Calculate current LTV
-			let current_ltv = if !total_collateral_usdt.is_zero() {
-				FixedU128::checked_from_rational(total_borrowed_usdt, total_collateral_usdt)
-					.unwrap_or_else(|| FixedU128::zero())
-			} else {
+			let ltv = if collateral_usdt.is_zero() {
 				FixedU128::zero()
+			} else {
+				Self::get_ratio(debt_usdt, collateral_usdt)?
+			};
+			let liquidation_ltv: FixedU128 = pool.liquidation_threshold.into();
+			let health_factor = if collateral_usdt.is_zero() && !debt_usdt.is_zero() {
+				Some(FixedU128::zero())
+			} else {
+				Self::health_factor(ltv, liquidation_ltv)
+			};
+			// the loan is liquidatable once `price * collateral * liquidation_ltv < debt_usdt`
+			let liquidation_price = if loan.collateral_balance.is_zero() {
+				None
+			} else {
+				FixedU128::checked_from_rational(debt_usdt, loan.collateral_balance)
+					.and_then(|price| price.checked_div(&liquidation_ltv))
 			};
 
-			// Convert ratios to FixedU128
-			let sale_ltv: FixedU128 = min_collateral_factor.into();
-			let liquidation_ltv: FixedU128 = min_liquidation_threshold.into();
-
-			(current_ltv, sale_ltv, liquidation_ltv)
+			Ok(LoanPosition {
+				borrowed_asset: loan.borrowed_asset,
+				collateral_asset: loan.collateral_asset,
+				debt: debt.saturated_into(),
+				debt_usdt: debt_usdt.saturated_into(),
+				collateral: loan.collateral_balance.saturated_into(),
+				collateral_usdt: collateral_usdt.saturated_into(),
+				ltv,
+				sale_ltv: pool.collateral_factor.into(),
+				liquidation_ltv,
+				health_factor,
+				liquidation_price,
+			})
 		}
 
 		/// Returns the amount of asset equivalent to the collateral
@@ -1876,39 +3033,70 @@ pub mod pallet {
 
 		/// Retrieves lending pools and their aggregated totals.
 		///
-		/// This function collects all the lending pools matching the given `asset` filter and
+		/// This function collects a page of the lending pools matching the given filters and
 		/// aggregates their total supply and borrow amounts.
 		///
+		/// Pools are paged in storage order: pass the `asset_id` of the last pool of a page as
+		/// `start_key` to get the next one.
+		///
 		/// # Arguments
 		///
 		/// * `asset` - An optional filter for a specific `AssetId`. If `None`, includes all pools.
 		/// * `account` - An optional reference to the account for which to fetch the balance.
+		/// * `start_key` - Only include the pools after the pool of this asset.
+		/// * `limit` - The maximum number of pools to include. If `None`, includes all pools.
+		/// * `active_only` - Whether to skip the pools that are not activated.
 		///
 		/// # Returns
 		///
 		/// A tuple of:
 		/// * `Vec<LendingPoolInfo>` - A vector containing details of each lending pool.
-		/// * `AggregatedTotals` - A struct containing the total supply and borrow amounts.
+		/// * `AggregatedTotals` - A struct containing the total supply and borrow amounts of the
+		///   returned pools.
 		pub fn get_lending_pools(
 			asset: Option<AssetIdOf<T>>,
 			account: Option<&T::AccountId>,
+			start_key: Option<AssetIdOf<T>>,
+			limit: Option<u32>,
+			active_only: bool,
 		) -> (Vec<LendingPoolInfo>, AggregatedTotals) {
 			let mut total_supply: u128 = 0;
 			let mut total_borrow: u128 = 0;
 
-			// Collect all lending pools and calculate aggregated totals in a single iteration
-			let pools: Vec<LendingPoolInfo> = LendingPoolStorage::<T>::iter()
-				.filter(|(_, pool)| {
-					match asset {
-						Some(asset_id) => pool.lend_token_id == asset_id,
-						None => true, // Include all pools if `asset` is `None`
-					}
-				})
-				.map(|(_, pool)| {
+			let is_selected = |pool: &LendingPool<T>| !active_only || pool.activated;
+			let selected: Vec<LendingPool<T>> = match asset {
+				// A single pool is looked up directly
+				Some(asset) => LendingPoolStorage::<T>::get(AssetPool::<T>::from(asset))
+					.into_iter()
+					.filter(is_selected)
+					.collect(),
+				None => {
+					let start_raw_key = match start_key {
+						Some(start_key) =>
+							LendingPoolStorage::<T>::hashed_key_for(AssetPool::<T>::from(start_key)),
+						None => LendingPoolStorage::<T>::final_prefix().to_vec(),
+					};
+					LendingPoolStorage::<T>::iter_from(start_raw_key)
+						.map(|(_, pool)| pool)
+						.filter(is_selected)
+						.take(limit.map_or(usize::MAX, |limit| limit as usize))
+						.collect()
+				},
+			};
+
+			// Calculate aggregated totals in the same iteration
+			let pools: Vec<LendingPoolInfo> = selected
+				.into_iter()
+				.map(|mut pool| {
+					// Accrue interest so that the exchange rate is up to date
+					let _ = pool.update_indexes();
+
 					// Retrieve metadata for the pool's asset
 					let (asset_name, asset_decimals, asset_symbol) =
 						Self::get_metadata(pool.lend_token_id);
-					let asset_icon = "<url>/dot.svg".as_bytes().to_vec(); // Placeholder for asset icon
+					let (asset_icon, asset_display_name, asset_coingecko_id) =
+						Self::get_display_metadata(pool.lend_token_id);
+					let (supply_apy_s, borrow_apy_s) = Self::reward_apys(&pool);
 
 					let (user_supplied_balance, user_asset_balance) = match account {
 						Some(account) => (
@@ -1942,12 +3130,17 @@ pub mod pallet {
 						asset_decimals,
 						asset_symbol,
 						asset_icon,
+						asset_display_name,
+						asset_coingecko_id,
 						collateral_q: pool.collateral_factor.deconstruct().into(),
 						utilization: pool.utilisation_ratio().unwrap_or_default().into(),
-						borrow_apy: pool.borrow_interest_rate().unwrap_or_default().into(),
-						borrow_apy_s: FixedU128::zero(), // Placeholder value
-						supply_apy: pool.supply_interest_rate().unwrap_or_default().into(),
-						supply_apy_s: FixedU128::zero(), // Placeholder value
+						borrow_apr: pool.borrow_interest_rate().unwrap_or_default(),
+						borrow_apy: pool.borrow_apy().unwrap_or_default(),
+						borrow_apy_s,
+						supply_apr: pool.supply_interest_rate().unwrap_or_default(),
+						supply_apy: pool.supply_apy().unwrap_or_default(),
+						supply_apy_s,
+						exchange_rate: pool.exchange_rate(),
 						is_activated: pool.activated,
 						user_supplied_balance,
 						user_asset_balance,
@@ -2002,7 +3195,8 @@ pub mod pallet {
 					// Retrieve metadata for the pool's asset
 					let (asset_name, asset_decimals, asset_symbol) =
 						Self::get_metadata(pool.lend_token_id);
-					let asset_icon = "<url>/dot.svg".as_bytes().to_vec(); // Placeholder for asset icon
+					let (asset_icon, asset_display_name, asset_coingecko_id) =
+						Self::get_display_metadata(pool.lend_token_id);
 
 					// Calculate the equivalents in USDT
 					let equivalent_balance =
@@ -2010,8 +3204,9 @@ pub mod pallet {
 					let equivalent_supplied_amount =
 						Self::get_usdt_equivalent_value(pool.lend_token_id, supplied_amount);
 
-					// Calculate the current APY for this pool
-					let apy = pool.supply_interest_rate().unwrap_or_default();
+					// Calculate the current APR and APY for this pool
+					let apr = pool.supply_interest_rate().unwrap_or_default();
+					let apy = pool.supply_apy().unwrap_or_default();
 
 					// Accumulate total supply
 					total_supply = total_supply
@@ -2025,9 +3220,12 @@ pub mod pallet {
 							asset_symbol,
 							decimals: asset_decimals,
 							asset_icon,
+							asset_display_name,
+							asset_coingecko_id,
 							balance: asset_balance.saturated_into::<u128>(),
 							usdt_balance: equivalent_balance.saturated_into::<u128>(),
 						},
+						apr,
 						apy,
 						supplied: supplied_amount.saturated_into::<u128>(),
 					})
@@ -2063,13 +3261,8 @@ pub mod pallet {
 			let mut borrowed_assets: Vec<BorrowedAsset> = Vec::new();
 			let mut collateral_assets: Vec<CollateralAsset> = Vec::new();
 
-			// Iterate over all borrows to collect information for the given account
-			for ((borrower, borrowed_asset, collateral_asset), loan) in Borrows::<T>::iter() {
-				// Process only entries for the given account
-				if borrower != *account {
-					continue;
-				}
-
+			// Iterate over the borrows of the given account
+			for ((borrowed_asset, collateral_asset), loan) in Borrows::<T>::iter_prefix(account) {
 				// Get the lending pool for the borrowed asset
 				let asset_pool = AssetPool::<T>::from(borrowed_asset);
 				let mut pool = match LendingPoolStorage::<T>::get(&asset_pool) {
@@ -2095,7 +3288,8 @@ pub mod pallet {
 				// Retrieve asset metadata for the borrowed asset
 				let (borrow_asset_name, borrow_asset_decimals, borrow_asset_symbol) =
 					Self::get_metadata(borrowed_asset);
-				let borrow_asset_icon = "<url>/dot.svg".as_bytes().to_vec(); // Placeholder for asset icon
+				let (borrow_asset_icon, borrow_asset_display_name, borrow_asset_coingecko_id) =
+					Self::get_display_metadata(borrowed_asset);
 
 				// Calculate equivalent borrowed amount in USDT
 				let equivalent_borrowed_amount =
@@ -2115,10 +3309,13 @@ pub mod pallet {
 						asset_name: borrow_asset_name,
 						decimals: borrow_asset_decimals,
 						asset_icon: borrow_asset_icon,
+						asset_display_name: borrow_asset_display_name,
+						asset_coingecko_id: borrow_asset_coingecko_id,
 						balance: borrow_balance.saturated_into::<u128>(),
 						usdt_balance: equivalent_balance.saturated_into::<u128>(),
 					},
-					apy: pool.borrow_interest_rate().unwrap_or_default(),
+					apr: pool.borrow_interest_rate().unwrap_or_default(),
+					apy: pool.borrow_apy().unwrap_or_default(),
 					borrowed: borrowed_amount.saturated_into::<u128>(),
 				});
 
@@ -2128,7 +3325,11 @@ pub mod pallet {
 				// Retrieve asset metadata for the collateral asset
 				let (collateral_asset_name, collateral_asset_decimals, collateral_asset_symbol) =
 					Self::get_metadata(collateral_asset);
-				let collateral_asset_icon = "<url>/dot.svg".as_bytes().to_vec(); // Placeholder for asset icon
+				let (
+					collateral_asset_icon,
+					collateral_asset_display_name,
+					collateral_asset_coingecko_id,
+				) = Self::get_display_metadata(collateral_asset);
 
 				// Calculate equivalent collateral amount in USDT
 				let equivalent_collateral_amount =
@@ -2146,6 +3347,8 @@ pub mod pallet {
 						asset_name: collateral_asset_name,
 						decimals: collateral_asset_decimals,
 						asset_icon: collateral_asset_icon,
+						asset_display_name: collateral_asset_display_name,
+						asset_coingecko_id: collateral_asset_coingecko_id,
 						balance: collateral_balance.saturated_into::<u128>(),
 						usdt_balance: equivalent_collateral_amount.saturated_into::<u128>(),
 					},
@@ -2209,5 +3412,172 @@ pub mod pallet {
 
 			Ok(equivalent_collateral_amount)
 		}
+
+		/// Calculates the maximum amount of `borrow_asset` that `account` can borrow against
+		/// `collateral_asset`, the reverse of `estimate_collateral_amount`.
+		///
+		/// The amount is limited by the liquidity of the pool (`reserve_balance`) and by the
+		/// collateral the account can lock, as `borrow` requires, through the collateral factor of
+		/// the pool. The collateral of the outstanding loans of the account is locked already, so
+		/// their debt does not reduce it.
+		///
+		/// # Returns
+		///
+		/// * `Result<AssetBalanceOf<T>, Error<T>>` - The largest amount `borrow` accepts, zero if
+		///   the pool is not active.
+		///
+		/// # Errors
+		///
+		/// Returns an error if the lending pool does not exist or if a price is not set.
+		pub fn max_borrowable(
+			account: &T::AccountId,
+			borrow_asset: AssetIdOf<T>,
+			collateral_asset: AssetIdOf<T>,
+		) -> Result<AssetBalanceOf<T>, Error<T>> {
+			let pool = LendingPoolStorage::<T>::get(AssetPool::<T>::from(borrow_asset))
+				.ok_or(Error::<T>::LendingPoolDoesNotExist)?;
+			if !pool.is_active() {
+				return Ok(Zero::zero());
+			}
+
+			let free_collateral = T::Fungibles::reducible_balance(
+				collateral_asset,
+				account,
+				Preservation::Preserve,
+				Fortitude::Polite,
+			);
+			// borrow_amount = collateral_amount * collateral_factor, in the borrowed asset
+			let collateral_value =
+				Self::get_equivalent_asset_amount(borrow_asset, collateral_asset, free_collateral)?;
+			let factor: Rate = pool.collateral_factor.into();
+			let estimate: AssetBalanceOf<T> = factor
+				.checked_mul(&FixedU128::from_inner(collateral_value.saturated_into()))
+				.ok_or(Error::<T>::OverflowError)?
+				.into_inner()
+				.saturated_into();
+			let max = estimate.min(pool.reserve_balance);
+
+			let fits = |amount: AssetBalanceOf<T>| -> Result<bool, Error<T>> {
+				Ok(Self::estimate_collateral_amount(borrow_asset, amount, collateral_asset)? <=
+					free_collateral)
+			};
+			if fits(max)? {
+				return Ok(max);
+			}
+			// the conversions rounded the estimate up, search the largest amount below it that
+			// fits the collateral
+			let (mut low, mut high) = (AssetBalanceOf::<T>::zero(), max);
+			while high - low > One::one() {
+				let mid = low + (high - low) / 2u32.into();
+				if fits(mid)? {
+					low = mid;
+				} else {
+					high = mid;
+				}
+			}
+			Ok(low)
+		}
+
+		/// Calculates the maximum amount of `asset` that `account` can withdraw from its pool: the
+		/// deposit of the account, including the accrued interest, limited by the liquidity of
+		/// the pool (`reserve_balance`). Supplied liquidity is not collateral, so the debt of the
+		/// account does not reduce it.
+		///
+		/// # Errors
+		///
+		/// Returns an error if the lending pool does not exist.
+		pub fn max_withdrawable(
+			account: &T::AccountId,
+			asset: AssetIdOf<T>,
+		) -> Result<AssetBalanceOf<T>, Error<T>> {
+			let mut pool = LendingPoolStorage::<T>::get(AssetPool::<T>::from(asset))
+				.ok_or(Error::<T>::LendingPoolDoesNotExist)?;
+			pool.update_indexes()?;
+
+			let deposit = pool.accrued_deposit(T::Fungibles::balance(pool.id, account))?;
+			Ok(deposit.min(pool.reserve_balance))
+		}
+	}
+
+	#[cfg(any(feature = "try-runtime", test))]
+	impl<T: Config> Pallet<T> {
+		/// Checks the invariants of the lending pallet:
+		///
+		/// * the pallet account holds at least `reserve_balance` plus all the tracked collateral of
+		///   every asset
+		/// * the `borrowed_balance` of every pool equals the sum of the principal of its loans
+		/// * the LP token (`id`) of every pool exists
+		/// * the `supply_index` and `borrow_index` of every pool never decrease: they are at least
+		///   their initial value of one, accruing interest up to now doesn't lower them, and the
+		///   `supply_index` and `last_accrued_interest_at` of the pool are at least those recorded
+		///   in `SupplyIndexStorage` by the earlier supplies to it. Loans record no borrow index,
+		///   so the `borrow_index` is only checked against one and the accrual
+		pub fn do_try_state() -> Result<(), sp_runtime::TryRuntimeError> {
+			use frame_support::sp_std::collections::btree_map::BTreeMap;
+
+			// asset_id => (expected pallet balance, sum of the principal of loans)
+			let mut tracked: BTreeMap<AssetIdOf<T>, (u128, u128)> = BTreeMap::new();
+
+			for (_, _, loan) in Borrows::<T>::iter() {
+				let collateral = tracked.entry(loan.collateral_asset).or_default();
+				collateral.0 =
+					collateral.0.saturating_add(loan.collateral_balance.saturated_into::<u128>());
+
+				let borrowed = tracked.entry(loan.borrowed_asset).or_default();
+				borrowed.1 =
+					borrowed.1.saturating_add(loan.principal_balance.saturated_into::<u128>());
+			}
+
+			for (_, pool) in LendingPoolStorage::<T>::iter() {
+				ensure!(
+					T::Fungibles::asset_exists(pool.id),
+					"The LP token of a lending pool does not exist"
+				);
+				ensure!(
+					pool.supply_index >= Rate::one() && pool.borrow_index >= Rate::one(),
+					"The indexes of a lending pool are below one"
+				);
+				let mut accrued = pool.clone();
+				accrued
+					.accrue_indexes()
+					.map_err(|_| "The interest of a lending pool can't accrue")?;
+				ensure!(
+					accrued.supply_index >= pool.supply_index &&
+						accrued.borrow_index >= pool.borrow_index,
+					"The indexes of a lending pool decrease as interest accrues"
+				);
+
+				let (_, principal) = tracked.get(&pool.lend_token_id).copied().unwrap_or_default();
+				ensure!(
+					pool.borrowed_balance.saturated_into::<u128>() == principal,
+					"The borrowed balance of a lending pool does not match its loans"
+				);
+
+				let expected = tracked.entry(pool.lend_token_id).or_default();
+				expected.0 =
+					expected.0.saturating_add(pool.reserve_balance.saturated_into::<u128>());
+			}
+
+			for ((_, asset), supplied) in SupplyIndexStorage::<T>::iter() {
+				if let Some(pool) = LendingPoolStorage::<T>::get(AssetPool::<T>::from(asset)) {
+					ensure!(
+						pool.supply_index >= supplied.supply_index &&
+							pool.last_accrued_interest_at >= supplied.last_accrued_interest_at,
+						"The supply index of a lending pool is below the one of an earlier supply"
+					);
+				}
+			}
+
+			let account_id = Self::account_id();
+			for (asset, (expected_balance, _)) in tracked {
+				let balance = T::Fungibles::balance(asset, &account_id).saturated_into::<u128>();
+				ensure!(
+					balance >= expected_balance,
+					"The pallet account holds less than the reserves and the collateral"
+				);
+			}
+
+			Ok(())
+		}
 	}
 }
@@ -0,0 +1,210 @@
+use crate::*;
+use frame_support::traits::{GetStorageVersion, OnRuntimeUpgrade, StorageVersion};
+
+/// Migrates the pallet storage from version 0 to version 1.
+///
+/// - Removes the unused `exchange_rate` field from every stored `LendingPool`. The exchange rate of
+///   the LP token is now derived from the pool's `supply_index`.
+/// - Removes the never-read `UnderlyingAssetStorage` map.
+/// - Resets `MinMaxExchangeRate` to the default bounds of the supply index, which replace the
+///   bounds of the removed exchange rate.
+pub mod v1 {
+	use super::*;
+	#[cfg(feature = "try-runtime")]
+	use frame_support::sp_runtime::TryRuntimeError;
+
+	/// The `LendingPool` layout prior to version 1
+	#[derive(Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, TypeInfo)]
+	#[scale_info(skip_type_params(T))]
+	pub struct OldLendingPool<T: Config> {
+		pub id: LendingPoolId,
+		pub lend_token_id: AssetIdOf<T>,
+		pub reserve_balance: AssetBalanceOf<T>,
+		pub borrowed_balance: AssetBalanceOf<T>,
+		pub activated: bool,
+		pub interest_model: InterestRateModel,
+		pub reserve_factor: Ratio,
+		pub exchange_rate: Ratio,
+		pub collateral_factor: Ratio,
+		pub liquidation_threshold: Ratio,
+		pub borrow_rate: Ratio,
+		pub supply_rate: Ratio,
+		pub last_accrued_interest_at: Timestamp,
+		pub borrow_index: Rate,
+		pub supply_index: Rate,
+	}
+
+	impl<T: Config> OldLendingPool<T> {
+		fn migrate(self) -> LendingPool<T> {
+			LendingPool {
+				id: self.id,
+				lend_token_id: self.lend_token_id,
+				reserve_balance: self.reserve_balance,
+				borrowed_balance: self.borrowed_balance,
+				activated: self.activated,
+				interest_model: self.interest_model,
+				reserve_factor: self.reserve_factor,
+				collateral_factor: self.collateral_factor,
+				liquidation_threshold: self.liquidation_threshold,
+				borrow_rate: self.borrow_rate,
+				supply_rate: self.supply_rate,
+				last_accrued_interest_at: self.last_accrued_interest_at,
+				borrow_index: self.borrow_index,
+				supply_index: self.supply_index,
+			}
+		}
+	}
+
+	/// The removed `UnderlyingAssetStorage`. The value type is irrelevant as the map is only
+	/// cleared.
+	#[frame_support::storage_alias]
+	pub type UnderlyingAssetStorage<T: Config> =
+		StorageMap<Pallet<T>, Blake2_128Concat, AssetIdOf<T>, ()>;
+
+	pub struct MigrateToV1<T>(PhantomData<T>);
+
+	impl<T: Config> OnRuntimeUpgrade for MigrateToV1<T> {
+		fn on_runtime_upgrade() -> Weight {
+			let on_chain_version = Pallet::<T>::on_chain_storage_version();
+			if on_chain_version != 0 {
+				log::info!(
+					target: "runtime::lending",
+					"MigrateToV1 skipped, on-chain storage version is {:?}",
+					on_chain_version
+				);
+				return T::DbWeight::get().reads(1);
+			}
+
+			let mut translated = 0u64;
+			LendingPoolStorage::<T>::translate::<OldLendingPool<T>, _>(|_, old| {
+				translated += 1;
+				Some(old.migrate())
+			});
+
+			let removed = UnderlyingAssetStorage::<T>::clear(u32::MAX, None).unique as u64;
+
+			MinMaxExchangeRate::<T>::put(DefaultMinMaxExchangeRate());
+
+			StorageVersion::new(1).put::<Pallet<T>>();
+
+			log::info!(
+				target: "runtime::lending",
+				"MigrateToV1 translated {} lending pools and removed {} underlying assets",
+				translated,
+				removed
+			);
+
+			T::DbWeight::get().reads_writes(translated + removed + 1, translated + removed + 2)
+		}
+
+		#[cfg(feature = "try-runtime")]
+		fn pre_upgrade() -> Result<Vec<u8>, TryRuntimeError> {
+			let pools = LendingPoolStorage::<T>::iter_keys().count() as u32;
+			Ok(pools.encode())
+		}
+
+		#[cfg(feature = "try-runtime")]
+		fn post_upgrade(state: Vec<u8>) -> Result<(), TryRuntimeError> {
+			let pools_before =
+				u32::decode(&mut &state[..]).map_err(|_| "Failed to decode pool count")?;
+			let pools_after = LendingPoolStorage::<T>::iter_values().count() as u32;
+			ensure!(pools_before == pools_after, "Lending pools were lost in the migration");
+			ensure!(
+				UnderlyingAssetStorage::<T>::iter_keys().next().is_none(),
+				"UnderlyingAssetStorage was not cleared"
+			);
+			ensure!(
+				MinMaxExchangeRate::<T>::get() == DefaultMinMaxExchangeRate(),
+				"MinMaxExchangeRate was not reset"
+			);
+			ensure!(
+				Pallet::<T>::on_chain_storage_version() == 1,
+				"Storage version was not updated"
+			);
+			Ok(())
+		}
+	}
+}
+
+/// Migrates the pallet storage from version 1 to version 2.
+///
+/// - Re-keys `Borrows` from a map keyed by `(account, borrowed_asset, collateral_asset)` to a
+///   double map keyed by the account, then by `(borrowed_asset, collateral_asset)`, so that the
+///   loans of one account can be iterated without scanning every loan.
+///
+/// All the loans are re-keyed in the upgrade block. `pre_upgrade` checks that this fits in a
+/// block, so try-runtime rejects the migration for a chain with too many loans.
+pub mod v2 {
+	use super::*;
+	#[cfg(feature = "try-runtime")]
+	use frame_support::sp_runtime::TryRuntimeError;
+
+	/// The `Borrows` map prior to version 2
+	#[frame_support::storage_alias]
+	pub type Borrows<T: Config> = StorageMap<
+		Pallet<T>,
+		Blake2_128Concat,
+		(AccountOf<T>, AssetIdOf<T>, AssetIdOf<T>),
+		UserBorrow<T>,
+	>;
+
+	pub struct MigrateToV2<T>(PhantomData<T>);
+
+	impl<T: Config> MigrateToV2<T> {
+		/// The weight of re-keying `migrated` loans
+		fn weight(migrated: u64) -> Weight {
+			T::DbWeight::get().reads_writes(migrated + 1, 2 * migrated + 1)
+		}
+	}
+
+	impl<T: Config> OnRuntimeUpgrade for MigrateToV2<T> {
+		fn on_runtime_upgrade() -> Weight {
+			let on_chain_version = Pallet::<T>::on_chain_storage_version();
+			if on_chain_version != 1 {
+				log::info!(
+					target: "runtime::lending",
+					"MigrateToV2 skipped, on-chain storage version is {:?}",
+					on_chain_version
+				);
+				return T::DbWeight::get().reads(1);
+			}
+
+			// Both layouts share the storage prefix, so all the old entries are taken out before
+			// any new one is written
+			let loans: Vec<_> = Borrows::<T>::drain().collect();
+			let migrated = loans.len() as u64;
+			for ((account, borrowed_asset, collateral_asset), loan) in loans {
+				crate::Borrows::<T>::insert(account, (borrowed_asset, collateral_asset), loan);
+			}
+
+			StorageVersion::new(2).put::<Pallet<T>>();
+
+			log::info!(target: "runtime::lending", "MigrateToV2 re-keyed {} loans", migrated);
+
+			Self::weight(migrated)
+		}
+
+		#[cfg(feature = "try-runtime")]
+		fn pre_upgrade() -> Result<Vec<u8>, TryRuntimeError> {
+			let loans = Borrows::<T>::iter_keys().count() as u32;
+			ensure!(
+				Self::weight(loans.into()).all_lte(T::BlockWeights::get().max_block),
+				"Re-keying the loans does not fit in a block"
+			);
+			Ok(loans.encode())
+		}
+
+		#[cfg(feature = "try-runtime")]
+		fn post_upgrade(state: Vec<u8>) -> Result<(), TryRuntimeError> {
+			let loans_before =
+				u32::decode(&mut &state[..]).map_err(|_| "Failed to decode loan count")?;
+			let loans_after = crate::Borrows::<T>::iter_values().count() as u32;
+			ensure!(loans_before == loans_after, "Loans were lost in the migration");
+			ensure!(
+				Pallet::<T>::on_chain_storage_version() == 2,
+				"Storage version was not updated"
+			);
+			Ok(())
+		}
+	}
+}
@@ -0,0 +1,291 @@
+//! The off-chain worker of the lending pallet.
+//!
+//! Every `LiquidationInterval` blocks the worker scans `Borrows` for the loans of accounts with a
+//! `weighted_health_factor` below one and liquidates them with the local key of type
+//! [`KEY_TYPE`]. Depending on `SignedLiquidations` it either submits a signed `liquidate`
+//! transaction, paying the fees, or a single unsigned `liquidate_unsigned_with_signed_payload`
+//! transaction for up to `MaxLiquidationsPerRun` loans, validated in `validate_unsigned`. Either
+//! way the account of the key repays `CloseFactor` of the debt and receives its value in
+//! collateral, plus the `LiquidationBonus`.
+use crate::*;
+use frame_support::{
+	sp_runtime::{
+		offchain::storage::{MutateStorageError, StorageRetrievalError, StorageValueRef},
+		transaction_validity::{InvalidTransaction, TransactionValidity, ValidTransaction},
+		KeyTypeId,
+	},
+	sp_std::collections::btree_map::BTreeMap,
+};
+use frame_system::offchain::{
+	SendSignedTransaction, SendUnsignedTransaction, SignedPayload, Signer, SigningTypes,
+};
+
+/// The key type of the off-chain worker's key
+pub const KEY_TYPE: KeyTypeId = KeyTypeId(*b"kylx");
+
+/// Offchain storage key of the block number of the last liquidation run
+const LAST_LIQUIDATION_RUN: &[u8] = b"kylix::lending::last-liquidation-run";
+
+/// The sr25519 key used by the off-chain worker to sign liquidations
+pub mod crypto {
+	use super::KEY_TYPE;
+	use frame_support::sp_runtime::{
+		app_crypto::{app_crypto, sr25519},
+		MultiSignature, MultiSigner,
+	};
+	use sp_core::sr25519::{Public as Sr25519Public, Signature as Sr25519Signature};
+
+	app_crypto!(sr25519, KEY_TYPE);
+
+	pub struct LendingAuthId;
+
+	impl frame_system::offchain::AppCrypto<MultiSigner, MultiSignature> for LendingAuthId {
+		type RuntimeAppPublic = Public;
+		type GenericSignature = Sr25519Signature;
+		type GenericPublic = Sr25519Public;
+	}
+}
+
+/// A loan of `Borrows`: `(borrower, borrowed_asset, collateral_asset)`
+pub type LoanKey<T> = (AccountOf<T>, AssetIdOf<T>, AssetIdOf<T>);
+
+/// A loan of an account with a weighted health factor below one, valued in USDT like
+/// `LoanPosition`. Used as response for rpc
+#[derive(Encode, Decode, Clone, PartialEq, Serialize, Deserialize, Debug, TypeInfo)]
+pub struct LiquidatablePosition<AccountId> {
+	pub borrower: AccountId,
+	pub borrowed_asset: u32,
+	pub collateral_asset: u32,
+	/// The debt of the loan, including interest, in the borrowed asset
+	pub debt: u128,
+	pub debt_usdt: u128,
+	pub collateral: u128,
+	pub collateral_usdt: u128,
+	/// The `weighted_health_factor` of all the loans of `borrower`, below one
+	pub health_factor: FixedU128,
+	/// The debt a liquidator repays in a single liquidation, `CloseFactor` of `debt` or the debt
+	/// the collateral covers
+	pub max_repayable: u128,
+	/// The collateral a liquidator receives for repaying `max_repayable`, including the
+	/// `LiquidationBonus`
+	pub max_seizable: u128,
+}
+
+/// The payload of an unsigned liquidation, signed by the off-chain worker's key
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, TypeInfo)]
+pub struct LiquidationPayload<Public, BlockNumber, Position> {
+	/// The key of the liquidator, whose account repays the debt and receives the collateral
+	pub public: Public,
+	/// The block at which the positions were found liquidatable
+	pub block_number: BlockNumber,
+	/// The loans to liquidate
+	pub positions: Vec<Position>,
+}
+
+impl<T: SigningTypes, Position: Encode> SignedPayload<T>
+	for LiquidationPayload<T::Public, BlockNumberFor<T>, Position>
+{
+	fn public(&self) -> T::Public {
+		self.public.clone()
+	}
+}
+
+impl<T: Config> Pallet<T> {
+	/// Entry point of the off-chain worker. Liquidates at most `MaxLiquidationsPerRun` loans,
+	/// at most once every `LiquidationInterval` blocks.
+	pub fn liquidate_offchain(block_number: BlockNumberFor<T>) -> Result<(), &'static str> {
+		if !Self::claim_offchain_run(
+			LAST_LIQUIDATION_RUN,
+			block_number,
+			T::LiquidationInterval::get(),
+		)? {
+			return Ok(());
+		}
+
+		let positions = Self::liquidatable_positions(T::MaxLiquidationsPerRun::get() as usize);
+		if positions.is_empty() {
+			return Ok(());
+		}
+
+		if T::SignedLiquidations::get() {
+			Self::send_signed_liquidation(positions[0].clone())
+		} else {
+			Self::send_unsigned_liquidations(block_number, positions)
+		}
+	}
+
+	/// Records `block_number` as the last run of the task whose last run is stored under `key`,
+	/// unless the task already ran less than `interval` blocks ago. Returns whether the task
+	/// should run.
+	pub(crate) fn claim_offchain_run(
+		key: &[u8],
+		block_number: BlockNumberFor<T>,
+		interval: BlockNumberFor<T>,
+	) -> Result<bool, &'static str> {
+		const RECENTLY_RUN: () = ();
+
+		let last_run = StorageValueRef::persistent(key);
+		let res =
+			last_run.mutate(|last: Result<Option<BlockNumberFor<T>>, StorageRetrievalError>| {
+				match last {
+					Ok(Some(block)) if block_number < block.saturating_add(interval) =>
+						Err(RECENTLY_RUN),
+					_ => Ok(block_number),
+				}
+			});
+		match res {
+			Ok(_) => Ok(true),
+			Err(MutateStorageError::ValueFunctionFailed(RECENTLY_RUN)) => Ok(false),
+			Err(MutateStorageError::ConcurrentModification(_)) =>
+				Err("Another off-chain worker run is in progress"),
+		}
+	}
+
+	/// Returns up to `limit` loans that can be liquidated. Loans whose value can not be
+	/// computed, e.g. because a price is missing, are skipped.
+	pub fn liquidatable_positions(limit: usize) -> Vec<LoanKey<T>> {
+		Self::liquidatable_loans(None)
+			.map(|(borrower, loan, _)| (borrower, loan.borrowed_asset, loan.collateral_asset))
+			.take(limit)
+			.collect()
+	}
+
+	/// Returns up to `limit` liquidatable loans with their value, in storage order after the
+	/// loan `cursor`. Pass the key of the last loan of a page as `cursor` to get the next one.
+	/// Loans whose value can not be computed, e.g. because a price is missing, are skipped.
+	pub fn get_liquidatable_positions(
+		limit: u32,
+		cursor: Option<LoanKey<T>>,
+	) -> Vec<LiquidatablePosition<T::AccountId>> {
+		Self::liquidatable_loans(cursor.as_ref())
+			.filter_map(|(borrower, loan, pool)| {
+				Self::liquidatable_position(borrower, &loan, &pool).ok()
+			})
+			.take(limit as usize)
+			.collect()
+	}
+
+	/// Iterates the liquidatable loans of `Borrows` after the loan `cursor`, with the pool of
+	/// each, its indexes updated to now
+	fn liquidatable_loans(
+		cursor: Option<&LoanKey<T>>,
+	) -> impl Iterator<Item = (AccountOf<T>, UserBorrow<T>, LendingPool<T>)> {
+		// the pools with their indexes updated to now, fetched once per borrowed asset
+		let mut pools: BTreeMap<AssetIdOf<T>, Option<LendingPool<T>>> = BTreeMap::new();
+
+		let loans = match cursor {
+			Some((borrower, borrowed_asset, collateral_asset)) => Borrows::<T>::iter_from(
+				Borrows::<T>::hashed_key_for(borrower, (*borrowed_asset, *collateral_asset)),
+			),
+			None => Borrows::<T>::iter(),
+		};
+		loans.filter_map(move |(borrower, _, loan)| {
+			let pool = pools.entry(loan.borrowed_asset).or_insert_with(|| {
+				LendingPoolStorage::<T>::get(AssetPool::<T>::from(loan.borrowed_asset))
+					.and_then(|mut pool| pool.update_indexes().ok().map(|_| pool))
+			});
+			let pool = pool.as_ref()?;
+			Self::loan_is_liquidatable(&borrower, pool, &loan)
+				.unwrap_or(false)
+				.then(|| (borrower, loan, pool.clone()))
+		})
+	}
+
+	/// Values `loan` of `borrower` in USDT. `pool` must have up to date indexes.
+	fn liquidatable_position(
+		borrower: AccountOf<T>,
+		loan: &UserBorrow<T>,
+		pool: &LendingPool<T>,
+	) -> Result<LiquidatablePosition<T::AccountId>, Error<T>> {
+		let position = Self::loan_position(pool, loan)?;
+		let health_factor = Self::account_health_factor(&borrower).unwrap_or_default();
+		let (max_repayable, max_seizable) = Self::liquidation_amounts(pool, loan)?;
+		Ok(LiquidatablePosition {
+			borrower,
+			borrowed_asset: position.borrowed_asset,
+			collateral_asset: position.collateral_asset,
+			debt: position.debt,
+			debt_usdt: position.debt_usdt,
+			collateral: position.collateral,
+			collateral_usdt: position.collateral_usdt,
+			health_factor,
+			max_repayable: max_repayable.saturated_into(),
+			max_seizable: max_seizable.saturated_into(),
+		})
+	}
+
+	/// Liquidates a single loan per run, as further signed transactions of the same run would
+	/// reuse the account's nonce
+	fn send_signed_liquidation(
+		(borrower, borrowed_asset, collateral_asset): LoanKey<T>,
+	) -> Result<(), &'static str> {
+		let (_, res) = Signer::<T, T::AuthorityId>::any_account()
+			.send_signed_transaction(|_| Call::liquidate {
+				borrower: borrower.clone(),
+				borrowed_asset,
+				collateral_asset,
+			})
+			.ok_or("No local account available to sign liquidations")?;
+		res.map_err(|()| "Failed to submit a signed liquidation")
+	}
+
+	fn send_unsigned_liquidations(
+		block_number: BlockNumberFor<T>,
+		positions: Vec<LoanKey<T>>,
+	) -> Result<(), &'static str> {
+		// the runtime would reject the transaction anyway
+		if NextUnsignedLiquidationAt::<T>::get() > block_number {
+			return Ok(());
+		}
+
+		let (_, res) = Signer::<T, T::AuthorityId>::any_account()
+			.send_unsigned_transaction(
+				|account| LiquidationPayload {
+					public: account.public.clone(),
+					block_number,
+					positions: positions.clone(),
+				},
+				|payload, signature| Call::liquidate_unsigned_with_signed_payload {
+					liquidation_payload: payload,
+					signature,
+				},
+			)
+			.ok_or("No local account available to sign liquidations")?;
+		res.map_err(|()| "Failed to submit the unsigned liquidations")
+	}
+
+	/// Validates an unsigned liquidation whose signature has already been verified.
+	///
+	/// Only one unsigned liquidation is accepted every `LiquidationInterval` blocks, it must
+	/// carry between one and `MaxLiquidationsPerRun` positions and at least one of them must be
+	/// liquidatable.
+	pub(crate) fn validate_liquidation_payload(
+		payload: &LiquidationPayload<T::Public, BlockNumberFor<T>, LoanKey<T>>,
+	) -> TransactionValidity {
+		let next_unsigned_at = NextUnsignedLiquidationAt::<T>::get();
+		if next_unsigned_at > payload.block_number {
+			return InvalidTransaction::Stale.into();
+		}
+		if payload.block_number > frame_system::Pallet::<T>::block_number() {
+			return InvalidTransaction::Future.into();
+		}
+		if payload.positions.is_empty() ||
+			payload.positions.len() > T::MaxLiquidationsPerRun::get() as usize
+		{
+			return InvalidTransaction::ExhaustsResources.into();
+		}
+		let any_liquidatable = payload.positions.iter().any(|(borrower, asset, collateral)| {
+			Self::is_liquidatable(borrower, *asset, *collateral).unwrap_or(false)
+		});
+		if !any_liquidatable {
+			return InvalidTransaction::Call.into();
+		}
+
+		ValidTransaction::with_tag_prefix("KylixLendingLiquidation")
+			.priority(T::UnsignedPriority::get())
+			.and_provides(next_unsigned_at)
+			.longevity(T::LiquidationInterval::get().saturated_into::<u64>().max(1))
+			.propagate(true)
+			.build()
+	}
+}
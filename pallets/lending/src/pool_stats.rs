@@ -0,0 +1,92 @@
+use crate::*;
+use frame_support::sp_std::collections::btree_set::BTreeSet;
+
+/// Market statistics of a lending pool, computed from the pool with its indexes updated to now.
+/// Used as response for rpc
+#[derive(Encode, Decode, Clone, PartialEq, Serialize, Deserialize, Debug, TypeInfo)]
+pub struct PoolStats {
+	pub asset_id: u32,
+	pub pool_id: u32,
+	pub is_activated: bool,
+	/// The deposits of all the suppliers, including the accrued interest
+	pub total_supply: u128,
+	/// The debt of all the loans, including the accrued interest
+	pub total_debt: u128,
+	/// The liquidity available to borrow and withdraw
+	pub reserve_balance: u128,
+	/// The principal of all the loans
+	pub borrowed_balance: u128,
+	pub borrow_index: FixedU128,
+	pub supply_index: FixedU128,
+	/// The number of accounts holding LP tokens of the pool
+	pub suppliers: u32,
+	/// The number of accounts with a loan of the pool
+	pub borrowers: u32,
+	pub utilization: FixedU128,
+	/// The borrow rate at 0% utilisation, `y0` of the `InterestRateModel`
+	pub rate_at_zero_utilization: FixedU128,
+	/// The borrow rate at 100% utilisation, `y1` of the `InterestRateModel`
+	pub rate_at_full_utilization: FixedU128,
+	/// The utilisation at the minimum borrow rate, `xm` of the `InterestRateModel`
+	pub optimal_utilization: FixedU128,
+	/// The minimum borrow rate, `ym` of the `InterestRateModel`
+	pub min_rate: FixedU128,
+	pub reserve_factor: FixedU128,
+	pub collateral_factor: FixedU128,
+	pub liquidation_threshold: FixedU128,
+	/// The timestamp, in seconds, the indexes were last updated at
+	pub last_accrued_interest_at: u64,
+}
+
+impl<T: Config> Pallet<T> {
+	/// Returns the market statistics of the lending pool of `asset`.
+	///
+	/// Iterates all the loans and supply indexes, so it is only meant for the runtime API.
+	pub fn get_pool_stats(asset: AssetIdOf<T>) -> Result<PoolStats, Error<T>> {
+		let mut pool = LendingPoolStorage::<T>::get(AssetPool::<T>::from(asset))
+			.ok_or(Error::<T>::LendingPoolDoesNotExist)?;
+		pool.update_indexes()?;
+
+		let total_supply = pool.accrued_deposit(T::Fungibles::total_issuance(pool.id))?;
+
+		let mut total_debt = AssetBalanceOf::<T>::zero();
+		let mut borrowers = BTreeSet::new();
+		for (borrower, _, loan) in
+			Borrows::<T>::iter().filter(|(_, (borrowed_asset, _), _)| *borrowed_asset == asset)
+		{
+			total_debt = total_debt
+				.checked_add(&pool.repayable_amount(&loan)?)
+				.ok_or(Error::<T>::OverflowError)?;
+			borrowers.insert(borrower);
+		}
+
+		let suppliers = SupplyIndexStorage::<T>::iter_keys()
+			.filter(|(supplier, supplied_asset)| {
+				*supplied_asset == asset && !T::Fungibles::balance(pool.id, supplier).is_zero()
+			})
+			.count();
+
+		Ok(PoolStats {
+			asset_id: asset,
+			pool_id: pool.id,
+			is_activated: pool.activated,
+			total_supply: total_supply.saturated_into(),
+			total_debt: total_debt.saturated_into(),
+			reserve_balance: pool.reserve_balance.saturated_into(),
+			borrowed_balance: pool.borrowed_balance.saturated_into(),
+			borrow_index: pool.borrow_index,
+			supply_index: pool.supply_index,
+			suppliers: suppliers.saturated_into(),
+			borrowers: borrowers.len().saturated_into(),
+			utilization: pool.utilisation_ratio()?.into(),
+			rate_at_zero_utilization: pool.interest_model.y0,
+			rate_at_full_utilization: pool.interest_model.y1,
+			optimal_utilization: pool.interest_model.xm,
+			min_rate: pool.interest_model.ym,
+			reserve_factor: pool.reserve_factor.into(),
+			collateral_factor: pool.collateral_factor.into(),
+			liquidation_threshold: pool.liquidation_threshold.into(),
+			last_accrued_interest_at: pool.last_accrued_interest_at,
+		})
+	}
+}
@@ -0,0 +1,143 @@
+//! The price feed of the off-chain worker.
+//!
+//! Every `PriceFeedInterval` blocks the worker fetches the USDT prices of `PriceFeedAssets` from
+//! the HTTP endpoint stored, as raw UTF-8 bytes, under [`PRICE_FEED_URL_KEY`] in the persistent
+//! offchain storage, e.g. with the `offchain_localStorageSet` RPC. The endpoint must answer a
+//! `GET` with a JSON object mapping asset ids to prices:
+//!
+//! ```json
+//! { "2": 6.42, "3": 21.5 }
+//! ```
+//!
+//! The prices of the configured assets are submitted in a single `set_asset_prices` transaction,
+//! signed with the local key of type [`KEY_TYPE`]. The feed is disabled while no endpoint is set.
+use crate::*;
+use frame_support::sp_runtime::offchain::{http, Duration, StorageKind};
+use frame_system::offchain::{SendSignedTransaction, Signer};
+use lite_json::{JsonValue, NumberValue};
+
+/// Offchain storage key of the URL of the price feed
+pub const PRICE_FEED_URL_KEY: &[u8] = b"kylix::lending::price-feed-url";
+
+/// Offchain storage key of the block number of the last price feed run
+const LAST_PRICE_FEED_RUN: &[u8] = b"kylix::lending::last-price-feed-run";
+
+/// How long the worker waits for the price feed to answer
+const PRICE_FEED_TIMEOUT_MS: u64 = 2_000;
+
+/// The asset the fetched prices are measured in, USDT
+const PRICE_FEED_BASE_ASSET: u32 = 1;
+
+/// Every asset with a lending pool, except the base asset of the price feed
+pub struct LendingPoolAssets<T>(PhantomData<T>);
+
+impl<T: Config> Get<Vec<AssetIdOf<T>>> for LendingPoolAssets<T> {
+	fn get() -> Vec<AssetIdOf<T>> {
+		LendingPoolStorage::<T>::iter_values()
+			.map(|pool| pool.lend_token_id)
+			.filter(|asset| *asset != PRICE_FEED_BASE_ASSET.into())
+			.collect()
+	}
+}
+
+impl<T: Config> Pallet<T> {
+	/// Entry point of the price feed. Returns whether a price update was submitted.
+	pub fn feed_prices_offchain(block_number: BlockNumberFor<T>) -> Result<bool, &'static str> {
+		let Some(url) =
+			sp_io::offchain::local_storage_get(StorageKind::PERSISTENT, PRICE_FEED_URL_KEY)
+		else {
+			return Ok(false);
+		};
+		if !Self::claim_offchain_run(
+			LAST_PRICE_FEED_RUN,
+			block_number,
+			T::PriceFeedInterval::get(),
+		)? {
+			return Ok(false);
+		}
+
+		let url = core::str::from_utf8(&url).map_err(|_| "The price feed URL is not UTF-8")?;
+		let body = Self::fetch_price_feed(url)?;
+		let prices = Self::parse_price_feed(&body, &T::PriceFeedAssets::get())
+			.ok_or("The price feed response is not a JSON object")?;
+		if prices.is_empty() {
+			return Ok(false);
+		}
+
+		let (_, res) = Signer::<T, T::AuthorityId>::any_account()
+			.send_signed_transaction(|_| Call::set_asset_prices {
+				base_asset: PRICE_FEED_BASE_ASSET.into(),
+				prices: prices.clone(),
+			})
+			.ok_or("No local account available to sign price updates")?;
+		res.map_err(|()| "Failed to submit the price update")?;
+		Ok(true)
+	}
+
+	fn fetch_price_feed(url: &str) -> Result<Vec<u8>, &'static str> {
+		let deadline =
+			sp_io::offchain::timestamp().add(Duration::from_millis(PRICE_FEED_TIMEOUT_MS));
+		let pending = http::Request::get(url)
+			.deadline(deadline)
+			.send()
+			.map_err(|_| "Failed to send the price feed request")?;
+		let response = pending
+			.try_wait(deadline)
+			.map_err(|_| "The price feed request timed out")?
+			.map_err(|_| "The price feed request failed")?;
+		if response.code != 200 {
+			return Err("The price feed answered with an unexpected status code");
+		}
+		Ok(response.body().collect())
+	}
+
+	/// Parses the prices of `assets` out of a price feed response. Assets missing from the
+	/// response, or whose price is not a positive decimal number, are skipped.
+	pub(crate) fn parse_price_feed(
+		body: &[u8],
+		assets: &[AssetIdOf<T>],
+	) -> Option<Vec<(AssetIdOf<T>, FixedU128)>> {
+		let body = core::str::from_utf8(body).ok()?;
+		let JsonValue::Object(entries) = lite_json::parse_json(body).ok()? else {
+			return None;
+		};
+
+		Some(
+			entries
+				.into_iter()
+				.filter_map(|(key, value)| {
+					let asset =
+						Self::parse_asset_id(&key).filter(|asset| assets.contains(asset))?;
+					let JsonValue::Number(price) = value else { return None };
+					Some((asset, Self::parse_price(&price)?))
+				})
+				.collect(),
+		)
+	}
+
+	fn parse_asset_id(key: &[char]) -> Option<AssetIdOf<T>> {
+		if key.is_empty() {
+			return None;
+		}
+		key.iter()
+			.try_fold(0u32, |id, c| id.checked_mul(10)?.checked_add(c.to_digit(10)?))
+			.map(Into::into)
+	}
+
+	/// Converts a JSON number to a price, rejecting negative numbers, exponents and more
+	/// fraction digits than `FixedU128` holds
+	fn parse_price(number: &NumberValue) -> Option<FixedU128> {
+		let accuracy_digits = FixedU128::accuracy().ilog10();
+		if number.negative || number.exponent != 0 || number.fraction_length > accuracy_digits {
+			return None;
+		}
+
+		let integer = FixedU128::checked_from_integer::<u128>(number.integer.into())?;
+		let fraction = FixedU128::from_inner(
+			u128::from(number.fraction)
+				.checked_mul(10u128.pow(accuracy_digits - number.fraction_length))?,
+		);
+		let price = integer.checked_add(&fraction)?;
+		(price > FixedU128::zero()).then_some(price)
+	}
+}
@@ -0,0 +1,50 @@
+use crate::*;
+
+/// The `RewardAsset` distributed every second to the suppliers and to the borrowers of a
+/// lending pool
+#[derive(
+	Clone, Encode, Decode, Eq, PartialEq, RuntimeDebug, MaxEncodedLen, TypeInfo, DefaultNoBound,
+)]
+#[scale_info(skip_type_params(T))]
+pub struct RewardSpeed<T: Config> {
+	pub supply_speed: AssetBalanceOf<T>,
+	pub borrow_speed: AssetBalanceOf<T>,
+}
+
+impl<T: Config> Pallet<T> {
+	/// Returns the incentive APYs of the suppliers and of the borrowers of `pool`: the value of
+	/// the rewards distributed in a year over the value supplied to, respectively borrowed from,
+	/// the pool. An APY is zero if its speed, its side of the pool or a needed price is zero.
+	pub fn reward_apys(pool: &LendingPool<T>) -> (Rate, Rate) {
+		let speed = RewardSpeeds::<T>::get(pool.lend_token_id);
+		let supplied = pool.reserve_balance.saturating_add(pool.borrowed_balance);
+		(
+			Self::reward_apy(pool.lend_token_id, speed.supply_speed, supplied).unwrap_or_default(),
+			Self::reward_apy(pool.lend_token_id, speed.borrow_speed, pool.borrowed_balance)
+				.unwrap_or_default(),
+		)
+	}
+
+	fn reward_apy(
+		asset: AssetIdOf<T>,
+		speed: AssetBalanceOf<T>,
+		balance: AssetBalanceOf<T>,
+	) -> Result<Rate, Error<T>> {
+		if speed.is_zero() || balance.is_zero() {
+			return Ok(Rate::zero());
+		}
+
+		let yearly_rewards = speed
+			.checked_mul(&SECONDS_PER_YEAR.saturated_into())
+			.ok_or(Error::<T>::OverflowError)?;
+		let reward_asset = T::RewardAsset::get();
+		// value the rewards in the asset of the pool
+		let yearly_rewards = if reward_asset == asset {
+			yearly_rewards
+		} else {
+			Self::get_equivalent_asset_amount(asset, reward_asset, yearly_rewards)?
+		};
+
+		Rate::checked_from_rational(yearly_rewards, balance).ok_or(Error::<T>::OverflowError)
+	}
+}
@@ -0,0 +1,144 @@
+use crate::*;
+use frame_support::{
+	sp_runtime::{ModuleError, TransactionOutcome},
+	storage::with_transaction,
+	traits::PalletInfoAccess,
+};
+
+/// An action of an account whose effect `simulate_action` projects. The collateral of a loan
+/// changes with it: borrowing locks more collateral, repaying releases some.
+#[derive(Encode, Decode, Clone, PartialEq, Eq, Serialize, Deserialize, Debug, TypeInfo)]
+pub enum SimulatedAction {
+	Supply { asset: u32, balance: u128 },
+	Withdraw { asset: u32, balance: u128 },
+	Borrow { asset: u32, balance: u128, collateral_asset: u32 },
+	Repay { asset: u32, balance: u128, collateral_asset: u32 },
+}
+
+impl SimulatedAction {
+	/// The asset of the lending pool the action is performed on
+	pub fn asset(&self) -> u32 {
+		match self {
+			Self::Supply { asset, .. } |
+			Self::Withdraw { asset, .. } |
+			Self::Borrow { asset, .. } |
+			Self::Repay { asset, .. } => *asset,
+		}
+	}
+
+	/// The asset the action values `asset` in, to report a missing price
+	fn base_asset(&self) -> u32 {
+		match self {
+			Self::Borrow { collateral_asset, .. } => *collateral_asset,
+			_ => 1, // USDT
+		}
+	}
+}
+
+/// The position of an account and the rates of the pool of the action after a simulated
+/// action. Used as response for rpc
+#[derive(Encode, Decode, Clone, PartialEq, Serialize, Deserialize, Debug, TypeInfo)]
+pub struct SimulationResult {
+	pub current_ltv: FixedU128,
+	pub sale_ltv: FixedU128,
+	pub liquidation_ltv: FixedU128,
+	/// `liquidation_ltv / current_ltv`, the position is liquidatable below one. `None` without
+	/// any debt
+	pub health_factor: Option<FixedU128>,
+	pub utilization: FixedU128,
+	pub borrow_apr: FixedU128,
+	pub supply_apr: FixedU128,
+	/// The error the action fails with, in which case the position and rates are the current
+	/// ones
+	pub error: Option<LendingApiError>,
+}
+
+impl LendingApiError {
+	/// Describes `error`, raised by an action on `asset` valued in `base_asset`
+	pub fn from_dispatch_error<T: Config>(
+		error: DispatchError,
+		asset: u32,
+		base_asset: u32,
+	) -> Self {
+		if let DispatchError::Module(ModuleError { index, error: ref encoded, .. }) = error {
+			if index as usize == Pallet::<T>::index() {
+				if let Ok(error) = Error::<T>::decode(&mut &encoded[..]) {
+					return Self::from_pallet_error(error, asset, base_asset);
+				}
+			}
+		}
+		Self::Other { error: <&'static str>::from(error).as_bytes().to_vec() }
+	}
+}
+
+impl<T: Config> Pallet<T> {
+	/// Returns the health factor of a position, `liquidation_ltv / current_ltv`, or `None` if
+	/// the position has no debt
+	pub fn health_factor(current_ltv: FixedU128, liquidation_ltv: FixedU128) -> Option<FixedU128> {
+		if current_ltv.is_zero() {
+			return None;
+		}
+		liquidation_ltv.checked_div(&current_ltv)
+	}
+
+	/// Performs `action` on behalf of `account` in a storage transaction that is rolled back,
+	/// and returns the resulting position of the account and rates of the pool of the action.
+	pub fn simulate_action(account: &T::AccountId, action: SimulatedAction) -> SimulationResult {
+		let asset = action.asset();
+		let base_asset = action.base_asset();
+
+		let mut projection = None;
+		let result = with_transaction(|| {
+			let result = Self::perform_action(account, action);
+			if result.is_ok() {
+				projection = Some(Self::project_action(account, asset));
+			}
+			TransactionOutcome::Rollback(result)
+		});
+
+		match result {
+			Ok(()) => projection.unwrap_or_else(|| Self::project_action(account, asset)),
+			Err(e) => SimulationResult {
+				error: Some(LendingApiError::from_dispatch_error::<T>(e, asset, base_asset)),
+				..Self::project_action(account, asset)
+			},
+		}
+	}
+
+	fn perform_action(account: &T::AccountId, action: SimulatedAction) -> DispatchResult {
+		match action {
+			SimulatedAction::Supply { asset, balance } =>
+				Self::do_supply(account, asset, balance.saturated_into()),
+			SimulatedAction::Withdraw { asset, balance } =>
+				Self::do_withdrawal(account, asset, balance.saturated_into()),
+			SimulatedAction::Borrow { asset, balance, collateral_asset } =>
+				Self::do_borrow(account, asset, balance.saturated_into(), collateral_asset),
+			SimulatedAction::Repay { asset, balance, collateral_asset } =>
+				Self::do_repay(account, asset, balance.saturated_into(), collateral_asset),
+		}
+	}
+
+	/// The current position of `account` and rates of the pool of `asset`
+	fn project_action(account: &T::AccountId, asset: AssetIdOf<T>) -> SimulationResult {
+		let (current_ltv, sale_ltv, liquidation_ltv) = Self::compute_user_ltv(account);
+		let pool = LendingPoolStorage::<T>::get(AssetPool::<T>::from(asset));
+		let rate = |f: fn(&LendingPool<T>) -> Result<Rate, Error<T>>| {
+			pool.as_ref().and_then(|pool| f(pool).ok()).unwrap_or_default()
+		};
+
+		SimulationResult {
+			current_ltv,
+			sale_ltv,
+			liquidation_ltv,
+			health_factor: Self::health_factor(current_ltv, liquidation_ltv),
+			utilization: pool
+				.as_ref()
+				.and_then(|pool| pool.utilisation_ratio().ok())
+				.unwrap_or_default()
+				.into(),
+			borrow_apr: rate(LendingPool::<T>::borrow_interest_rate),
+			supply_apr: rate(LendingPool::<T>::supply_interest_rate),
+			error: None,
+		}
+	}
+}
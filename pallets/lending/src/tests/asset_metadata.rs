@@ -0,0 +1,119 @@
+use crate::{tests::mock::*, AssetDisplayMetadata, AssetMetadata, Error, Event};
+use frame_support::{assert_noop, assert_ok};
+use sp_runtime::{DispatchError, FixedU128};
+
+const ICON: &[u8] = b"https://assets.kylix.finance/icons/dot.svg";
+
+fn set_dot_metadata() {
+	assert_ok!(Lending::set_asset_metadata(
+		RuntimeOrigin::root(),
+		DOT,
+		ICON.to_vec(),
+		b"Polkadot".to_vec(),
+		b"polkadot".to_vec()
+	));
+}
+
+#[test]
+fn test_set_asset_metadata() {
+	ExtBuilder::default().build_and_execute(|| {
+		set_dot_metadata();
+
+		let metadata = AssetMetadata::<Test>::get(DOT).unwrap();
+		assert_eq!(metadata.icon.into_inner(), ICON.to_vec());
+		assert_eq!(metadata.display_name.into_inner(), b"Polkadot".to_vec());
+		assert_eq!(metadata.coingecko_id.into_inner(), b"polkadot".to_vec());
+		System::assert_last_event(Event::AssetMetadataSet { asset: DOT }.into());
+	});
+}
+
+#[test]
+fn test_set_asset_metadata_requires_manager_origin() {
+	ExtBuilder::default().build_and_execute(|| {
+		assert_noop!(
+			Lending::set_asset_metadata(
+				RuntimeOrigin::signed(ALICE),
+				DOT,
+				ICON.to_vec(),
+				b"Polkadot".to_vec(),
+				b"polkadot".to_vec()
+			),
+			DispatchError::BadOrigin
+		);
+		assert_noop!(
+			Lending::clear_asset_metadata(RuntimeOrigin::signed(ALICE), DOT),
+			DispatchError::BadOrigin
+		);
+	});
+}
+
+#[test]
+fn test_set_asset_metadata_rejects_long_fields() {
+	ExtBuilder::default().build_and_execute(|| {
+		assert_noop!(
+			Lending::set_asset_metadata(
+				RuntimeOrigin::root(),
+				DOT,
+				ICON.to_vec(),
+				vec![b'a'; 65],
+				b"polkadot".to_vec()
+			),
+			Error::<Test>::AssetMetadataTooLong
+		);
+		assert_eq!(
+			AssetDisplayMetadata::<Test>::new(vec![b'a'; 64], vec![], vec![])
+				.map(|m| m.icon.len())
+				.ok(),
+			Some(64)
+		);
+	});
+}
+
+#[test]
+fn test_clear_asset_metadata() {
+	ExtBuilder::default().build_and_execute(|| {
+		assert_noop!(
+			Lending::clear_asset_metadata(RuntimeOrigin::root(), DOT),
+			Error::<Test>::AssetMetadataNotFound
+		);
+
+		set_dot_metadata();
+		assert_ok!(Lending::clear_asset_metadata(RuntimeOrigin::root(), DOT));
+
+		assert!(AssetMetadata::<Test>::get(DOT).is_none());
+		System::assert_last_event(Event::AssetMetadataCleared { asset: DOT }.into());
+	});
+}
+
+#[test]
+fn test_runtime_apis_return_asset_metadata() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (KSM, BOB, 1_000_000)])
+		.build_and_execute(|| {
+			setup_active_pool(DOT, 100_000);
+			assert_ok!(Lending::set_asset_price(
+				RuntimeOrigin::root(),
+				DOT,
+				KSM,
+				FixedU128::from(1)
+			));
+			assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), DOT, 500, KSM));
+			set_dot_metadata();
+
+			let (pools, _) = Lending::get_lending_pools(Some(DOT), None, None, None, false);
+			assert_eq!(pools[0].asset_icon, ICON.to_vec());
+			assert_eq!(pools[0].asset_display_name, b"Polkadot".to_vec());
+			assert_eq!(pools[0].asset_coingecko_id, b"polkadot".to_vec());
+
+			let (supplied, _) = Lending::get_asset_wise_supplies(&ALICE);
+			assert_eq!(supplied[0].asset_info.asset_icon, ICON.to_vec());
+
+			let (borrowed, collaterals, _, _) = Lending::get_asset_wise_borrows_collaterals(&BOB);
+			assert_eq!(borrowed[0].asset_info.asset_coingecko_id, b"polkadot".to_vec());
+			// without metadata the display name falls back to the name of the asset
+			let collateral = &collaterals[0].asset_info;
+			assert!(collateral.asset_icon.is_empty());
+			assert!(collateral.asset_coingecko_id.is_empty());
+			assert_eq!(collateral.asset_display_name, Lending::get_metadata(KSM).0);
+		});
+}
@@ -1,6 +1,6 @@
 use crate::{tests::mock::*, AssetPool, Borrows, Error, Event};
 use frame_support::{assert_noop, assert_ok};
-use sp_runtime::FixedU128;
+use sp_runtime::{traits::One, FixedU128};
 
 #[test]
 fn borrow_maximum_allowed_tokens_from_pool() {
@@ -10,8 +10,7 @@ fn borrow_maximum_allowed_tokens_from_pool() {
 			(DOT, BOB, 1_000_000),
 			(KSM, BOB, 1_000_000),
 		])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			// Setup and activate the DOT lending pool
 			setup_active_pool(DOT, 1000);
 			let bob_initial_ksm_balance = Fungibles::balance(KSM, &BOB);
@@ -26,7 +25,7 @@ fn borrow_maximum_allowed_tokens_from_pool() {
 
 			let price = FixedU128::from_rational(1, 1);
 			let dot_borrow_amount = 500;
-			assert_ok!(Lending::set_asset_price(RuntimeOrigin::signed(ALICE), DOT, KSM, price));
+			assert_ok!(Lending::set_asset_price(RuntimeOrigin::root(), DOT, KSM, price));
 
 			let ksm_collateral_amount =
 				Lending::estimate_collateral_amount(DOT, dot_borrow_amount, KSM).unwrap();
@@ -43,8 +42,11 @@ fn borrow_maximum_allowed_tokens_from_pool() {
 			System::assert_last_event(
 				Event::Borrowed {
 					who: BOB,
+					pool_id: LENDING_POOL_TOKEN,
 					borrowed_asset_id: DOT,
 					borrowed_balance: dot_borrow_amount,
+					scaled_balance: dot_borrow_amount,
+					borrow_index: FixedU128::one(),
 					collateral_asset_id: KSM,
 					collateral_balance: ksm_collateral_amount,
 				}
@@ -73,8 +75,7 @@ fn borrow_partial_amount_of_tokens_from_pool() {
 			(DOT, BOB, 1_000_000),
 			(KSM, BOB, 2_000_000),
 		])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			// Setup and activate the DOT lending pool
 			setup_active_pool(DOT, 1000);
 			let bob_initial_ksm_balance = Fungibles::balance(KSM, &BOB);
@@ -89,7 +90,7 @@ fn borrow_partial_amount_of_tokens_from_pool() {
 
 			// Set price: 1 DOT = 0.1 KSM
 			let price = FixedU128::from_rational(1, 10);
-			assert_ok!(Lending::set_asset_price(RuntimeOrigin::signed(ALICE), DOT, KSM, price));
+			assert_ok!(Lending::set_asset_price(RuntimeOrigin::root(), DOT, KSM, price));
 
 			// First borrow: partial amount
 			let dot_borrow_amount_1 = 250; // Assuming 50% collateral factor
@@ -101,8 +102,11 @@ fn borrow_partial_amount_of_tokens_from_pool() {
 			System::assert_last_event(
 				Event::Borrowed {
 					who: BOB,
+					pool_id: LENDING_POOL_TOKEN,
 					borrowed_asset_id: DOT,
 					borrowed_balance: dot_borrow_amount_1,
+					scaled_balance: dot_borrow_amount_1,
+					borrow_index: FixedU128::one(),
 					collateral_asset_id: KSM,
 					collateral_balance: ksm_collateral_amount_1,
 				}
@@ -134,8 +138,11 @@ fn borrow_partial_amount_of_tokens_from_pool() {
 			System::assert_last_event(
 				Event::Borrowed {
 					who: BOB,
+					pool_id: LENDING_POOL_TOKEN,
 					borrowed_asset_id: DOT,
 					borrowed_balance: dot_borrow_amount_2,
+					scaled_balance: dot_borrow_amount_2,
+					borrow_index: FixedU128::one(),
 					collateral_asset_id: KSM,
 					collateral_balance: ksm_collateral_amount_2,
 				}
@@ -170,8 +177,7 @@ fn repay_all_borrowed_tokens_to_pool() {
 			(KSM, BOB, 1_000_000),
 			(DOT, BOB, 1_000_000),
 		])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			// Setup and activate the DOT lending pool
 			setup_active_pool(DOT, 1000);
 
@@ -187,7 +193,7 @@ fn repay_all_borrowed_tokens_to_pool() {
 
 			// Set price: 1 DOT = 1 KSM for simplicity
 			let price = FixedU128::from_rational(1, 1);
-			assert_ok!(Lending::set_asset_price(RuntimeOrigin::signed(ALICE), DOT, KSM, price));
+			assert_ok!(Lending::set_asset_price(RuntimeOrigin::root(), DOT, KSM, price));
 
 			// Borrow 500 DOT using 1000 KSM as collateral
 			let ksm_collateral_amount = 1000;
@@ -208,8 +214,11 @@ fn repay_all_borrowed_tokens_to_pool() {
 			System::assert_last_event(
 				Event::Repaid {
 					who: BOB,
+					pool_id: LENDING_POOL_TOKEN,
 					repaid_asset_id: DOT,
 					repaid_balance: dot_borrow_amount,
+					scaled_balance: dot_borrow_amount,
+					borrow_index: FixedU128::one(),
 					collateral_asset_id: KSM,
 					collateral_balance: ksm_collateral_amount,
 				}
@@ -236,8 +245,7 @@ fn partial_repay_of_borrowed_tokens() {
 			(KSM, BOB, 1_000_000),
 			(DOT, BOB, 1_000_000),
 		])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			// Setup and activate the DOT lending pool
 			setup_active_pool(DOT, 1000);
 
@@ -253,7 +261,7 @@ fn partial_repay_of_borrowed_tokens() {
 
 			// Set price: 1 DOT = 1 KSM for simplicity
 			let price = FixedU128::from_rational(1, 1);
-			assert_ok!(Lending::set_asset_price(RuntimeOrigin::signed(ALICE), DOT, KSM, price));
+			assert_ok!(Lending::set_asset_price(RuntimeOrigin::root(), DOT, KSM, price));
 
 			// Borrow 500 DOT using 1000 KSM as collateral
 			let ksm_collateral_amount = 1000;
@@ -280,8 +288,11 @@ fn partial_repay_of_borrowed_tokens() {
 			System::assert_last_event(
 				Event::Repaid {
 					who: BOB,
+					pool_id: LENDING_POOL_TOKEN,
 					repaid_asset_id: DOT,
 					repaid_balance: repayment_amount,
+					scaled_balance: repayment_amount,
+					borrow_index: FixedU128::one(),
 					collateral_asset_id: KSM,
 					collateral_balance: 400,
 				}
@@ -308,7 +319,7 @@ fn partial_repay_of_borrowed_tokens() {
 			);
 
 			// Verify remaining loan in Borrows storage
-			let remaining_loan = Borrows::<Test>::get((BOB, DOT, KSM)).expect("Loan should exist");
+			let remaining_loan = Borrows::<Test>::get(BOB, (DOT, KSM)).expect("Loan should exist");
 
 			// Expected remaining borrowed balance
 			let expected_remaining_borrowed = dot_borrow_amount - repayment_amount;
@@ -345,8 +356,7 @@ fn repay_full_with_accumulated_interest() {
 			(KSM, BOB, 1_000_000),
 			(DOT, BOB, 1_000_000),
 		])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			// Setup and activate the DOT lending pool
 			setup_active_pool(DOT, 100_000); // Provide sufficient initial liquidity
 
@@ -362,7 +372,7 @@ fn repay_full_with_accumulated_interest() {
 
 			// Set price: 1 DOT = 1 KSM for simplicity
 			let price = FixedU128::from_rational(1, 1);
-			assert_ok!(Lending::set_asset_price(RuntimeOrigin::signed(ALICE), DOT, KSM, price));
+			assert_ok!(Lending::set_asset_price(RuntimeOrigin::root(), DOT, KSM, price));
 
 			let ksm_collateral_amount = 100_000;
 			let dot_borrow_amount = 50_000;
@@ -401,7 +411,7 @@ fn repay_full_with_accumulated_interest() {
 			Lending::reserve_pools(asset_pool);
 
 			// Get BOB's loan
-			let loan = Borrows::<Test>::get((BOB, DOT, KSM)).expect("Loan should exist");
+			let loan = Borrows::<Test>::get(BOB, (DOT, KSM)).expect("Loan should exist");
 
 			// Calculate the repayable amount
 			let repayable_amount = pool.repayable_amount(&loan).unwrap();
@@ -421,8 +431,11 @@ fn repay_full_with_accumulated_interest() {
 			System::assert_last_event(
 				Event::Repaid {
 					who: BOB,
+					pool_id: LENDING_POOL_TOKEN,
 					repaid_asset_id: DOT,
 					repaid_balance: repayable_amount,
+					scaled_balance: loan.borrowed_balance,
+					borrow_index: pool.borrow_index,
 					collateral_asset_id: KSM,
 					collateral_balance: ksm_collateral_amount,
 				}
@@ -462,8 +475,7 @@ fn partial_repay_with_accumulated_interest() {
 			(KSM, BOB, 1_000_000),
 			(DOT, BOB, 1_000_000),
 		])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			setup_active_pool(DOT, 100_000);
 
 			let bob_initial_ksm_balance = Fungibles::balance(KSM, &BOB);
@@ -471,7 +483,7 @@ fn partial_repay_with_accumulated_interest() {
 			let asset_pool = AssetPool::<Test>::from(DOT);
 			assert!(Lending::reserve_pools(asset_pool.clone()).is_some(), "DOT pool should exist");
 			let price = FixedU128::from_rational(1, 1);
-			assert_ok!(Lending::set_asset_price(RuntimeOrigin::signed(ALICE), DOT, KSM, price));
+			assert_ok!(Lending::set_asset_price(RuntimeOrigin::root(), DOT, KSM, price));
 
 			// Bob borrows DOT by providing KSM as collateral
 			let ksm_collateral_amount = 100_000;
@@ -505,7 +517,7 @@ fn partial_repay_with_accumulated_interest() {
 			assert_ok!(pool.update_indexes());
 
 			// Get BOB's loan
-			let mut loan = Borrows::<Test>::get((BOB, DOT, KSM)).expect("Loan should exist");
+			let mut loan = Borrows::<Test>::get(BOB, (DOT, KSM)).expect("Loan should exist");
 
 			// Calculate the repayable amount
 			let repayable_amount = pool.repayable_amount(&loan).unwrap();
@@ -523,7 +535,7 @@ fn partial_repay_with_accumulated_interest() {
 			assert_ok!(Lending::repay(RuntimeOrigin::signed(BOB), DOT, partial_repayment, KSM));
 
 			// Fetch the updated loan
-			loan = Borrows::<Test>::get((BOB, DOT, KSM)).expect("Loan should still exist");
+			loan = Borrows::<Test>::get(BOB, (DOT, KSM)).expect("Loan should still exist");
 
 			// Verify Loan State After Partial Repayment
 			// Calculate expected remaining borrowed balance
@@ -576,3 +588,227 @@ fn partial_repay_with_accumulated_interest() {
 			println!("Pool's borrowed balance after repayment: {:?}", pool.borrowed_balance);
 		});
 }
+
+/// Creates the DOT pool, with the default factors of 50% and 80%, and a KSM pool with the given
+/// collateral factor and liquidation threshold. DOT, KSM and KYL are all worth 1 USDT.
+fn setup_pools_with_mixed_factors(ksm_collateral_factor: u32, ksm_liquidation_threshold: u32) {
+	setup_active_pool(DOT, 100_000);
+	assert_ok!(Lending::create_lending_pool(
+		RuntimeOrigin::signed(ALICE),
+		LENDING_POOL_TOKEN + 1,
+		KSM,
+		100_000
+	));
+	assert_ok!(Lending::activate_lending_pool(RuntimeOrigin::signed(ALICE), KSM));
+	set_pool_factors(KSM, ksm_collateral_factor, ksm_liquidation_threshold);
+	for asset in [DOT, KSM, KYL] {
+		assert_ok!(Lending::set_asset_price(RuntimeOrigin::root(), asset, USDT, FixedU128::one()));
+	}
+}
+
+fn mixed_factors_balances() -> Vec<(AssetId, AccountId, u128)> {
+	vec![
+		(DOT, ALICE, 1_000_000),
+		(KSM, ALICE, 1_000_000),
+		(DOT, BOB, 10_000),
+		(KYL, BOB, 1_000_000),
+	]
+}
+
+#[test]
+fn test_compute_user_ltv_weights_thresholds_by_collateral() {
+	ExtBuilder::default()
+		.with_endowed_balances(mixed_factors_balances())
+		.build_and_execute(|| {
+			setup_pools_with_mixed_factors(70, 75);
+
+			// 1500 DOT against 3000 KYL and 700 KSM against 1000 KYL, each at its collateral factor
+			assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), DOT, 1_500, KYL));
+			assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), KSM, 700, KYL));
+
+			// the minimum collateral factor, 50%, would put the account past its borrow limit
+			let (current_ltv, sale_ltv, liquidation_ltv) = Lending::compute_user_ltv(&BOB);
+			assert_eq!(current_ltv, FixedU128::from_rational(2_200, 4_000));
+			// 3/4 * 50% + 1/4 * 70%
+			assert_eq!(sale_ltv, FixedU128::from_rational(55, 100));
+			// 3/4 * 80% + 1/4 * 75%
+			assert_eq!(liquidation_ltv, FixedU128::from_rational(7_875, 10_000));
+		});
+}
+
+#[test]
+fn test_borrow_checks_the_weighted_health_factor_that_withdrawals_leave_unchanged() {
+	ExtBuilder::default()
+		.with_endowed_balances(mixed_factors_balances())
+		.build_and_execute(|| {
+			// loans of the KSM pool start past its liquidation threshold
+			setup_pools_with_mixed_factors(90, 75);
+			assert_ok!(Lending::supply(RuntimeOrigin::signed(BOB), DOT, 1_000));
+
+			// 0.75 * 1000 / 900 < 1
+			assert_noop!(
+				Lending::borrow(RuntimeOrigin::signed(BOB), KSM, 900, KYL),
+				Error::<Test>::HealthFactorTooLow
+			);
+
+			// the collateral of a DOT loan covers the KSM loan: (0.8 * 3000 + 0.75 * 1000) / 2400
+			assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), DOT, 1_500, KYL));
+			assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), KSM, 900, KYL));
+			let positions = Lending::get_loan_positions(&BOB);
+			assert_eq!(
+				Lending::weighted_health_factor(&positions),
+				Some(FixedU128::from_rational(3_150, 2_400))
+			);
+
+			// the account becomes liquidatable, but withdrawing its supply leaves the loans
+			// unchanged
+			assert_ok!(Lending::set_asset_price(
+				RuntimeOrigin::root(),
+				KYL,
+				USDT,
+				FixedU128::from_rational(1, 4)
+			));
+			let positions = Lending::get_loan_positions(&BOB);
+			let health_factor = Lending::weighted_health_factor(&positions);
+			assert!(health_factor.unwrap() < FixedU128::one());
+			let user_ltv = Lending::compute_user_ltv(&BOB);
+			assert_ok!(Lending::withdraw(RuntimeOrigin::signed(BOB), DOT, 1_000));
+			assert_eq!(
+				Lending::weighted_health_factor(&Lending::get_loan_positions(&BOB)),
+				health_factor
+			);
+			assert_eq!(Lending::compute_user_ltv(&BOB), user_ltv);
+		});
+}
+
+#[test]
+fn test_repay_all_closes_the_loan() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![
+			(DOT, ALICE, 1_000_000),
+			(KSM, BOB, 1_000_000),
+			(DOT, BOB, 1_000_000),
+		])
+		.build_and_execute(|| {
+			setup_active_pool(DOT, 100_000);
+			assert_ok!(Lending::set_asset_price(RuntimeOrigin::root(), DOT, KSM, FixedU128::one()));
+			assert_noop!(
+				Lending::repay_all(RuntimeOrigin::signed(BOB), DOT, KSM),
+				Error::<Test>::LoanDoesNotExists
+			);
+
+			let bob_initial_ksm_balance = Fungibles::balance(KSM, &BOB);
+			let bob_initial_dot_balance = Fungibles::balance(DOT, &BOB);
+			assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), DOT, 25_000, KSM));
+
+			// the debt grows every second
+			run_to_block(1_000);
+			let asset_pool = AssetPool::<Test>::from(DOT);
+			let mut pool = Lending::reserve_pools(&asset_pool).unwrap();
+			assert_ok!(pool.update_indexes());
+			let loan = Borrows::<Test>::get(BOB, (DOT, KSM)).unwrap();
+			let repayable_amount = pool.repayable_amount(&loan).unwrap();
+			assert!(repayable_amount > 25_000);
+
+			assert_ok!(Lending::repay_all(RuntimeOrigin::signed(BOB), DOT, KSM));
+			System::assert_last_event(
+				Event::Repaid {
+					who: BOB,
+					pool_id: LENDING_POOL_TOKEN,
+					repaid_asset_id: DOT,
+					repaid_balance: repayable_amount,
+					scaled_balance: loan.borrowed_balance,
+					borrow_index: pool.borrow_index,
+					collateral_asset_id: KSM,
+					collateral_balance: loan.collateral_balance,
+				}
+				.into(),
+			);
+
+			// the loan is closed and all the collateral is back
+			assert!(Borrows::<Test>::get(BOB, (DOT, KSM)).is_none());
+			assert_eq!(Fungibles::balance(KSM, &BOB), bob_initial_ksm_balance);
+			assert_eq!(
+				Fungibles::balance(DOT, &BOB),
+				bob_initial_dot_balance + 25_000 - repayable_amount
+			);
+			assert_eq!(Lending::reserve_pools(&asset_pool).unwrap().borrowed_balance, 0);
+		});
+}
+
+#[test]
+fn test_repay_on_behalf_releases_the_collateral_to_the_borrower() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![
+			(DOT, ALICE, 1_000_000),
+			(KSM, BOB, 1_000_000),
+			(DOT, BOB, 1_000_000),
+		])
+		.build_and_execute(|| {
+			setup_active_pool(DOT, 100_000);
+			assert_ok!(Lending::set_asset_price(RuntimeOrigin::root(), DOT, KSM, FixedU128::one()));
+			assert_noop!(
+				Lending::repay_on_behalf(RuntimeOrigin::signed(ALICE), BOB, DOT, 10_000, KSM),
+				Error::<Test>::LoanDoesNotExists
+			);
+
+			assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), DOT, 25_000, KSM));
+			assert_noop!(
+				Lending::repay_on_behalf(RuntimeOrigin::signed(ALICE), BOB, DOT, 0, KSM),
+				Error::<Test>::InvalidLiquidityWithdrawal
+			);
+			let alice_initial_dot_balance = Fungibles::balance(DOT, &ALICE);
+			let bob_initial_ksm_balance = Fungibles::balance(KSM, &BOB);
+			let bob_initial_dot_balance = Fungibles::balance(DOT, &BOB);
+			let loan = Borrows::<Test>::get(BOB, (DOT, KSM)).unwrap();
+
+			// ALICE pays down part of the loan of BOB
+			assert_ok!(Lending::repay_on_behalf(
+				RuntimeOrigin::signed(ALICE),
+				BOB,
+				DOT,
+				10_000,
+				KSM
+			));
+			let partial_loan = Borrows::<Test>::get(BOB, (DOT, KSM)).unwrap();
+			let released_collateral = loan.collateral_balance - partial_loan.collateral_balance;
+			assert!(released_collateral > 0);
+			System::assert_last_event(
+				Event::RepaidOnBehalf {
+					payer: ALICE,
+					borrower: BOB,
+					pool_id: LENDING_POOL_TOKEN,
+					repaid_asset_id: DOT,
+					repaid_balance: 10_000,
+					scaled_balance: 10_000,
+					borrow_index: FixedU128::one(),
+					collateral_asset_id: KSM,
+					collateral_balance: released_collateral,
+				}
+				.into(),
+			);
+			assert_eq!(Fungibles::balance(DOT, &ALICE), alice_initial_dot_balance - 10_000);
+			assert_eq!(Fungibles::balance(DOT, &BOB), bob_initial_dot_balance);
+			assert_eq!(
+				Fungibles::balance(KSM, &BOB),
+				bob_initial_ksm_balance + released_collateral
+			);
+			assert_eq!(Fungibles::balance(KSM, &ALICE), 0);
+
+			// the balance is capped at the debt, which closes the loan
+			assert_ok!(Lending::repay_on_behalf(
+				RuntimeOrigin::signed(ALICE),
+				BOB,
+				DOT,
+				50_000,
+				KSM
+			));
+			assert!(Borrows::<Test>::get(BOB, (DOT, KSM)).is_none());
+			assert_eq!(Fungibles::balance(DOT, &ALICE), alice_initial_dot_balance - 25_000);
+			assert_eq!(
+				Fungibles::balance(KSM, &BOB),
+				bob_initial_ksm_balance + loan.collateral_balance
+			);
+			assert_eq!(Fungibles::balance(KSM, &ALICE), 0);
+		});
+}
@@ -1,6 +1,6 @@
-use crate::{tests::mock::*, AssetPool, Error, Event, LendingPool};
+use crate::{tests::mock::*, AssetPool, Error, Event, LendingPool, MinMaxExchangeRate};
 use frame_support::{assert_noop, assert_ok};
-use sp_runtime::{assert_eq_error_rate, Permill};
+use sp_runtime::{assert_eq_error_rate, traits::One, FixedPointNumber, FixedU128, Permill};
 
 const NEW_ASSET: AssetId = 8888u32;
 
@@ -8,8 +8,7 @@ const NEW_ASSET: AssetId = 8888u32;
 fn test_create_lending_pool_succeeds_for_new_asset() {
 	ExtBuilder::default()
 		.with_endowed_balances(vec![(NEW_ASSET, ALICE, 1_000_000)])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			let amount = 1_000;
 			assert_ok!(Lending::create_lending_pool(
 				RuntimeOrigin::signed(ALICE),
@@ -25,13 +24,22 @@ fn test_create_lending_pool_succeeds_for_new_asset() {
 			assert!(events.iter().any(|record| record.event ==
 				Event::LendingPoolAdded { who: ALICE, asset: NEW_ASSET }.into()));
 			assert!(events.iter().any(|record| record.event ==
-				Event::LiquiditySupplied { who: ALICE, asset: NEW_ASSET, balance: amount }
-					.into()));
+				Event::LiquiditySupplied {
+					who: ALICE,
+					asset: NEW_ASSET,
+					pool_id: LENDING_POOL_TOKEN,
+					balance: amount,
+					scaled_balance: amount,
+					supply_index: FixedU128::one(),
+				}
+				.into()));
 			assert!(events.iter().any(|record| record.event ==
 				Event::LPTokenMinted {
 					who: ALICE,
-					asset: LENDING_POOL_TOKEN,
-					balance: amount
+					asset: NEW_ASSET,
+					pool_id: LENDING_POOL_TOKEN,
+					balance: amount,
+					supply_index: FixedU128::one(),
 				}
 				.into()));
 		});
@@ -41,8 +49,7 @@ fn test_create_lending_pool_succeeds_for_new_asset() {
 fn test_create_lending_pool_fails_for_existing_asset() {
 	ExtBuilder::default()
 		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000)])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			// First creation should succeed
 			assert_ok!(Lending::create_lending_pool(
 				RuntimeOrigin::signed(ALICE),
@@ -68,8 +75,7 @@ fn test_create_lending_pool_fails_for_existing_asset() {
 fn test_create_lending_pool_fails_with_zero_amount() {
 	ExtBuilder::default()
 		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000)])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			// Pool creation should fail
 			assert_noop!(
 				Lending::create_lending_pool(
@@ -85,7 +91,7 @@ fn test_create_lending_pool_fails_with_zero_amount() {
 
 #[test]
 fn test_create_lending_pool_fails_with_insufficient_balance() {
-	ExtBuilder::default().with_endowed_balances(vec![]).build().execute_with(|| {
+	ExtBuilder::default().with_endowed_balances(vec![]).build_and_execute(|| {
 		// Pool creation should fail
 		assert_noop!(
 			Lending::create_lending_pool(
@@ -103,8 +109,7 @@ fn test_create_lending_pool_fails_with_insufficient_balance() {
 fn test_create_lending_pool_fails_with_existing_id() {
 	ExtBuilder::default()
 		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (KSM, ALICE, 1_000_000)])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			// First creation should succeed
 			assert_ok!(Lending::create_lending_pool(
 				RuntimeOrigin::signed(ALICE),
@@ -126,9 +131,45 @@ fn test_create_lending_pool_fails_with_existing_id() {
 		});
 }
 
+#[test]
+fn test_create_lending_pool_fails_with_exchange_rate_out_of_bounds() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000)])
+		.build_and_execute(|| {
+			// The initial exchange rate of 1 is above the maximum allowed
+			MinMaxExchangeRate::<Test>::put((
+				FixedU128::from_rational(2, 100),
+				FixedU128::from_rational(1, 2),
+			));
+			assert_noop!(
+				Lending::create_lending_pool(
+					RuntimeOrigin::signed(ALICE),
+					LENDING_POOL_TOKEN,
+					DOT,
+					1_000
+				),
+				Error::<Test>::ExchangeRateOutOfBounds
+			);
+		});
+}
+
+#[test]
+fn test_new_lending_pool_has_initial_exchange_rate() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000)])
+		.build_and_execute(|| {
+			setup_active_pool(DOT, 1_000);
+			let pool = Lending::reserve_pools(AssetPool::<Test>::from(DOT)).unwrap();
+			assert_eq!(pool.exchange_rate(), FixedU128::from_u32(1));
+
+			let (lending_pools, _) = Lending::get_lending_pools(Some(DOT), None, None, None, false);
+			assert_eq!(lending_pools.first().unwrap().exchange_rate, FixedU128::from_u32(1));
+		});
+}
+
 #[test]
 fn test_default_utilisation_rate() {
-	ExtBuilder::default().build().execute_with(|| {
+	ExtBuilder::default().build_and_execute(|| {
 		let pool: LendingPool<Test> = LendingPool::from(0, DOT, 10000).expect("failed");
 
 		assert_eq!(pool.reserve_balance, 10000);
@@ -146,7 +187,7 @@ fn test_default_utilisation_rate() {
 
 #[test]
 fn test_utilisation_rate_with_partial_borrowing() {
-	ExtBuilder::default().build().execute_with(|| {
+	ExtBuilder::default().build_and_execute(|| {
 		let mut pool: LendingPool<Test> = LendingPool::from(0, DOT, 5000).expect("failed");
 		pool.borrowed_balance = 5000;
 
@@ -161,7 +202,7 @@ fn test_utilisation_rate_with_partial_borrowing() {
 
 #[test]
 fn test_utilisation_rate_with_high_borrowing() {
-	ExtBuilder::default().build().execute_with(|| {
+	ExtBuilder::default().build_and_execute(|| {
 		let mut pool: LendingPool<Test> = LendingPool::from(0, DOT, 1000).expect("failed");
 		pool.borrowed_balance = 9000;
 
@@ -172,7 +213,7 @@ fn test_utilisation_rate_with_high_borrowing() {
 
 #[test]
 fn test_supply_interest_rate_with_partial_borrowing() {
-	ExtBuilder::default().build().execute_with(|| {
+	ExtBuilder::default().build_and_execute(|| {
 		let mut pool: LendingPool<Test> = LendingPool::from(0, DOT, 5000).expect("failed");
 		let error_margin: Rate = Rate::from_float(0.001);
 		pool.borrowed_balance = 5000;
@@ -189,7 +230,7 @@ fn test_supply_interest_rate_with_partial_borrowing() {
 
 #[test]
 fn test_supply_interest_rate_with_high_borrowing() {
-	ExtBuilder::default().build().execute_with(|| {
+	ExtBuilder::default().build_and_execute(|| {
 		let mut pool: LendingPool<Test> = LendingPool::from(0, DOT, 1000).expect("failed");
 		let error_margin: Rate = Rate::from_float(0.001);
 		pool.borrowed_balance = 9000;
@@ -203,8 +244,7 @@ fn test_supply_interest_rate_with_high_borrowing() {
 fn test_activate_fails_for_non_existent_lending_pool() {
 	ExtBuilder::default()
 		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000)])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			// Supply
 			assert_noop!(
 				Lending::activate_lending_pool(RuntimeOrigin::signed(ALICE), DOT),
@@ -217,8 +257,7 @@ fn test_activate_fails_for_non_existent_lending_pool() {
 fn test_activate_fails_for_already_activated_lending_pool() {
 	ExtBuilder::default()
 		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000)])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			assert_ok!(Lending::create_lending_pool(
 				RuntimeOrigin::signed(ALICE),
 				LENDING_POOL_TOKEN,
@@ -1,4 +1,8 @@
-use crate::{interest_rate::InterestRateModel, tests::mock::*};
+use crate::{
+	interest_rate::InterestRateModel, tests::mock::*, AssetPool, Error, Event, LendingPool,
+	LendingPoolStorage, UserBorrow, MAX_RATE_CURVE_POINTS, SECONDS_PER_YEAR,
+};
+use frame_support::assert_ok;
 use num_traits::{One, Zero};
 use sp_runtime::{assert_eq_error_rate, FixedU128};
 
@@ -78,3 +82,279 @@ fn test_calculate_cosine_interest() {
 	let interest_rate = model.calculate_cosine_interest(utilization).unwrap();
 	assert!(interest_rate > Rate::zero());
 }
+
+/// A pool lending half of its liquidity at a flat annual `rate` whose indexes were last
+/// updated `elapsed` seconds ago
+fn pool_at_flat_rate(rate: Rate, elapsed: u64) -> LendingPool<Test> {
+	Timestamp::set_timestamp(100 * SECONDS_PER_YEAR * 1_000);
+	let mut pool = LendingPool::<Test>::from(0, DOT, 1_000).unwrap();
+	pool.borrowed_balance = 1_000;
+	pool.interest_model = InterestRateModel::new(rate, rate, Rate::from_rational(80, 100), rate);
+	pool.last_accrued_interest_at -= elapsed;
+	pool
+}
+
+#[test]
+fn test_exp_matches_reference_values() {
+	ExtBuilder::default().build().execute_with(|| {
+		let pool = pool_at_flat_rate(Rate::zero(), 0);
+		// (x, e^x) with e^x taken from a high precision reference
+		let cases = [
+			(Rate::zero(), Rate::one()),
+			(Rate::from_rational(1, 1_000_000), Rate::from_inner(1_000_001_000_000_500_000)),
+			(Rate::one(), Rate::from_inner(2_718_281_828_459_045_235)),
+			(Rate::from_rational(15, 10), Rate::from_inner(4_481_689_070_338_064_822)),
+			(Rate::from(10), Rate::from_inner(22_026_465_794_806_716_516_957)),
+			(Rate::from(40), Rate::from_inner(235_385_266_837_019_985_407_899_910_749_034_804)),
+		];
+		for (x, expected) in cases {
+			let exp = pool.exp_fixed_u128(x).unwrap();
+			// relative error below 1e-12
+			let tolerance = Rate::from_inner(expected.into_inner() / 1_000_000_000_000 + 1);
+			assert_eq_error_rate!(exp, expected, tolerance);
+		}
+	});
+}
+
+#[test]
+fn test_exp_fails_only_when_the_result_is_not_representable() {
+	ExtBuilder::default().build().execute_with(|| {
+		let pool = pool_at_flat_rate(Rate::zero(), 0);
+		assert!(pool.exp_fixed_u128(Rate::from(47)).is_ok());
+		assert!(pool.exp_fixed_u128(Rate::from(48)).is_err());
+	});
+}
+
+#[test]
+fn test_borrow_index_accrues_in_steps_past_the_exp_limit() {
+	ExtBuilder::default().build().execute_with(|| {
+		// 1000% for 3 years untouched: e^30 is compounded one year at a time
+		let mut pool = pool_at_flat_rate(Rate::from(10), 3 * SECONDS_PER_YEAR);
+		let now = pool.last_accrued_interest_at + 3 * SECONDS_PER_YEAR;
+		assert_ok!(pool.accrue_interest());
+		let expected = Rate::from_inner(10_686_474_581_524_462_146_990_195_140_625);
+		let tolerance = Rate::from_inner(expected.into_inner() / 1_000_000_000);
+		assert_eq_error_rate!(pool.borrow_index, expected, tolerance);
+		assert_eq!(pool.last_accrued_interest_at, now);
+	});
+}
+
+#[test]
+fn test_saturating_borrow_index_never_lets_supply_outgrow_debt() {
+	ExtBuilder::default().build().execute_with(|| {
+		// 1000% for 10 years untouched: e^100 does not fit into a `FixedU128`
+		let mut pool = pool_at_flat_rate(Rate::from(10), 10 * SECONDS_PER_YEAR);
+		let now = pool.last_accrued_interest_at + 10 * SECONDS_PER_YEAR;
+		assert_ok!(pool.accrue_interest());
+		// the borrow index accrued as far as it fits, the supply index over the same time
+		assert!(pool.borrow_index > Rate::from_inner(u128::MAX / 2));
+		assert!(pool.supply_index > Rate::one());
+		assert!(pool.supply_index <= pool.borrow_index);
+		assert_eq!(pool.last_accrued_interest_at, now);
+		System::assert_last_event(
+			Event::InterestAccrualCapped {
+				asset: DOT,
+				pool_id: pool.id,
+				borrow_index: pool.borrow_index,
+			}
+			.into(),
+		);
+
+		// from then on neither index accrues
+		let indexes = (pool.supply_index, pool.borrow_index);
+		Timestamp::set_timestamp((now + SECONDS_PER_YEAR) * 1_000);
+		assert_ok!(pool.accrue_interest());
+		assert_eq!((pool.supply_index, pool.borrow_index), indexes);
+		assert_eq!(pool.last_accrued_interest_at, now + SECONDS_PER_YEAR);
+
+		// and its loans can still be valued
+		let loan = UserBorrow::<Test> {
+			borrowed_asset: DOT,
+			borrowed_balance: 1_000,
+			collateral_asset: KSM,
+			collateral_balance: 2_000,
+			principal_balance: 1_000,
+		};
+		assert!(pool.repayable_amount(&loan).unwrap() > 1_000);
+	});
+}
+
+#[test]
+fn test_borrow_index_compounds_over_years_long_gaps() {
+	ExtBuilder::default().build().execute_with(|| {
+		// 15% for 10 years untouched: e^1.5
+		let mut pool = pool_at_flat_rate(Rate::from_rational(15, 100), 10 * SECONDS_PER_YEAR);
+		assert_ok!(pool.update_indexes());
+		assert_eq_error_rate!(
+			pool.borrow_index,
+			Rate::from_inner(4_481_689_070_338_064_822),
+			Rate::from_inner(10_000_000)
+		);
+
+		// compounding in yearly steps ends up at the same index as one ten year step
+		let mut stepped = pool_at_flat_rate(Rate::from_rational(15, 100), 10 * SECONDS_PER_YEAR);
+		for _ in 0..10 {
+			let next_year = stepped.last_accrued_interest_at + SECONDS_PER_YEAR;
+			Timestamp::set_timestamp(next_year * 1_000);
+			assert_ok!(stepped.update_indexes());
+		}
+		assert_eq_error_rate!(
+			stepped.borrow_index,
+			pool.borrow_index,
+			Rate::from_inner(10_000_000)
+		);
+	});
+}
+
+#[test]
+fn test_borrow_index_compounds_extreme_rates() {
+	ExtBuilder::default().build().execute_with(|| {
+		// 500% for 5 years: e^25
+		let mut pool = pool_at_flat_rate(Rate::from(5), 5 * SECONDS_PER_YEAR);
+		assert_ok!(pool.update_indexes());
+		let expected = Rate::from_inner(72_004_899_337_385_872_524_161_351_466);
+		let tolerance = Rate::from_inner(expected.into_inner() / 1_000_000_000_000);
+		assert_eq_error_rate!(pool.borrow_index, expected, tolerance);
+
+		// 10000% for a single second still accrues
+		let mut pool = pool_at_flat_rate(Rate::from(100), 1);
+		assert_ok!(pool.update_indexes());
+		assert!(pool.borrow_index > Rate::one());
+	});
+}
+
+#[test]
+fn test_apys_match_a_year_of_accrual() {
+	ExtBuilder::default().build().execute_with(|| {
+		// 15% borrow rate: e^0.15 - 1
+		let mut pool = pool_at_flat_rate(Rate::from_rational(15, 100), SECONDS_PER_YEAR);
+		let borrow_apy = pool.borrow_apy().unwrap();
+		assert_eq_error_rate!(
+			borrow_apy,
+			Rate::from_inner(161_834_242_728_283_123),
+			Rate::from_inner(10_000_000)
+		);
+		assert!(borrow_apy > pool.borrow_interest_rate().unwrap());
+
+		assert_ok!(pool.update_indexes());
+		assert_eq_error_rate!(pool.borrow_index - Rate::one(), borrow_apy, small_error());
+
+		// the supply index accrued daily for a year
+		let mut pool = pool_at_flat_rate(Rate::from_rational(15, 100), SECONDS_PER_YEAR);
+		let supply_apr = pool.supply_interest_rate().unwrap();
+		let supply_apy = pool.supply_apy().unwrap();
+		assert!(supply_apy > supply_apr);
+		for _ in 0..365 {
+			let next_day = pool.last_accrued_interest_at + SECONDS_PER_YEAR / 365;
+			Timestamp::set_timestamp(next_day * 1_000);
+			assert_ok!(pool.update_indexes());
+		}
+		assert_eq_error_rate!(
+			pool.supply_index - Rate::one(),
+			supply_apy,
+			Rate::from_rational(1, 100_000)
+		);
+	});
+}
+
+#[test]
+fn test_get_rate_curve() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (KSM, BOB, 1_000_000)])
+		.build_and_execute(|| {
+			assert!(matches!(Lending::get_rate_curve(DOT, 5), Err(Error::LendingPoolDoesNotExist)));
+			setup_active_pool(DOT, 100_000);
+
+			let model = create_default_model();
+			let curve = Lending::get_rate_curve(DOT, 5).unwrap();
+			let utilizations: Vec<Rate> =
+				curve.points.iter().map(|point| point.utilization).collect();
+			assert_eq!(
+				utilizations,
+				[0, 25, 50, 75, 100].map(|percent| Rate::from_rational(percent, 100)).to_vec()
+			);
+			for point in &curve.points {
+				assert_eq!(
+					point.borrow_rate,
+					model.calculate_cosine_interest(point.utilization).unwrap()
+				);
+			}
+			assert_eq!(
+				(curve.points[0].borrow_rate, curve.points[0].supply_rate),
+				(model.y0, Rate::zero())
+			);
+			// 90% of the borrow rate at full utilisation goes to the suppliers
+			assert_eq!(curve.points[4].borrow_rate, model.y1);
+			assert_eq!(curve.points[4].supply_rate, Rate::from_rational(135, 1_000));
+			// nothing is borrowed yet, the current rates are the start of the curve
+			assert_eq!(curve.current, curve.points[0]);
+
+			assert_eq!(Lending::get_rate_curve(DOT, 0).unwrap().points.len(), 2);
+			assert_eq!(
+				Lending::get_rate_curve(DOT, u32::MAX).unwrap().points.len(),
+				MAX_RATE_CURVE_POINTS as usize
+			);
+
+			assert_ok!(Lending::set_asset_price(RuntimeOrigin::root(), DOT, KSM, Rate::one()));
+			assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), DOT, 25_000, KSM));
+			let pool = LendingPoolStorage::<Test>::get(AssetPool::<Test>::from(DOT)).unwrap();
+			let curve = Lending::get_rate_curve(DOT, 5).unwrap();
+			assert_eq!(curve.current, curve.points[1]);
+			assert_eq!(curve.current.utilization, Rate::from_rational(1, 4));
+			assert_eq!(curve.current.borrow_rate, pool.borrow_interest_rate().unwrap());
+			assert_eq!(curve.current.supply_rate, pool.supply_interest_rate().unwrap());
+		});
+}
+
+fn interest_accrued_events() -> Vec<Event<Test>> {
+	System::events()
+		.into_iter()
+		.filter_map(|record| match record.event {
+			RuntimeEvent::Lending(event @ Event::InterestAccrued { .. }) => Some(event),
+			_ => None,
+		})
+		.collect()
+}
+
+#[test]
+fn test_moving_the_indexes_deposits_interest_accrued() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (KSM, BOB, 1_000_000)])
+		.build_and_execute(|| {
+			setup_active_pool(DOT, 100_000);
+			assert_ok!(Lending::set_asset_price(RuntimeOrigin::root(), DOT, KSM, Rate::one()));
+			assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), DOT, 25_000, KSM));
+			// no time has passed since the pool was created
+			assert!(interest_accrued_events().is_empty());
+
+			run_to_block(10);
+			assert_ok!(Lending::supply(RuntimeOrigin::signed(ALICE), DOT, 1_000));
+			let pool = LendingPoolStorage::<Test>::get(AssetPool::<Test>::from(DOT)).unwrap();
+			assert!(pool.supply_index > Rate::one() && pool.borrow_index > Rate::one());
+			assert_eq!(
+				interest_accrued_events(),
+				vec![Event::InterestAccrued {
+					asset: DOT,
+					pool_id: LENDING_POOL_TOKEN,
+					supply_index: pool.supply_index,
+					borrow_index: pool.borrow_index,
+					accrued_at: pool.last_accrued_interest_at,
+				}]
+			);
+			System::assert_last_event(
+				Event::LiquiditySupplied {
+					who: ALICE,
+					asset: DOT,
+					pool_id: LENDING_POOL_TOKEN,
+					balance: 1_000,
+					scaled_balance: pool.scaled_supply_balance(1_000).unwrap(),
+					supply_index: pool.supply_index,
+				}
+				.into(),
+			);
+
+			// the indexes already accrued up to now
+			assert_ok!(Lending::supply(RuntimeOrigin::signed(ALICE), DOT, 1_000));
+			assert_eq!(interest_accrued_events().len(), 1);
+		});
+}
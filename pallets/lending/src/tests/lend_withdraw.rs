@@ -1,12 +1,12 @@
-use crate::{tests::mock::*, AssetPool, Error, Event, LendingPoolStorage};
+use crate::{tests::mock::*, AssetPool, Error, Event, LendingPoolStorage, MinMaxExchangeRate};
 use frame_support::{assert_noop, assert_ok};
+use sp_runtime::{traits::One, FixedU128};
 
 #[test]
 fn test_supply_succeeds_for_activated_lending_pool() {
 	ExtBuilder::default()
 		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000)])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			let initial_dot_balance = Fungibles::balance(DOT, &ALICE);
 			let pallet_initial_dot_balance = get_pallet_balance(DOT);
 			let amount = 1_000;
@@ -33,13 +33,23 @@ fn test_supply_succeeds_for_activated_lending_pool() {
 			assert_ok!(Lending::supply(RuntimeOrigin::signed(ALICE), DOT, supply_amount),);
 			// Check supply events
 			System::assert_last_event(
-				Event::LiquiditySupplied { who: ALICE, asset: DOT, balance: supply_amount }.into(),
+				Event::LiquiditySupplied {
+					who: ALICE,
+					asset: DOT,
+					pool_id: LENDING_POOL_TOKEN,
+					balance: supply_amount,
+					scaled_balance: supply_amount,
+					supply_index: FixedU128::one(),
+				}
+				.into(),
 			);
 			assert!(System::events().iter().any(|record| record.event ==
 				Event::LPTokenMinted {
 					who: ALICE,
-					asset: LENDING_POOL_TOKEN,
-					balance: amount
+					asset: DOT,
+					pool_id: LENDING_POOL_TOKEN,
+					balance: amount,
+					supply_index: FixedU128::one(),
 				}
 				.into()));
 			// Check final balances
@@ -62,8 +72,7 @@ fn test_supply_succeeds_for_activated_lending_pool() {
 fn test_supply_fails_with_zero_amount() {
 	ExtBuilder::default()
 		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000)])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			let amount = 1_000;
 			assert_ok!(Lending::create_lending_pool(
 				RuntimeOrigin::signed(ALICE),
@@ -83,8 +92,7 @@ fn test_supply_fails_with_zero_amount() {
 fn test_supply_fails_with_insufficient_balance() {
 	ExtBuilder::default()
 		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000)])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			let amount = 1_000;
 			assert_ok!(Lending::create_lending_pool(
 				RuntimeOrigin::signed(ALICE),
@@ -104,8 +112,7 @@ fn test_supply_fails_with_insufficient_balance() {
 fn test_supply_fails_for_nonexistent_lending_pool() {
 	ExtBuilder::default()
 		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000)])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			assert_noop!(
 				Lending::supply(RuntimeOrigin::signed(ALICE), DOT, 1_000),
 				Error::<Test>::LendingPoolDoesNotExist
@@ -117,8 +124,7 @@ fn test_supply_fails_for_nonexistent_lending_pool() {
 fn test_supply_fails_for_inactive_lending_pool() {
 	ExtBuilder::default()
 		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000)])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			assert_ok!(Lending::create_lending_pool(
 				RuntimeOrigin::signed(ALICE),
 				LENDING_POOL_TOKEN,
@@ -136,13 +142,20 @@ fn test_supply_fails_for_inactive_lending_pool() {
 fn test_supply_succeeds_for_active_pool() {
 	ExtBuilder::default()
 		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (DOT, BOB, 1_000_000)])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			setup_active_pool(DOT, 1000);
 			assert_ok!(Lending::supply(RuntimeOrigin::signed(BOB), DOT, 500));
 
 			System::assert_last_event(
-				Event::LiquiditySupplied { who: BOB, asset: DOT, balance: 500 }.into(),
+				Event::LiquiditySupplied {
+					who: BOB,
+					asset: DOT,
+					pool_id: LENDING_POOL_TOKEN,
+					balance: 500,
+					scaled_balance: 500,
+					supply_index: FixedU128::one(),
+				}
+				.into(),
 			);
 		});
 }
@@ -151,8 +164,7 @@ fn test_supply_succeeds_for_active_pool() {
 fn test_supply_fails_for_inactive_pool() {
 	ExtBuilder::default()
 		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (DOT, BOB, 1_000_000)])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			assert_ok!(Lending::create_lending_pool(
 				RuntimeOrigin::signed(ALICE),
 				LENDING_POOL_TOKEN,
@@ -170,8 +182,7 @@ fn test_supply_fails_for_inactive_pool() {
 fn test_withdraw_all_tokens_succeeds() {
 	ExtBuilder::default()
 		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (DOT, BOB, 1_000_000)])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			setup_active_pool(DOT, 1000);
 			assert_ok!(Lending::supply(RuntimeOrigin::signed(BOB), DOT, 500));
 
@@ -181,7 +192,15 @@ fn test_withdraw_all_tokens_succeeds() {
 			assert_ok!(Lending::withdraw(RuntimeOrigin::signed(BOB), DOT, withdraw_amount));
 
 			System::assert_last_event(
-				Event::LiquidityWithdrawn { who: BOB, asset: DOT, balance: withdraw_amount }.into(),
+				Event::LiquidityWithdrawn {
+					who: BOB,
+					asset: DOT,
+					pool_id: LENDING_POOL_TOKEN,
+					balance: withdraw_amount,
+					scaled_balance: withdraw_amount,
+					supply_index: FixedU128::one(),
+				}
+				.into(),
 			);
 
 			// Check final balances
@@ -202,8 +221,7 @@ fn test_withdraw_all_tokens_succeeds() {
 fn test_withdraw_fails_with_insufficient_eligibility() {
 	ExtBuilder::default()
 		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (DOT, BOB, 1_000_000)])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			setup_active_pool(DOT, 1000);
 			assert_ok!(Lending::supply(RuntimeOrigin::signed(BOB), DOT, 500));
 			assert_noop!(
@@ -217,8 +235,7 @@ fn test_withdraw_fails_with_insufficient_eligibility() {
 fn test_withdraw_fails_with_insufficient_reserve() {
 	ExtBuilder::default()
 		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (DOT, BOB, 1_000_000)])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			setup_active_pool(DOT, 1000);
 			assert_noop!(
 				Lending::withdraw(RuntimeOrigin::signed(BOB), DOT, 1500),
@@ -231,8 +248,7 @@ fn test_withdraw_fails_with_insufficient_reserve() {
 fn test_withdraw_fails_with_zero_amount() {
 	ExtBuilder::default()
 		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (DOT, BOB, 1_000_000)])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			setup_active_pool(DOT, 1000);
 			assert_ok!(Lending::supply(RuntimeOrigin::signed(BOB), DOT, 500));
 			assert_noop!(
@@ -246,8 +262,7 @@ fn test_withdraw_fails_with_zero_amount() {
 fn test_withdraw_fails_for_nonexistent_lending_pool() {
 	ExtBuilder::default()
 		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (DOT, BOB, 1_000_000)])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			assert_noop!(
 				Lending::withdraw(RuntimeOrigin::signed(BOB), DOT, 500),
 				Error::<Test>::LendingPoolDoesNotExist
@@ -259,14 +274,92 @@ fn test_withdraw_fails_for_nonexistent_lending_pool() {
 fn test_partial_withdraw_succeeds() {
 	ExtBuilder::default()
 		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (DOT, BOB, 1_000_000)])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			setup_active_pool(DOT, 1000);
 			assert_ok!(Lending::supply(RuntimeOrigin::signed(BOB), DOT, 500));
 			assert_ok!(Lending::withdraw(RuntimeOrigin::signed(BOB), DOT, 250));
 
 			System::assert_last_event(
-				Event::LiquidityWithdrawn { who: BOB, asset: DOT, balance: 250 }.into(),
+				Event::LiquidityWithdrawn {
+					who: BOB,
+					asset: DOT,
+					pool_id: LENDING_POOL_TOKEN,
+					balance: 250,
+					scaled_balance: 250,
+					supply_index: FixedU128::one(),
+				}
+				.into(),
+			);
+		});
+}
+
+#[test]
+fn test_withdraw_all_burns_all_lp_tokens() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![
+			(DOT, ALICE, 1_000_000),
+			(KSM, ALICE, 1_000_000),
+			(DOT, BOB, 1_000_000),
+		])
+		.build_and_execute(|| {
+			setup_active_pool(DOT, 100_000);
+			assert_ok!(Lending::supply(RuntimeOrigin::signed(BOB), DOT, 10_000));
+			assert_ok!(Lending::set_asset_price(RuntimeOrigin::root(), DOT, KSM, FixedU128::one()));
+			assert_ok!(Lending::borrow(RuntimeOrigin::signed(ALICE), DOT, 25_000, KSM));
+
+			// the deposit of BOB grows every second
+			run_to_block(1_000);
+			let asset_pool = AssetPool::<Test>::from(DOT);
+			let mut pool = LendingPoolStorage::<Test>::get(&asset_pool).unwrap();
+			assert_ok!(pool.update_indexes());
+			let lp_tokens = Fungibles::balance(LENDING_POOL_TOKEN, &BOB);
+			let deposit = pool.accrued_deposit(lp_tokens).unwrap();
+			assert!(deposit > 10_000);
+
+			let initial_dot_balance = Fungibles::balance(DOT, &BOB);
+			assert_ok!(Lending::withdraw_all(RuntimeOrigin::signed(BOB), DOT));
+			System::assert_last_event(
+				Event::LiquidityWithdrawn {
+					who: BOB,
+					asset: DOT,
+					pool_id: LENDING_POOL_TOKEN,
+					balance: deposit,
+					scaled_balance: lp_tokens,
+					supply_index: pool.supply_index,
+				}
+				.into(),
 			);
+			assert_eq!(Fungibles::balance(DOT, &BOB), initial_dot_balance + deposit);
+			assert_eq!(Fungibles::balance(LENDING_POOL_TOKEN, &BOB), 0);
+
+			// nothing is left to withdraw
+			assert_noop!(
+				Lending::withdraw_all(RuntimeOrigin::signed(BOB), DOT),
+				Error::<Test>::InvalidLiquidityWithdrawal
+			);
+			assert_noop!(
+				Lending::withdraw_all(RuntimeOrigin::signed(BOB), KSM),
+				Error::<Test>::LendingPoolDoesNotExist
+			);
+		});
+}
+
+#[test]
+fn test_supply_and_withdraw_past_the_maximum_exchange_rate() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (DOT, BOB, 1_000_000)])
+		.build_and_execute(|| {
+			setup_active_pool(DOT, 100_000);
+			assert_ok!(Lending::supply(RuntimeOrigin::signed(ALICE), DOT, 1_000));
+
+			// the supply index moved past the maximum exchange rate
+			let asset_pool = AssetPool::<Test>::from(DOT);
+			let (_, max_exchange_rate) = MinMaxExchangeRate::<Test>::get();
+			LendingPoolStorage::<Test>::mutate(&asset_pool, |pool| {
+				pool.as_mut().unwrap().supply_index = max_exchange_rate + FixedU128::one();
+			});
+			assert_ok!(Lending::supply(RuntimeOrigin::signed(BOB), DOT, 100_000));
+			assert_ok!(Lending::withdraw(RuntimeOrigin::signed(ALICE), DOT, 1_000));
+			assert_ok!(Lending::withdraw_all(RuntimeOrigin::signed(ALICE), DOT));
 		});
 }
@@ -0,0 +1,364 @@
+use crate::{
+	tests::mock::*, AssetPool, BadDebt, Borrows, Call, Error, Event, LendingPoolStorage,
+	LiquidatablePosition, LiquidationPayload, NextUnsignedLiquidationAt,
+};
+use codec::Encode;
+use frame_support::{
+	assert_noop, assert_ok,
+	pallet_prelude::{InvalidTransaction, TransactionSource, ValidateUnsigned},
+	traits::{fungibles::Inspect, Hooks, UnfilteredDispatchable},
+};
+use sp_runtime::{
+	testing::{TestSignature, UintAuthorityId},
+	traits::One,
+	FixedU128,
+};
+
+const LIQUIDATOR: AccountId = 4;
+
+fn balances() -> Vec<(AssetId, AccountId, u128)> {
+	vec![(DOT, ALICE, 1_000_000), (KSM, BOB, 1_000_000), (DOT, LIQUIDATOR, 1_000_000)]
+}
+
+/// BOB borrows 500 DOT against 1000 KSM, a loan to value of 50%
+fn setup_loan() {
+	setup_active_pool(DOT, 100_000);
+	set_dot_price_in_ksm(1);
+	assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), DOT, 500, KSM));
+}
+
+/// Sets the USDT prices of DOT and KSM, the weighted health factor values the loans in USDT
+fn set_dot_price_in_ksm(price: u128) {
+	set_usdt_price(DOT, FixedU128::from(price));
+	set_usdt_price(KSM, FixedU128::one());
+}
+
+fn set_usdt_price(asset: AssetId, price: FixedU128) {
+	assert_ok!(Lending::set_asset_price(RuntimeOrigin::root(), asset, USDT, price));
+}
+
+#[test]
+fn test_liquidate_loan_past_liquidation_threshold() {
+	ExtBuilder::default().with_endowed_balances(balances()).build_and_execute(|| {
+		setup_loan();
+		// the debt is now worth 1000 KSM, a loan to value of 100% > 80%
+		set_dot_price_in_ksm(2);
+
+		assert_ok!(Lending::liquidate(RuntimeOrigin::signed(LIQUIDATOR), BOB, DOT, KSM));
+
+		// half of the debt is repaid, for its value of 500 KSM plus the 5% bonus
+		let loan = Borrows::<Test>::get(BOB, (DOT, KSM)).unwrap();
+		assert_eq!(loan.principal_balance, 250);
+		assert_eq!(loan.borrowed_balance, 250);
+		assert_eq!(loan.collateral_balance, 475);
+		assert_eq!(Fungibles::balance(DOT, LIQUIDATOR), 1_000_000 - 250);
+		assert_eq!(Fungibles::balance(KSM, LIQUIDATOR), 525);
+		let pool = LendingPoolStorage::<Test>::get(AssetPool::<Test>::from(DOT)).unwrap();
+		assert_eq!(pool.borrowed_balance, 250);
+		assert_eq!(pool.reserve_balance, 100_000 - 250);
+		System::assert_last_event(RuntimeEvent::Lending(Event::Liquidated {
+			liquidator: LIQUIDATOR,
+			borrower: BOB,
+			borrowed_asset_id: DOT,
+			repaid_balance: 250,
+			collateral_asset_id: KSM,
+			collateral_balance: 525,
+		}));
+	});
+}
+
+#[test]
+fn test_liquidate_seizes_at_most_the_collateral_and_records_the_rest_of_the_debt_as_bad_debt() {
+	ExtBuilder::default().with_endowed_balances(balances()).build_and_execute(|| {
+		setup_loan();
+		// the debt is now worth 2500 KSM, the collateral only 200 DOT: less than the debt plus
+		// the 5% bonus, and less than the half of the debt a liquidator would repay
+		set_dot_price_in_ksm(5);
+
+		assert_ok!(Lending::liquidate(RuntimeOrigin::signed(LIQUIDATOR), BOB, DOT, KSM));
+
+		// all the collateral is seized for the debt it covers with the bonus, 200 / 1.05 DOT
+		assert_eq!(Fungibles::balance(DOT, LIQUIDATOR), 1_000_000 - 190);
+		assert_eq!(Fungibles::balance(KSM, LIQUIDATOR), 1_000);
+		// the loan is closed and the rest of its debt is bad debt
+		assert_eq!(Borrows::<Test>::get(BOB, (DOT, KSM)), None);
+		assert_eq!(BadDebt::<Test>::get(DOT), 500 - 190);
+		let pool = LendingPoolStorage::<Test>::get(AssetPool::<Test>::from(DOT)).unwrap();
+		assert_eq!(pool.borrowed_balance, 0);
+		assert_eq!(pool.reserve_balance, 100_000 - 500 + 190);
+		System::assert_has_event(RuntimeEvent::Lending(Event::Liquidated {
+			liquidator: LIQUIDATOR,
+			borrower: BOB,
+			borrowed_asset_id: DOT,
+			repaid_balance: 190,
+			collateral_asset_id: KSM,
+			collateral_balance: 1_000,
+		}));
+		System::assert_last_event(RuntimeEvent::Lending(Event::BadDebtRecorded {
+			borrower: BOB,
+			borrowed_asset_id: DOT,
+			collateral_asset_id: KSM,
+			bad_debt: 310,
+		}));
+	});
+}
+
+#[test]
+fn test_liquidate_releases_the_rest_of_the_collateral_with_the_last_of_the_debt() {
+	ExtBuilder::default().with_endowed_balances(balances()).build_and_execute(|| {
+		setup_active_pool(DOT, 100_000);
+		set_pool_factors(DOT, 10, 80);
+		set_dot_price_in_ksm(1);
+		assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), DOT, 1, KSM));
+		assert_eq!(Borrows::<Test>::get(BOB, (DOT, KSM)).unwrap().collateral_balance, 10);
+		let bob_ksm_balance = Fungibles::balance(KSM, BOB);
+		// the debt is now worth 9 KSM, a loan to value of 90%
+		set_dot_price_in_ksm(9);
+
+		// half of a debt of 1 rounds up to the whole debt
+		assert_ok!(Lending::liquidate(RuntimeOrigin::signed(LIQUIDATOR), BOB, DOT, KSM));
+
+		assert_eq!(Borrows::<Test>::get(BOB, (DOT, KSM)), None);
+		assert_eq!(Fungibles::balance(DOT, LIQUIDATOR), 1_000_000 - 1);
+		// 9 KSM for the debt plus a bonus rounded down to nothing
+		assert_eq!(Fungibles::balance(KSM, LIQUIDATOR), 9);
+		assert_eq!(Fungibles::balance(KSM, BOB), bob_ksm_balance + 1);
+		let pool = LendingPoolStorage::<Test>::get(AssetPool::<Test>::from(DOT)).unwrap();
+		assert_eq!(pool.borrowed_balance, 0);
+	});
+}
+
+#[test]
+fn test_liquidate_fails_for_healthy_loan() {
+	ExtBuilder::default().with_endowed_balances(balances()).build_and_execute(|| {
+		setup_loan();
+		assert_noop!(
+			Lending::liquidate(RuntimeOrigin::signed(LIQUIDATOR), BOB, DOT, KSM),
+			Error::<Test>::PositionNotLiquidatable
+		);
+		assert_noop!(
+			Lending::liquidate(RuntimeOrigin::signed(LIQUIDATOR), ALICE, DOT, KSM),
+			Error::<Test>::LoanDoesNotExists
+		);
+	});
+}
+
+#[test]
+fn test_liquidation_follows_the_weighted_health_factor() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![
+			(DOT, ALICE, 1_000_000),
+			(KSM, BOB, 1_000_000),
+			(KYL, BOB, 1_000_000),
+			(DOT, LIQUIDATOR, 1_000_000),
+		])
+		.build_and_execute(|| {
+			setup_active_pool(DOT, 100_000);
+			for (asset, price) in [(DOT, 1), (KSM, 2), (KYL, 4)] {
+				set_usdt_price(asset, FixedU128::from(price));
+			}
+			// 500 DOT against 500 KSM and 1000 DOT against 500 KYL, both at a LTV of 50%
+			assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), DOT, 500, KSM));
+			assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), DOT, 1_000, KYL));
+
+			// the KSM loan alone is past the liquidation threshold, but the KYL loan still
+			// covers the debt of the account: (0.8 * 250 + 0.8 * 2000) / 1500
+			set_usdt_price(KSM, FixedU128::from_rational(1, 2));
+			let positions = Lending::get_loan_positions(&BOB);
+			assert!(positions
+				.iter()
+				.any(|position| position.health_factor < Some(FixedU128::one())));
+			assert_eq!(Lending::account_health_factor(&BOB), Some(FixedU128::from_rational(6, 5)));
+			assert_noop!(
+				Lending::liquidate(RuntimeOrigin::signed(LIQUIDATOR), BOB, DOT, KSM),
+				Error::<Test>::PositionNotLiquidatable
+			);
+			assert_eq!(Lending::get_liquidatable_positions(10, None), vec![]);
+
+			// (0.8 * 250 + 0.8 * 1000) / 1500, every loan of the account can be liquidated
+			set_usdt_price(KYL, FixedU128::from(2));
+			assert_eq!(Lending::account_health_factor(&BOB), Some(FixedU128::from_rational(2, 3)));
+			assert_eq!(Lending::get_liquidatable_positions(10, None).len(), 2);
+			assert_ok!(Lending::liquidate(RuntimeOrigin::signed(LIQUIDATOR), BOB, DOT, KYL));
+			// 500 DOT repaid for 250 KYL plus the 5% bonus
+			assert_eq!(Fungibles::balance(KYL, LIQUIDATOR), 262);
+		});
+}
+
+#[test]
+fn test_get_liquidatable_positions() {
+	ExtBuilder::default().with_endowed_balances(balances()).build_and_execute(|| {
+		setup_loan();
+		assert_eq!(Lending::get_liquidatable_positions(10, None), vec![]);
+
+		// the debt is now worth 1000 USDT, as much as the collateral
+		set_dot_price_in_ksm(2);
+		let position = LiquidatablePosition {
+			borrower: BOB,
+			borrowed_asset: DOT,
+			collateral_asset: KSM,
+			debt: 500,
+			debt_usdt: 1_000,
+			collateral: 1_000,
+			collateral_usdt: 1_000,
+			health_factor: FixedU128::from_rational(8, 10),
+			max_repayable: 250,
+			max_seizable: 525,
+		};
+		assert_eq!(Lending::get_liquidatable_positions(10, None), vec![position]);
+		assert_eq!(Lending::get_liquidatable_positions(0, None), vec![]);
+		// there is no loan after the last one
+		assert_eq!(Lending::get_liquidatable_positions(10, Some((BOB, DOT, KSM))), vec![]);
+	});
+}
+
+#[test]
+fn test_offchain_worker_submits_signed_liquidations() {
+	let (mut ext, _, pool_state) =
+		ExtBuilder::default().with_endowed_balances(balances()).build_offchain();
+	ext.execute_with(|| {
+		UintAuthorityId::set_all_keys(vec![LIQUIDATOR]);
+		setup_loan();
+
+		// nothing to liquidate while the loan is healthy
+		Lending::offchain_worker(1);
+		assert!(pool_state.read().transactions.is_empty());
+
+		set_dot_price_in_ksm(2);
+		Lending::offchain_worker(6);
+
+		let tx = pop_transaction(&pool_state);
+		assert!(pool_state.read().transactions.is_empty());
+		assert_eq!(tx.signature, Some((0, ())));
+		assert_eq!(
+			tx.call,
+			RuntimeCall::Lending(Call::liquidate {
+				borrower: BOB,
+				borrowed_asset: DOT,
+				collateral_asset: KSM
+			})
+		);
+	});
+}
+
+#[test]
+fn test_offchain_worker_is_rate_limited() {
+	let (mut ext, _, pool_state) =
+		ExtBuilder::default().with_endowed_balances(balances()).build_offchain();
+	ext.execute_with(|| {
+		UintAuthorityId::set_all_keys(vec![LIQUIDATOR]);
+		setup_loan();
+		set_dot_price_in_ksm(2);
+
+		Lending::offchain_worker(1);
+		assert_eq!(pool_state.read().transactions.len(), 1);
+
+		// within `LiquidationInterval` of the last run
+		Lending::offchain_worker(5);
+		assert_eq!(pool_state.read().transactions.len(), 1);
+
+		Lending::offchain_worker(6);
+		assert_eq!(pool_state.read().transactions.len(), 2);
+	});
+}
+
+#[test]
+fn test_offchain_worker_submits_unsigned_liquidation_with_signed_payload() {
+	let (mut ext, _, pool_state) =
+		ExtBuilder::default().with_endowed_balances(balances()).build_offchain();
+	ext.execute_with(|| {
+		SignedLiquidations::set(&false);
+		UintAuthorityId::set_all_keys(vec![LIQUIDATOR]);
+		set_authorities(vec![LIQUIDATOR]);
+		setup_loan();
+		set_dot_price_in_ksm(2);
+
+		Lending::offchain_worker(1);
+
+		let tx = pop_transaction(&pool_state);
+		assert_eq!(tx.signature, None);
+		let RuntimeCall::Lending(call) = tx.call else { panic!("unexpected call") };
+		let Call::liquidate_unsigned_with_signed_payload { ref liquidation_payload, .. } = call
+		else {
+			panic!("unexpected call")
+		};
+		assert_eq!(
+			*liquidation_payload,
+			LiquidationPayload {
+				public: UintAuthorityId(LIQUIDATOR),
+				block_number: 1,
+				positions: vec![(BOB, DOT, KSM)],
+			}
+		);
+		assert_ok!(Lending::validate_unsigned(TransactionSource::External, &call));
+
+		assert_ok!(call.clone().dispatch_bypass_filter(RuntimeOrigin::none()));
+		assert_eq!(Borrows::<Test>::get(BOB, (DOT, KSM)).unwrap().principal_balance, 250);
+		assert_eq!(Fungibles::balance(KSM, LIQUIDATOR), 525);
+		assert_eq!(NextUnsignedLiquidationAt::<Test>::get(), 6);
+
+		// only one unsigned liquidation is accepted per interval
+		assert_eq!(
+			Lending::validate_unsigned(TransactionSource::External, &call),
+			InvalidTransaction::Stale.into()
+		);
+		assert_ok!(Lending::do_try_state());
+	});
+}
+
+#[test]
+fn test_validate_unsigned_rejects_invalid_liquidations() {
+	ExtBuilder::default().with_endowed_balances(balances()).build_and_execute(|| {
+		set_authorities(vec![LIQUIDATOR]);
+		setup_loan();
+		set_dot_price_in_ksm(2);
+
+		let payload = LiquidationPayload {
+			public: UintAuthorityId(LIQUIDATOR),
+			block_number: 1,
+			positions: vec![(BOB, DOT, KSM)],
+		};
+		let call = |payload: LiquidationPayload<_, _, _>, signer| {
+			let signature = TestSignature(signer, payload.encode());
+			Call::<Test>::liquidate_unsigned_with_signed_payload {
+				liquidation_payload: payload,
+				signature,
+			}
+		};
+
+		assert_ok!(Lending::validate_unsigned(
+			TransactionSource::External,
+			&call(payload.clone(), LIQUIDATOR)
+		));
+		// signed by another key
+		assert_eq!(
+			Lending::validate_unsigned(TransactionSource::External, &call(payload.clone(), BOB)),
+			InvalidTransaction::BadProof.into()
+		);
+		// signed by a key that is not one of the authorities
+		let unknown = LiquidationPayload { public: UintAuthorityId(BOB), ..payload.clone() };
+		assert_eq!(
+			Lending::validate_unsigned(TransactionSource::External, &call(unknown, BOB)),
+			InvalidTransaction::BadSigner.into()
+		);
+		// from the future
+		let future = LiquidationPayload { block_number: 2, ..payload.clone() };
+		assert_eq!(
+			Lending::validate_unsigned(TransactionSource::External, &call(future, LIQUIDATOR)),
+			InvalidTransaction::Future.into()
+		);
+		// too many positions
+		let too_many =
+			LiquidationPayload { positions: vec![(BOB, DOT, KSM); 5], ..payload.clone() };
+		assert_eq!(
+			Lending::validate_unsigned(TransactionSource::External, &call(too_many, LIQUIDATOR)),
+			InvalidTransaction::ExhaustsResources.into()
+		);
+		// nothing to liquidate
+		set_dot_price_in_ksm(1);
+		assert_eq!(
+			Lending::validate_unsigned(TransactionSource::External, &call(payload, LIQUIDATOR)),
+			InvalidTransaction::Call.into()
+		);
+	});
+}
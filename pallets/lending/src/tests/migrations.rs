@@ -0,0 +1,113 @@
+use crate::{
+	migrations::{
+		v1::{MigrateToV1, OldLendingPool, UnderlyingAssetStorage},
+		v2::{self, MigrateToV2},
+	},
+	tests::mock::*,
+	AssetPool, Borrows, InterestRateModel, LendingPoolStorage, MinMaxExchangeRate, Rate,
+	UserBorrow,
+};
+use codec::Encode;
+use frame_support::traits::{GetStorageVersion, OnRuntimeUpgrade, StorageVersion};
+use sp_runtime::{traits::One, FixedPointNumber, Permill};
+
+#[test]
+fn test_migrate_to_v1_drops_exchange_rate_and_underlying_assets() {
+	ExtBuilder::default().build().execute_with(|| {
+		let old_pool = OldLendingPool::<Test> {
+			id: LENDING_POOL_TOKEN,
+			lend_token_id: DOT,
+			reserve_balance: 1_000,
+			borrowed_balance: 500,
+			activated: true,
+			interest_model: InterestRateModel::default(),
+			reserve_factor: Permill::from_percent(10),
+			exchange_rate: Permill::zero(),
+			collateral_factor: Permill::from_percent(50),
+			liquidation_threshold: Permill::from_percent(80),
+			borrow_rate: Permill::from_percent(20),
+			supply_rate: Permill::zero(),
+			last_accrued_interest_at: 42,
+			borrow_index: Rate::saturating_from_rational(11, 10),
+			supply_index: Rate::saturating_from_rational(105, 100),
+		};
+		let key = LendingPoolStorage::<Test>::hashed_key_for(AssetPool::<Test>::from(DOT));
+		frame_support::storage::unhashed::put_raw(&key, &old_pool.encode());
+		UnderlyingAssetStorage::<Test>::insert(DOT, ());
+		MinMaxExchangeRate::<Test>::put((Rate::saturating_from_rational(2, 100), Rate::one()));
+		StorageVersion::new(0).put::<Lending>();
+
+		MigrateToV1::<Test>::on_runtime_upgrade();
+
+		let pool = LendingPoolStorage::<Test>::get(AssetPool::<Test>::from(DOT))
+			.expect("pool should be translated");
+		assert_eq!(pool.id, LENDING_POOL_TOKEN);
+		assert_eq!(pool.reserve_balance, 1_000);
+		assert_eq!(pool.borrowed_balance, 500);
+		assert_eq!(pool.collateral_factor, Permill::from_percent(50));
+		assert_eq!(pool.last_accrued_interest_at, 42);
+		assert_eq!(pool.borrow_index, Rate::saturating_from_rational(11, 10));
+		assert_eq!(pool.exchange_rate(), Rate::saturating_from_rational(105, 100));
+
+		assert!(UnderlyingAssetStorage::<Test>::iter_keys().next().is_none());
+		assert_eq!(
+			MinMaxExchangeRate::<Test>::get(),
+			(Rate::one(), Rate::saturating_from_integer(10))
+		);
+		assert_eq!(Lending::on_chain_storage_version(), 1);
+	});
+}
+
+#[test]
+fn test_migrate_to_v1_is_skipped_when_already_migrated() {
+	ExtBuilder::default().build().execute_with(|| {
+		StorageVersion::new(1).put::<Lending>();
+		UnderlyingAssetStorage::<Test>::insert(DOT, ());
+
+		MigrateToV1::<Test>::on_runtime_upgrade();
+
+		assert!(UnderlyingAssetStorage::<Test>::contains_key(DOT));
+	});
+}
+
+fn loan(borrowed_asset: AssetId, collateral_asset: AssetId, balance: u128) -> UserBorrow<Test> {
+	UserBorrow {
+		borrowed_asset,
+		borrowed_balance: balance,
+		collateral_asset,
+		collateral_balance: 2 * balance,
+		principal_balance: balance,
+	}
+}
+
+#[test]
+fn test_migrate_to_v2_keys_borrows_by_account() {
+	ExtBuilder::default().build().execute_with(|| {
+		v2::Borrows::<Test>::insert((BOB, DOT, KSM), loan(DOT, KSM, 100));
+		v2::Borrows::<Test>::insert((BOB, KSM, DOT), loan(KSM, DOT, 200));
+		v2::Borrows::<Test>::insert((ALICE, DOT, USDT), loan(DOT, USDT, 300));
+		StorageVersion::new(1).put::<Lending>();
+
+		MigrateToV2::<Test>::on_runtime_upgrade();
+
+		assert_eq!(Borrows::<Test>::get(BOB, (DOT, KSM)), Some(loan(DOT, KSM, 100)));
+		assert_eq!(Borrows::<Test>::get(BOB, (KSM, DOT)), Some(loan(KSM, DOT, 200)));
+		assert_eq!(Borrows::<Test>::get(ALICE, (DOT, USDT)), Some(loan(DOT, USDT, 300)));
+		assert_eq!(Borrows::<Test>::iter_prefix(BOB).count(), 2);
+		assert_eq!(Borrows::<Test>::iter().count(), 3);
+		assert_eq!(Lending::on_chain_storage_version(), 2);
+	});
+}
+
+#[test]
+fn test_migrate_to_v2_is_skipped_when_already_migrated() {
+	ExtBuilder::default().build().execute_with(|| {
+		StorageVersion::new(2).put::<Lending>();
+		Borrows::<Test>::insert(BOB, (DOT, KSM), loan(DOT, KSM, 100));
+
+		MigrateToV2::<Test>::on_runtime_upgrade();
+
+		assert_eq!(Borrows::<Test>::get(BOB, (DOT, KSM)), Some(loan(DOT, KSM, 100)));
+		assert_eq!(Lending::on_chain_storage_version(), 2);
+	});
+}
@@ -1,5 +1,6 @@
 use crate as pallet_template;
-use crate::{AssetBalanceOf, AssetIdOf, BalanceOf};
+use crate::{AssetBalanceOf, AssetIdOf, AssetPool, BalanceOf, LendingPoolStorage, Ratio};
+use codec::Decode;
 pub type Fungibles = <Test as crate::Config>::Fungibles;
 use frame_support::{
 	assert_ok, derive_impl, parameter_types,
@@ -8,14 +9,26 @@ use frame_support::{
 	},
 	PalletId,
 };
-use frame_system::{EnsureRoot, EnsureSigned};
+use frame_system::{offchain::AppCrypto, EnsureRoot, EnsureSigned};
 use once_cell::sync::OnceCell;
-use sp_core::H256;
+use parking_lot::RwLock;
+use sp_core::{
+	offchain::{
+		testing::{OffchainState, PoolState, TestOffchainExt, TestTransactionPoolExt},
+		OffchainDbExt, OffchainWorkerExt, TransactionPoolExt,
+	},
+	H256,
+};
 use sp_runtime::{
-	traits::{AccountIdConversion, BlakeTwo256, IdentityLookup},
-	BuildStorage, FixedU128,
+	testing::{TestSignature, TestXt, UintAuthorityId},
+	traits::{AccountIdConversion, BlakeTwo256, Extrinsic as ExtrinsicT, IdentityLookup},
+	BuildStorage, FixedU128, Permill,
+};
+use std::{
+	cell::RefCell,
+	collections::{BTreeMap, HashSet},
+	sync::Arc,
 };
-use std::{cell::RefCell, collections::HashSet};
 
 // Define a static OnceCell to ensure the logger is initialized only once
 static INIT_LOGGER: OnceCell<()> = OnceCell::new();
@@ -39,6 +52,7 @@ pub const BOB: AccountId = 3;
 pub const USDT: AssetId = 1u32;
 pub const DOT: AssetId = 2u32;
 pub const KSM: AssetId = 3u32;
+pub const KYL: AssetId = 4u32;
 
 pub const LENDING_POOL_TOKEN: AssetId = 99999u32;
 pub type Rate = FixedU128;
@@ -46,6 +60,8 @@ const BLOCK_TIME_MS: u64 = 6_000; // 6 seconds per block in milliseconds
 
 thread_local! {
 	pub static ENDOWED_BALANCES: RefCell<Vec<(AssetId, AccountId, Balance)>> = RefCell::new(Vec::new());
+	/// The last seen (supply_index, borrow_index) of every lending pool
+	pub static POOL_INDEXES: RefCell<BTreeMap<AssetId, (Rate, Rate)>> = RefCell::new(BTreeMap::new());
 }
 
 // Configure a mock runtime to test the pallet.
@@ -134,6 +150,48 @@ impl pallet_assets::Config for Test {
 
 parameter_types! {
 	pub const KylixPalletId: PalletId = PalletId(*b"kylixpdl");
+	pub storage SignedLiquidations: bool = true;
+	pub PriceFeedAssets: Vec<AssetId> = vec![DOT, KSM];
+	pub const CloseFactor: Permill = Permill::from_percent(50);
+	pub const LiquidationBonus: Permill = Permill::from_percent(5);
+}
+
+pub type Extrinsic = TestXt<RuntimeCall, ()>;
+
+impl frame_system::offchain::SigningTypes for Test {
+	type Public = UintAuthorityId;
+	type Signature = TestSignature;
+}
+
+impl<LocalCall> frame_system::offchain::SendTransactionTypes<LocalCall> for Test
+where
+	RuntimeCall: From<LocalCall>,
+{
+	type OverarchingCall = RuntimeCall;
+	type Extrinsic = Extrinsic;
+}
+
+impl<LocalCall> frame_system::offchain::CreateSignedTransaction<LocalCall> for Test
+where
+	RuntimeCall: From<LocalCall>,
+{
+	fn create_transaction<C: AppCrypto<Self::Public, Self::Signature>>(
+		call: RuntimeCall,
+		_public: UintAuthorityId,
+		_account: AccountId,
+		nonce: u64,
+	) -> Option<(RuntimeCall, <Extrinsic as ExtrinsicT>::SignaturePayload)> {
+		Some((call, (nonce, ())))
+	}
+}
+
+/// Signs with the keys set by `UintAuthorityId::set_all_keys`, no keystore needed
+pub struct TestAuthId;
+
+impl AppCrypto<UintAuthorityId, TestSignature> for TestAuthId {
+	type RuntimeAppPublic = UintAuthorityId;
+	type GenericPublic = UintAuthorityId;
+	type GenericSignature = TestSignature;
 }
 
 impl pallet_template::Config for Test {
@@ -145,8 +203,19 @@ impl pallet_template::Config for Test {
 	#[doc = r" Type to access the Assets Pallet."]
 	type Fungibles = Assets;
 	type PalletId = KylixPalletId;
-	//	#[doc = r" The origin which can add or remove LendingPools and update LendingPools (interest
-	// rate model, kink, activate, deactivate)."] 	type ManagerOrigin = ();
+	type AuthorityId = TestAuthId;
+	type CloseFactor = CloseFactor;
+	type LiquidationBonus = LiquidationBonus;
+	type MaxAuthorities = ConstU32<8>;
+	type LiquidationInterval = ConstU64<5>;
+	type MaxLiquidationsPerRun = ConstU32<4>;
+	type SignedLiquidations = SignedLiquidations;
+	type UnsignedPriority = ConstU64<{ 1 << 20 }>;
+	type PriceFeedAssets = PriceFeedAssets;
+	type PriceFeedInterval = ConstU64<10>;
+	type ManagerOrigin = EnsureRoot<AccountId>;
+	type MaxAssetMetadataLength = ConstU32<64>;
+	type RewardAsset = ConstU32<KYL>;
 }
 
 pub struct ExtBuilder {
@@ -158,6 +227,9 @@ impl Default for ExtBuilder {
 		ENDOWED_BALANCES.with(|v| {
 			v.borrow_mut().clear();
 		});
+		POOL_INDEXES.with(|v| {
+			v.borrow_mut().clear();
+		});
 		Self { endowed_balances: vec![] }
 	}
 }
@@ -196,6 +268,45 @@ impl ExtBuilder {
 		ext.execute_with(|| System::set_block_number(1));
 		ext
 	}
+
+	/// Builds the externalities with the offchain worker extensions registered. Returns the state
+	/// of the transaction pool the offchain worker submits to.
+	pub fn build_offchain(
+		self,
+	) -> (sp_io::TestExternalities, Arc<RwLock<OffchainState>>, Arc<RwLock<PoolState>>) {
+		let mut ext = self.build();
+		let (offchain, offchain_state) = TestOffchainExt::new();
+		let (pool, pool_state) = TestTransactionPoolExt::new();
+		ext.register_extension(OffchainDbExt::new(offchain.clone()));
+		ext.register_extension(OffchainWorkerExt::new(offchain));
+		ext.register_extension(TransactionPoolExt::new(pool));
+		(ext, offchain_state, pool_state)
+	}
+
+	/// Builds the externalities and runs `test` in them, checking the pallet invariants
+	/// afterwards.
+	pub fn build_and_execute(self, test: impl FnOnce()) {
+		self.build().execute_with(|| {
+			test();
+			assert_ok!(Lending::do_try_state());
+			assert_indexes_monotonic();
+		})
+	}
+}
+
+/// Asserts that no lending pool index decreased since the last call
+pub fn assert_indexes_monotonic() {
+	POOL_INDEXES.with(|v| {
+		let mut indexes = v.borrow_mut();
+		for (_, pool) in LendingPoolStorage::<Test>::iter() {
+			let current = (pool.supply_index, pool.borrow_index);
+			if let Some((supply_index, borrow_index)) = indexes.get(&pool.lend_token_id) {
+				assert!(current.0 >= *supply_index, "supply index decreased");
+				assert!(current.1 >= *borrow_index, "borrow index decreased");
+			}
+			indexes.insert(pool.lend_token_id, current);
+		}
+	});
 }
 
 pub fn setup_active_pool(asset: AssetIdOf<Test>, initial_balance: BalanceOf<Test>) {
@@ -208,12 +319,34 @@ pub fn setup_active_pool(asset: AssetIdOf<Test>, initial_balance: BalanceOf<Test
 	assert_ok!(Lending::activate_lending_pool(RuntimeOrigin::signed(ALICE), asset));
 }
 
+/// Sets the collateral factor and the liquidation threshold of the pool of `asset`, in percent
+pub fn set_pool_factors(
+	asset: AssetIdOf<Test>,
+	collateral_factor: u32,
+	liquidation_threshold: u32,
+) {
+	LendingPoolStorage::<Test>::mutate(AssetPool::<Test>::from(asset), |pool| {
+		let pool = pool.as_mut().expect("the pool exists");
+		pool.collateral_factor = Ratio::from_percent(collateral_factor);
+		pool.liquidation_threshold = Ratio::from_percent(liquidation_threshold);
+	});
+}
+
+/// Sets the accounts allowed to feed prices and to sign unsigned liquidations
+pub fn set_authorities(authorities: Vec<AccountId>) {
+	assert_ok!(Lending::set_authorities(
+		RuntimeOrigin::root(),
+		authorities.try_into().expect("within MaxAuthorities")
+	));
+}
+
 pub fn get_pallet_balance(asset: AssetIdOf<Test>) -> AssetBalanceOf<Test> {
 	let pallet_account: AccountId = KylixPalletId::get().into_account_truncating();
 	return Fungibles::balance(asset, pallet_account);
 }
 
 pub fn run_to_block(n: u64) {
+	assert_indexes_monotonic();
 	let current_block = System::block_number();
 	if n > current_block {
 		Lending::on_finalize(current_block);
@@ -223,3 +356,9 @@ pub fn run_to_block(n: u64) {
 		Lending::on_initialize(System::block_number());
 	}
 }
+
+/// Pops the last transaction submitted by the off-chain worker
+pub fn pop_transaction(pool_state: &RwLock<PoolState>) -> Extrinsic {
+	let tx = pool_state.write().transactions.pop().expect("a transaction was submitted");
+	Extrinsic::decode(&mut &*tx).unwrap()
+}
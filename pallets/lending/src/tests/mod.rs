@@ -1,6 +1,15 @@
+mod asset_metadata;
 mod borrow_repay;
 mod create_activate;
 mod interest_rate;
 mod lend_withdraw;
+mod liquidation;
+mod migrations;
 pub(crate) mod mock;
+mod on_idle;
+mod price_feed;
+mod properties;
+mod rewards;
 mod runtime_apis;
+mod simulation;
+mod try_state;
@@ -0,0 +1,122 @@
+use crate::{
+	tests::mock::*, weights::WeightInfo, AssetPool, LastIdleAccruedPool, LendingPoolStorage,
+};
+use frame_support::{assert_ok, traits::Hooks, weights::Weight};
+use sp_runtime::FixedU128;
+
+const ASSETS: [AssetId; 3] = [USDT, DOT, KSM];
+
+fn setup_pools() {
+	for (i, asset) in ASSETS.iter().enumerate() {
+		assert_ok!(Lending::create_lending_pool(
+			RuntimeOrigin::signed(ALICE),
+			LENDING_POOL_TOKEN + i as u32,
+			*asset,
+			1_000
+		));
+		assert_ok!(Lending::activate_lending_pool(RuntimeOrigin::signed(ALICE), *asset));
+	}
+	assert_ok!(Lending::set_asset_price(
+		RuntimeOrigin::root(),
+		DOT,
+		KSM,
+		FixedU128::from_rational(1, 1)
+	));
+	assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), DOT, 500, KSM));
+}
+
+fn now_in_seconds() -> u64 {
+	Timestamp::get() / 1_000
+}
+
+/// Number of pools whose interest is accrued up to the current block
+fn fresh_pools() -> usize {
+	LendingPoolStorage::<Test>::iter_values()
+		.filter(|pool| pool.last_accrued_interest_at == now_in_seconds())
+		.count()
+}
+
+fn endowed() -> ExtBuilder {
+	ExtBuilder::default().with_endowed_balances(vec![
+		(USDT, ALICE, 1_000_000),
+		(DOT, ALICE, 1_000_000),
+		(KSM, ALICE, 1_000_000),
+		(KSM, BOB, 1_000_000),
+	])
+}
+
+#[test]
+fn test_on_idle_accrues_interest_on_untouched_pools() {
+	endowed().build_and_execute(|| {
+		setup_pools();
+		let before = LendingPoolStorage::<Test>::get(AssetPool::<Test>::from(DOT)).unwrap();
+
+		run_to_block(100_000);
+		assert_eq!(fresh_pools(), 0);
+
+		let consumed = Lending::on_idle(System::block_number(), Weight::MAX);
+
+		assert_eq!(
+			consumed,
+			<() as WeightInfo>::accrue_interest().saturating_mul(ASSETS.len() as u64)
+		);
+		assert_eq!(fresh_pools(), ASSETS.len());
+		let after = LendingPoolStorage::<Test>::get(AssetPool::<Test>::from(DOT)).unwrap();
+		assert!(after.borrow_index > before.borrow_index);
+		assert!(after.supply_index > before.supply_index);
+		assert_eq!(after.borrowed_balance, before.borrowed_balance);
+		assert_eq!(after.reserve_balance, before.reserve_balance);
+	});
+}
+
+#[test]
+fn test_on_idle_rotates_through_pools_within_the_weight_budget() {
+	endowed().build_and_execute(|| {
+		setup_pools();
+		run_to_block(100_000);
+		let budget = <() as WeightInfo>::accrue_interest();
+
+		let mut rotation = vec![];
+		for fresh in 1..=ASSETS.len() {
+			assert_eq!(Lending::on_idle(System::block_number(), budget), budget);
+			assert_eq!(fresh_pools(), fresh);
+			rotation.push(LastIdleAccruedPool::<Test>::get().unwrap());
+		}
+		let mut visited = rotation.clone();
+		visited.sort();
+		assert_eq!(visited, ASSETS.to_vec());
+
+		// the rotation starts over after the last pool
+		run_to_block(200_000);
+		assert_eq!(Lending::on_idle(System::block_number(), budget), budget);
+		assert_eq!(LastIdleAccruedPool::<Test>::get(), Some(rotation[0]));
+		assert_eq!(fresh_pools(), 1);
+	});
+}
+
+#[test]
+fn test_on_idle_does_nothing_without_enough_weight() {
+	endowed().build_and_execute(|| {
+		setup_pools();
+		run_to_block(100_000);
+
+		let budget = <() as WeightInfo>::accrue_interest().saturating_sub(Weight::from_parts(1, 0));
+		assert_eq!(Lending::on_idle(System::block_number(), budget), Weight::zero());
+		assert_eq!(fresh_pools(), 0);
+		assert_eq!(LastIdleAccruedPool::<Test>::get(), None);
+	});
+}
+
+#[test]
+fn test_on_idle_leaves_pools_accrued_in_the_same_block_untouched() {
+	endowed().build_and_execute(|| {
+		setup_pools();
+		run_to_block(100_000);
+		Lending::on_idle(System::block_number(), Weight::MAX);
+		let pools: Vec<_> = LendingPoolStorage::<Test>::iter().collect();
+
+		Lending::on_idle(System::block_number(), Weight::MAX);
+
+		assert_eq!(LendingPoolStorage::<Test>::iter().collect::<Vec<_>>(), pools);
+	});
+}
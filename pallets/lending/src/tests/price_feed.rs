@@ -0,0 +1,246 @@
+use crate::{
+	tests::mock::*, AssetPrices, Authorities, Call, Error, Event, LendingPoolAssets, Pallet,
+	PRICE_FEED_URL_KEY,
+};
+use frame_support::{
+	assert_noop, assert_ok,
+	traits::{Get, Hooks, UnfilteredDispatchable},
+};
+use parking_lot::RwLock;
+use sp_core::offchain::{
+	testing::{OffchainState, PendingRequest},
+	StorageKind,
+};
+use sp_runtime::{testing::UintAuthorityId, traits::Zero, DispatchError, FixedU128};
+use std::sync::Arc;
+
+const FEEDER: AccountId = 4;
+const URL: &str = "https://prices.kylix.finance/usdt";
+
+fn set_price_feed_url() {
+	sp_io::offchain::local_storage_set(StorageKind::PERSISTENT, PRICE_FEED_URL_KEY, URL.as_bytes());
+}
+
+fn expect_price_feed_request(offchain_state: &Arc<RwLock<OffchainState>>, body: &str) {
+	offchain_state.write().expect_request(PendingRequest {
+		method: "GET".into(),
+		uri: URL.into(),
+		response: Some(body.as_bytes().to_vec()),
+		sent: true,
+		..Default::default()
+	});
+}
+
+fn parse(body: &str) -> Option<Vec<(AssetId, FixedU128)>> {
+	Pallet::<Test>::parse_price_feed(body.as_bytes(), &[DOT, KSM])
+}
+
+#[test]
+fn test_offchain_worker_submits_signed_price_update() {
+	let (mut ext, offchain_state, pool_state) = ExtBuilder::default().build_offchain();
+	ext.execute_with(|| {
+		UintAuthorityId::set_all_keys(vec![FEEDER]);
+		set_price_feed_url();
+		expect_price_feed_request(&offchain_state, r#"{"2": 6.42, "3": 21.5, "7": 1.0}"#);
+
+		Lending::offchain_worker(1);
+
+		let tx = pop_transaction(&pool_state);
+		assert!(pool_state.read().transactions.is_empty());
+		assert_eq!(tx.signature, Some((0, ())));
+		let prices = vec![
+			(DOT, FixedU128::from_rational(642, 100)),
+			(KSM, FixedU128::from_rational(215, 10)),
+		];
+		assert_eq!(
+			tx.call,
+			RuntimeCall::Lending(Call::set_asset_prices { base_asset: USDT, prices })
+		);
+
+		let RuntimeCall::Lending(call) = tx.call else { panic!("unexpected call") };
+		assert_noop!(
+			call.clone().dispatch_bypass_filter(RuntimeOrigin::signed(FEEDER)),
+			DispatchError::BadOrigin
+		);
+		set_authorities(vec![FEEDER]);
+		assert_ok!(call.dispatch_bypass_filter(RuntimeOrigin::signed(FEEDER)));
+		assert_eq!(AssetPrices::<Test>::get((DOT, USDT)), Some(FixedU128::from_rational(642, 100)));
+		assert_eq!(AssetPrices::<Test>::get((KSM, USDT)), Some(FixedU128::from_rational(215, 10)));
+	});
+}
+
+#[test]
+fn test_price_feed_is_rate_limited() {
+	let (mut ext, offchain_state, pool_state) = ExtBuilder::default().build_offchain();
+	ext.execute_with(|| {
+		UintAuthorityId::set_all_keys(vec![FEEDER]);
+		set_price_feed_url();
+
+		expect_price_feed_request(&offchain_state, r#"{"2": 6}"#);
+		Lending::offchain_worker(1);
+		assert_eq!(pool_state.read().transactions.len(), 1);
+
+		// within `PriceFeedInterval` of the last run no request is sent
+		Lending::offchain_worker(10);
+		assert_eq!(pool_state.read().transactions.len(), 1);
+
+		expect_price_feed_request(&offchain_state, r#"{"2": 7}"#);
+		Lending::offchain_worker(11);
+		assert_eq!(pool_state.read().transactions.len(), 2);
+	});
+}
+
+#[test]
+fn test_price_feed_is_disabled_without_url() {
+	let (mut ext, _, pool_state) = ExtBuilder::default().build_offchain();
+	ext.execute_with(|| {
+		UintAuthorityId::set_all_keys(vec![FEEDER]);
+
+		assert_eq!(Lending::feed_prices_offchain(1), Ok(false));
+		assert!(pool_state.read().transactions.is_empty());
+	});
+}
+
+#[test]
+fn test_price_feed_rejects_invalid_responses() {
+	let (mut ext, offchain_state, pool_state) = ExtBuilder::default().build_offchain();
+	ext.execute_with(|| {
+		UintAuthorityId::set_all_keys(vec![FEEDER]);
+		set_price_feed_url();
+		expect_price_feed_request(&offchain_state, "Internal Server Error");
+
+		assert!(Lending::feed_prices_offchain(1).is_err());
+		assert!(pool_state.read().transactions.is_empty());
+	});
+}
+
+#[test]
+fn test_price_update_skips_signed_liquidation_of_the_same_block() {
+	let (mut ext, offchain_state, pool_state) = ExtBuilder::default()
+		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (KSM, BOB, 1_000_000)])
+		.build_offchain();
+	ext.execute_with(|| {
+		UintAuthorityId::set_all_keys(vec![FEEDER]);
+		setup_active_pool(DOT, 100_000);
+		assert_ok!(Lending::set_asset_price(RuntimeOrigin::root(), DOT, USDT, FixedU128::from(1)));
+		assert_ok!(Lending::set_asset_price(RuntimeOrigin::root(), KSM, USDT, FixedU128::from(1)));
+		assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), DOT, 500, KSM));
+		assert_ok!(Lending::set_asset_price(RuntimeOrigin::root(), DOT, USDT, FixedU128::from(2)));
+		set_price_feed_url();
+		expect_price_feed_request(&offchain_state, r#"{"2": 6.42}"#);
+
+		// both would be signed with the same nonce
+		Lending::offchain_worker(1);
+		let tx = pop_transaction(&pool_state);
+		assert!(matches!(tx.call, RuntimeCall::Lending(Call::set_asset_prices { .. })));
+		assert!(pool_state.read().transactions.is_empty());
+
+		Lending::offchain_worker(2);
+		let tx = pop_transaction(&pool_state);
+		assert!(matches!(tx.call, RuntimeCall::Lending(Call::liquidate { .. })));
+	});
+}
+
+#[test]
+fn test_parse_price_feed() {
+	assert_eq!(
+		parse(r#"{"3": 0.000000000000000001, "2": 12}"#),
+		Some(vec![(KSM, FixedU128::from_inner(1)), (DOT, FixedU128::from(12))])
+	);
+	// unknown assets, invalid ids and invalid prices are skipped
+	assert_eq!(
+		parse(
+			r#"{"4": 1, "x": 1, "": 1, "2": -1.5, "3": 1e3, "2": 0, "3": "5", "2": 1.0000000000000000001}"#
+		),
+		Some(vec![])
+	);
+	assert_eq!(parse("[6.42]"), None);
+	assert_eq!(parse("not json"), None);
+}
+
+#[test]
+fn test_set_asset_prices() {
+	ExtBuilder::default().build_and_execute(|| {
+		let prices = vec![(DOT, FixedU128::from(6)), (KSM, FixedU128::from(21))];
+		assert_noop!(
+			Lending::set_asset_prices(RuntimeOrigin::signed(FEEDER), USDT, prices.clone()),
+			DispatchError::BadOrigin
+		);
+		set_authorities(vec![FEEDER]);
+		assert_ok!(Lending::set_asset_prices(RuntimeOrigin::signed(FEEDER), USDT, prices));
+
+		assert_eq!(AssetPrices::<Test>::get((DOT, USDT)), Some(FixedU128::from(6)));
+		assert_eq!(AssetPrices::<Test>::get((KSM, USDT)), Some(FixedU128::from(21)));
+		System::assert_last_event(
+			Event::AssetPriceAdded { asset: KSM, base_asset: USDT, price: FixedU128::from(21) }
+				.into(),
+		);
+	});
+}
+
+#[test]
+fn test_set_asset_price_requires_the_manager_origin_or_an_authority() {
+	ExtBuilder::default().build_and_execute(|| {
+		assert_noop!(
+			Lending::set_asset_price(RuntimeOrigin::signed(ALICE), DOT, USDT, FixedU128::from(6)),
+			DispatchError::BadOrigin
+		);
+		assert_ok!(Lending::set_asset_price(RuntimeOrigin::root(), DOT, USDT, FixedU128::from(6)));
+		assert_eq!(AssetPrices::<Test>::get((DOT, USDT)), Some(FixedU128::from(6)));
+
+		set_authorities(vec![FEEDER]);
+		assert_ok!(Lending::set_asset_price(
+			RuntimeOrigin::signed(FEEDER),
+			DOT,
+			USDT,
+			FixedU128::from(7)
+		));
+		assert_eq!(AssetPrices::<Test>::get((DOT, USDT)), Some(FixedU128::from(7)));
+	});
+}
+
+#[test]
+fn test_set_authorities_requires_the_manager_origin() {
+	ExtBuilder::default().build_and_execute(|| {
+		assert_noop!(
+			Lending::set_authorities(
+				RuntimeOrigin::signed(FEEDER),
+				vec![FEEDER].try_into().unwrap()
+			),
+			DispatchError::BadOrigin
+		);
+		set_authorities(vec![FEEDER]);
+		assert_eq!(Authorities::<Test>::get().into_inner(), vec![FEEDER]);
+		System::assert_last_event(Event::AuthoritiesSet { authorities: vec![FEEDER] }.into());
+	});
+}
+
+#[test]
+fn test_set_asset_prices_rejects_zero_price() {
+	ExtBuilder::default().build_and_execute(|| {
+		let prices = vec![(DOT, FixedU128::from(6)), (KSM, FixedU128::zero())];
+		assert_noop!(
+			Lending::set_asset_prices(RuntimeOrigin::root(), USDT, prices),
+			Error::<Test>::InvalidAssetPrice
+		);
+		assert_eq!(AssetPrices::<Test>::get((DOT, USDT)), None);
+	});
+}
+
+#[test]
+fn test_lending_pool_assets_exclude_usdt() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![(USDT, ALICE, 1_000_000), (DOT, ALICE, 1_000_000)])
+		.build_and_execute(|| {
+			assert!(LendingPoolAssets::<Test>::get().is_empty());
+
+			setup_active_pool(USDT, 1_000);
+			assert_ok!(Lending::create_lending_pool(
+				RuntimeOrigin::signed(ALICE),
+				LENDING_POOL_TOKEN + 1,
+				DOT,
+				1_000
+			));
+			assert_eq!(LendingPoolAssets::<Test>::get(), vec![DOT]);
+		});
+}
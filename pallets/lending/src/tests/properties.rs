@@ -0,0 +1,238 @@
+//! Property based tests of the lending math and of random sequences of user actions driven
+//! through the mock runtime.
+use crate::{
+	tests::mock::*, AssetPool, InterestRateModel, LendingPool, LendingPoolStorage, UserBorrow,
+};
+use frame_support::{assert_ok, traits::fungibles::Inspect};
+use num_traits::{One, Zero};
+use proptest::prelude::*;
+use sp_runtime::{traits::AccountIdConversion, FixedPointNumber, FixedU128};
+
+const ACCOUNTS: [AccountId; 2] = [ALICE, BOB];
+const ASSETS: [AssetId; 2] = [DOT, KSM];
+const INITIAL_BALANCE: Balance = 1_000_000_000;
+
+type Balance = u128;
+
+/// A user action applied to the mock runtime
+#[derive(Clone, Debug)]
+enum Action {
+	Supply { who: AccountId, asset: AssetId, balance: Balance },
+	Withdraw { who: AccountId, asset: AssetId, balance: Balance },
+	Borrow { who: AccountId, asset: AssetId, balance: Balance },
+	Repay { who: AccountId, asset: AssetId, balance: Balance },
+	AdvanceBlocks(u64),
+}
+
+fn action() -> impl Strategy<Value = Action> {
+	let who = prop::sample::select(ACCOUNTS.to_vec());
+	let asset = prop::sample::select(ASSETS.to_vec());
+	let balance = 1..INITIAL_BALANCE / 10;
+	prop_oneof![
+		(who.clone(), asset.clone(), balance.clone())
+			.prop_map(|(who, asset, balance)| Action::Supply { who, asset, balance }),
+		(who.clone(), asset.clone(), balance.clone())
+			.prop_map(|(who, asset, balance)| Action::Withdraw { who, asset, balance }),
+		(who.clone(), asset.clone(), balance.clone())
+			.prop_map(|(who, asset, balance)| Action::Borrow { who, asset, balance }),
+		(who, asset, balance).prop_map(|(who, asset, balance)| Action::Repay {
+			who,
+			asset,
+			balance
+		}),
+		// from one block up to roughly two years
+		(1u64..10_000_000).prop_map(Action::AdvanceBlocks),
+	]
+}
+
+/// The other asset of the two, used as collateral
+fn collateral_of(asset: AssetId) -> AssetId {
+	if asset == DOT {
+		KSM
+	} else {
+		DOT
+	}
+}
+
+/// Total amount of `asset` held by the users and the pallet
+fn total_issuance_held(asset: AssetId) -> Balance {
+	let pallet_account: AccountId = KylixPalletId::get().into_account_truncating();
+	ACCOUNTS
+		.iter()
+		.chain(core::iter::once(&pallet_account))
+		.map(|who| Fungibles::balance(asset, who))
+		.sum()
+}
+
+fn apply(action: &Action) {
+	// Failing actions are expected, e.g. withdrawing more than supplied, they must not panic
+	let _ = match *action {
+		Action::Supply { who, asset, balance } =>
+			Lending::supply(RuntimeOrigin::signed(who), asset, balance),
+		Action::Withdraw { who, asset, balance } =>
+			Lending::withdraw(RuntimeOrigin::signed(who), asset, balance),
+		Action::Borrow { who, asset, balance } =>
+			Lending::borrow(RuntimeOrigin::signed(who), asset, balance, collateral_of(asset)),
+		Action::Repay { who, asset, balance } =>
+			Lending::repay(RuntimeOrigin::signed(who), asset, balance, collateral_of(asset)),
+		Action::AdvanceBlocks(blocks) => {
+			run_to_block(System::block_number() + blocks);
+			Ok(())
+		},
+	};
+}
+
+proptest! {
+	#![proptest_config(ProptestConfig::with_cases(64))]
+
+	#[test]
+	fn random_actions_conserve_funds_and_keep_invariants(
+		actions in prop::collection::vec(action(), 1..40)
+	) {
+		let balances = ACCOUNTS
+			.iter()
+			.flat_map(|who| ASSETS.iter().map(move |asset| (*asset, *who, INITIAL_BALANCE)))
+			.collect();
+		ExtBuilder::default().with_endowed_balances(balances).build_and_execute(|| {
+			setup_active_pool(DOT, INITIAL_BALANCE / 10);
+			assert_ok!(Lending::create_lending_pool(
+				RuntimeOrigin::signed(ALICE),
+				LENDING_POOL_TOKEN + 1,
+				KSM,
+				INITIAL_BALANCE / 10
+			));
+			assert_ok!(Lending::activate_lending_pool(RuntimeOrigin::signed(ALICE), KSM));
+			assert_ok!(Lending::set_asset_price(
+				RuntimeOrigin::root(),
+				DOT,
+				KSM,
+				FixedU128::from_rational(1, 2)
+			));
+
+			let issuance: Vec<Balance> =
+				ASSETS.iter().map(|asset| total_issuance_held(*asset)).collect();
+
+			for action in actions.iter() {
+				apply(action);
+
+				// No asset is created or destroyed by the pallet
+				for (asset, expected) in ASSETS.iter().zip(issuance.iter()) {
+					assert_eq!(total_issuance_held(*asset), *expected, "after {:?}", action);
+				}
+				assert_ok!(Lending::do_try_state());
+				assert_indexes_monotonic();
+			}
+		});
+	}
+
+	#[test]
+	fn exp_is_at_least_linear(x in 0u128..FixedU128::DIV) {
+		ExtBuilder::default().build().execute_with(|| {
+			let pool = LendingPool::<Test>::from(0, DOT, 1).unwrap();
+			let x = FixedU128::from_inner(x);
+			let exp = pool.exp_fixed_u128(x).unwrap();
+			assert!(exp >= FixedU128::one() + x);
+		});
+	}
+
+	#[test]
+	fn cosine_interest_is_bounded(utilization in 0u128..=FixedU128::DIV) {
+		let model = InterestRateModel::default();
+		let rate = model.calculate_cosine_interest(FixedU128::from_inner(utilization)).unwrap();
+		let min = model.ym.min(model.y0).min(model.y1);
+		let max = model.ym.max(model.y0).max(model.y1);
+		assert!(rate >= min && rate <= max);
+	}
+
+	#[test]
+	fn cosine_interest_rejects_utilization_above_one(excess in 1u128..u64::MAX as u128) {
+		let model = InterestRateModel::default();
+		let utilization = FixedU128::from_inner(FixedU128::DIV + excess);
+		assert!(model.calculate_cosine_interest(utilization).is_err());
+	}
+
+	#[test]
+	fn interest_factors_never_decrease_balances(
+		borrowed in 0u128..1_000_000_000_000,
+		reserve in 1u128..1_000_000_000_000,
+		elapsed in 0u64..(10 * crate::SECONDS_PER_YEAR),
+	) {
+		ExtBuilder::default().build().execute_with(|| {
+			Timestamp::set_timestamp(20 * crate::SECONDS_PER_YEAR * 1_000);
+			let mut pool = LendingPool::<Test>::from(0, DOT, reserve).unwrap();
+			pool.borrowed_balance = borrowed;
+			let borrow_rate = pool.borrow_interest_rate().unwrap();
+			let supply_rate = pool.supply_interest_rate().unwrap();
+
+			let linear = pool.calculate_linear_interest(supply_rate, elapsed).unwrap();
+			assert!(linear >= FixedU128::one());
+			// the default model borrows at most at `y1` = 15%, so over 10 years the exponent stays
+			// below 1.5, well within the domain of `exp_fixed_u128`
+			let compounded = pool.calculate_compunded_interest(borrow_rate, elapsed).unwrap();
+			assert!(compounded >= FixedU128::one());
+			assert!(compounded >= linear || borrow_rate.is_zero());
+		});
+	}
+
+	#[test]
+	fn scaled_supply_round_trip_never_creates_funds(
+		deposit in 0u128..u64::MAX as u128,
+		index in FixedU128::DIV..10 * FixedU128::DIV,
+	) {
+		ExtBuilder::default().build().execute_with(|| {
+			let mut pool = LendingPool::<Test>::from(0, DOT, 1).unwrap();
+			pool.supply_index = FixedU128::from_inner(index);
+
+			let scaled = pool.scaled_supply_balance(deposit).unwrap();
+			let accrued = pool.accrued_deposit(scaled).unwrap();
+			// rounding always favours the pool and loses at most `index + 1` units
+			assert!(accrued <= deposit);
+			assert!(accrued + 11 >= deposit);
+		});
+	}
+
+	#[test]
+	fn repayable_amount_covers_the_borrowed_amount(
+		borrowed in 0u128..u64::MAX as u128,
+		index in FixedU128::DIV..10 * FixedU128::DIV,
+		later_index in FixedU128::DIV..10 * FixedU128::DIV,
+	) {
+		ExtBuilder::default().build().execute_with(|| {
+			let mut pool = LendingPool::<Test>::from(0, DOT, 1).unwrap();
+			pool.borrow_index = FixedU128::from_inner(index);
+
+			let loan = UserBorrow::<Test> {
+				borrowed_asset: DOT,
+				borrowed_balance: pool.scaled_borrow_balance(borrowed).unwrap(),
+				collateral_asset: KSM,
+				collateral_balance: Zero::zero(),
+				principal_balance: borrowed,
+			};
+			let repayable = pool.repayable_amount(&loan).unwrap();
+			// rounding always favours the pool and adds at most `index + 1` units
+			assert!(repayable >= borrowed);
+			assert!(repayable <= borrowed + 11);
+			// and repaying the borrowed amount settles at most the debt it recorded
+			assert!(pool.scaled_repaid_balance(borrowed).unwrap() <= loan.borrowed_balance);
+
+			// the debt only grows while the index grows
+			pool.borrow_index = FixedU128::from_inner(index.max(later_index));
+			assert!(pool.repayable_amount(&loan).unwrap() >= repayable);
+		});
+	}
+}
+
+#[test]
+fn pool_storage_is_untouched_by_failed_actions() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![(DOT, ALICE, INITIAL_BALANCE)])
+		.build_and_execute(|| {
+			setup_active_pool(DOT, 1_000);
+			let pool = LendingPoolStorage::<Test>::get(AssetPool::<Test>::from(DOT));
+
+			apply(&Action::Withdraw { who: BOB, asset: DOT, balance: 500 });
+			apply(&Action::Repay { who: BOB, asset: DOT, balance: 500 });
+
+			assert_eq!(LendingPoolStorage::<Test>::get(AssetPool::<Test>::from(DOT)), pool);
+		});
+}
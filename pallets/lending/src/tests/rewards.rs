@@ -0,0 +1,89 @@
+use crate::{tests::mock::*, Error, Event, RewardSpeed, RewardSpeeds, SECONDS_PER_YEAR};
+use frame_support::{assert_noop, assert_ok};
+use sp_runtime::{traits::Zero, DispatchError, FixedU128};
+
+fn setup_borrowed_pool() {
+	setup_active_pool(DOT, 100_000);
+	assert_ok!(Lending::set_asset_price(RuntimeOrigin::root(), DOT, KSM, FixedU128::from(1)));
+	assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), DOT, 500, KSM));
+}
+
+#[test]
+fn test_set_reward_speed() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000)])
+		.build_and_execute(|| {
+			setup_active_pool(DOT, 100_000);
+
+			assert_ok!(Lending::set_reward_speed(RuntimeOrigin::root(), DOT, 3, 5));
+
+			assert_eq!(
+				RewardSpeeds::<Test>::get(DOT),
+				RewardSpeed { supply_speed: 3, borrow_speed: 5 }
+			);
+			System::assert_last_event(
+				Event::RewardSpeedUpdated { asset: DOT, supply_speed: 3, borrow_speed: 5 }.into(),
+			);
+		});
+}
+
+#[test]
+fn test_set_reward_speed_fails() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000)])
+		.build_and_execute(|| {
+			assert_noop!(
+				Lending::set_reward_speed(RuntimeOrigin::root(), DOT, 3, 5),
+				Error::<Test>::LendingPoolDoesNotExist
+			);
+
+			setup_active_pool(DOT, 100_000);
+			assert_noop!(
+				Lending::set_reward_speed(RuntimeOrigin::signed(ALICE), DOT, 3, 5),
+				DispatchError::BadOrigin
+			);
+		});
+}
+
+#[test]
+fn test_lending_pools_return_reward_apys() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (KSM, BOB, 1_000_000)])
+		.build_and_execute(|| {
+			setup_borrowed_pool();
+			assert_ok!(Lending::set_reward_speed(RuntimeOrigin::root(), DOT, 1, 3));
+			// 1 KYL = 2 DOT
+			assert_ok!(Lending::set_asset_price(
+				RuntimeOrigin::root(),
+				KYL,
+				DOT,
+				FixedU128::from(2)
+			));
+
+			let (pools, _) = Lending::get_lending_pools(Some(DOT), None, None, None, false);
+
+			// the yearly rewards, valued in DOT, over the 100_000 DOT supplied and the 500 borrowed
+			let yearly = 2 * SECONDS_PER_YEAR as u128;
+			assert_eq!(pools[0].supply_apy_s, FixedU128::from_rational(yearly, 100_000));
+			assert_eq!(pools[0].borrow_apy_s, FixedU128::from_rational(3 * yearly, 500));
+		});
+}
+
+#[test]
+fn test_reward_apys_are_zero_without_speed_or_price() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (KSM, BOB, 1_000_000)])
+		.build_and_execute(|| {
+			setup_borrowed_pool();
+
+			let (pools, _) = Lending::get_lending_pools(Some(DOT), None, None, None, false);
+			assert!(pools[0].supply_apy_s.is_zero());
+			assert!(pools[0].borrow_apy_s.is_zero());
+
+			// the price of the reward asset is not set
+			assert_ok!(Lending::set_reward_speed(RuntimeOrigin::root(), DOT, 1, 3));
+			let (pools, _) = Lending::get_lending_pools(Some(DOT), None, None, None, false);
+			assert!(pools[0].supply_apy_s.is_zero());
+			assert!(pools[0].borrow_apy_s.is_zero());
+		});
+}
@@ -1,6 +1,6 @@
-use crate::{tests::mock::*, AssetPool};
-use frame_support::assert_ok;
-use num_traits::Zero;
+use crate::{tests::mock::*, AssetPool, Error, LendingApiError, LendingPoolInfo, LoanPosition};
+use frame_support::{assert_noop, assert_ok};
+use num_traits::{One, Zero};
 use sp_runtime::{FixedPointNumber, FixedU128};
 
 #[test]
@@ -11,8 +11,7 @@ fn test_compute_user_ltv_on_max_borrow() {
 			(DOT, BOB, 1_000_000),
 			(KSM, BOB, 1_000_000),
 		])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			// Setup and activate the DOT lending pool
 			setup_active_pool(DOT, 1000);
 
@@ -25,7 +24,7 @@ fn test_compute_user_ltv_on_max_borrow() {
 
 			// Set DOT price in terms of USDT: 1 DOT = 1 USDT
 			assert_ok!(Lending::set_asset_price(
-				RuntimeOrigin::signed(ALICE),
+				RuntimeOrigin::root(),
 				DOT,
 				USDT,
 				FixedU128::from_rational(1, 1), // 1 DOT = 1 USDT
@@ -33,13 +32,13 @@ fn test_compute_user_ltv_on_max_borrow() {
 
 			// Set KSM price in terms of USDT: 1 KSM = 2 USDT
 			assert_ok!(Lending::set_asset_price(
-				RuntimeOrigin::signed(ALICE),
+				RuntimeOrigin::root(),
 				KSM,
 				USDT,
 				FixedU128::from_rational(2, 1), // 1 KSM = 2 USDT
 			));
 
-			assert_ok!(Lending::set_asset_price(RuntimeOrigin::signed(ALICE), DOT, KSM, price));
+			assert_ok!(Lending::set_asset_price(RuntimeOrigin::root(), DOT, KSM, price));
 
 			assert_ok!(Lending::supply(RuntimeOrigin::signed(BOB), DOT, 1_000));
 			let ksm_collateral_amount =
@@ -65,11 +64,76 @@ fn test_compute_user_ltv_on_max_borrow() {
 		});
 }
 
+#[test]
+fn test_loan_positions_and_weighted_health_factor() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![
+			(DOT, ALICE, 1_000_000),
+			(KSM, BOB, 1_000_000),
+			(KYL, BOB, 1_000_000),
+		])
+		.build_and_execute(|| {
+			setup_active_pool(DOT, 100_000);
+			for (asset, price) in [(DOT, 1), (KSM, 2), (KYL, 4)] {
+				assert_ok!(Lending::set_asset_price(
+					RuntimeOrigin::root(),
+					asset,
+					USDT,
+					FixedU128::from_u32(price)
+				));
+			}
+			assert_eq!(Lending::get_loan_positions(&BOB), vec![]);
+			assert_eq!(Lending::weighted_health_factor(&[]), None);
+
+			// 500 DOT against 500 KSM and 1000 DOT against 500 KYL, both at a LTV of 50%
+			assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), DOT, 500, KSM));
+			assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), DOT, 1_000, KYL));
+			let position = |collateral_asset, debt: u128, collateral: u128| LoanPosition {
+				borrowed_asset: DOT,
+				collateral_asset,
+				debt,
+				debt_usdt: debt,
+				collateral,
+				collateral_usdt: debt * 2,
+				ltv: FixedU128::from_rational(1, 2),
+				sale_ltv: FixedU128::from_rational(1, 2),
+				liquidation_ltv: FixedU128::from_rational(4, 5),
+				health_factor: Some(FixedU128::from_rational(8, 5)),
+				// the debt over the collateral at the liquidation threshold
+				liquidation_price: Some(FixedU128::from_rational(debt * 5, collateral * 4)),
+			};
+			let mut positions = Lending::get_loan_positions(&BOB);
+			positions.sort_by_key(|position| position.collateral_asset);
+			assert_eq!(positions, vec![position(KSM, 500, 500), position(KYL, 1_000, 500)]);
+			assert_eq!(
+				Lending::weighted_health_factor(&positions),
+				Some(FixedU128::from_rational(8, 5))
+			);
+
+			// KSM falls below the liquidation price of its loan, but the KYL loan still covers
+			// the debt: (0.8 * 250 + 0.8 * 2000) / 1500
+			assert_ok!(Lending::set_asset_price(
+				RuntimeOrigin::root(),
+				KSM,
+				USDT,
+				FixedU128::from_rational(1, 2)
+			));
+			let mut positions = Lending::get_loan_positions(&BOB);
+			positions.sort_by_key(|position| position.collateral_asset);
+			assert_eq!(positions[0].collateral_usdt, 250);
+			assert_eq!(positions[0].health_factor, Some(FixedU128::from_rational(2, 5)));
+			assert_eq!(
+				Lending::weighted_health_factor(&positions),
+				Some(FixedU128::from_rational(6, 5))
+			);
+		});
+}
+
 #[test]
 fn test_get_asset_price_with_usdt() {
-	ExtBuilder::default().build().execute_with(|| {
+	ExtBuilder::default().build_and_execute(|| {
 		assert_ok!(Lending::set_asset_price(
-			RuntimeOrigin::signed(ALICE),
+			RuntimeOrigin::root(),
 			DOT,
 			USDT,
 			FixedU128::from_rational(10, 1), // 1 DOT = 10 USDT
@@ -85,10 +149,10 @@ fn test_get_asset_price_with_usdt() {
 
 #[test]
 fn test_get_asset_price_with_base() {
-	ExtBuilder::default().build().execute_with(|| {
+	ExtBuilder::default().build_and_execute(|| {
 		// Set DOT price in terms of USDT: 1 DOT = 10 USDT
 		assert_ok!(Lending::set_asset_price(
-			RuntimeOrigin::signed(ALICE),
+			RuntimeOrigin::root(),
 			DOT,
 			USDT,
 			FixedU128::from_rational(10, 1),
@@ -96,7 +160,7 @@ fn test_get_asset_price_with_base() {
 
 		// Set KSM price in terms of USDT: 1 KSM = 20 USDT
 		assert_ok!(Lending::set_asset_price(
-			RuntimeOrigin::signed(ALICE),
+			RuntimeOrigin::root(),
 			KSM,
 			USDT,
 			FixedU128::from_rational(20, 1),
@@ -112,7 +176,7 @@ fn test_get_asset_price_with_base() {
 
 #[test]
 fn test_get_asset_price_with_error() {
-	ExtBuilder::default().build().execute_with(|| {
+	ExtBuilder::default().build_and_execute(|| {
 		let err_price = Lending::get_asset_price(DOT, KSM);
 		assert!(matches!(err_price, Err(_)));
 	});
@@ -126,8 +190,7 @@ fn test_get_asset_wise_supplies_with_one_supply() {
 			(DOT, BOB, 1_000_000),
 			(KSM, BOB, 1_000_000),
 		])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			// Setup and activate the DOT lending pool
 			setup_active_pool(DOT, 1000);
 
@@ -136,7 +199,7 @@ fn test_get_asset_wise_supplies_with_one_supply() {
 			let dot_borrow_amount = 500;
 			// Set DOT price in terms of USDT: 1 DOT = 1 USDT
 			assert_ok!(Lending::set_asset_price(
-				RuntimeOrigin::signed(ALICE),
+				RuntimeOrigin::root(),
 				DOT,
 				USDT,
 				FixedU128::from_rational(1, 1), // 1 DOT = 1 USDT
@@ -144,13 +207,13 @@ fn test_get_asset_wise_supplies_with_one_supply() {
 
 			// Set KSM price in terms of USDT: 1 KSM = 2 USDT
 			assert_ok!(Lending::set_asset_price(
-				RuntimeOrigin::signed(ALICE),
+				RuntimeOrigin::root(),
 				KSM,
 				USDT,
 				FixedU128::from_rational(2, 1), // 1 KSM = 2 USDT
 			));
 
-			assert_ok!(Lending::set_asset_price(RuntimeOrigin::signed(ALICE), DOT, KSM, price));
+			assert_ok!(Lending::set_asset_price(RuntimeOrigin::root(), DOT, KSM, price));
 
 			assert_ok!(Lending::supply(RuntimeOrigin::signed(BOB), DOT, 1_000));
 
@@ -204,8 +267,7 @@ fn test_get_asset_wise_supplies_with_two_supply() {
 			(DOT, BOB, 1_000_000),
 			(KSM, BOB, 1_000_000),
 		])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			// Setup and activate the DOT lending pool
 			setup_active_pool(DOT, 1000);
 			assert_ok!(Lending::create_lending_pool(
@@ -218,7 +280,7 @@ fn test_get_asset_wise_supplies_with_two_supply() {
 
 			// Set DOT price in terms of USDT: 1 DOT = 1 USDT
 			assert_ok!(Lending::set_asset_price(
-				RuntimeOrigin::signed(ALICE),
+				RuntimeOrigin::root(),
 				DOT,
 				USDT,
 				FixedU128::from_rational(1, 1), // 1 DOT = 1 USDT
@@ -226,7 +288,7 @@ fn test_get_asset_wise_supplies_with_two_supply() {
 
 			// Set KSM price in terms of USDT: 1 KSM = 2 USDT
 			assert_ok!(Lending::set_asset_price(
-				RuntimeOrigin::signed(ALICE),
+				RuntimeOrigin::root(),
 				KSM,
 				USDT,
 				FixedU128::from_rational(2, 1),
@@ -275,15 +337,14 @@ fn test_get_asset_wise_borrows_collaterals_with_one_borrow() {
 			(DOT, BOB, 1_000_000),
 			(KSM, BOB, 1_000_000),
 		])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			// Setup and activate the DOT lending pool
 			setup_active_pool(DOT, 1000);
 
 			let dot_borrow_amount = 500;
 			// Set DOT price in terms of USDT: 1 DOT = 1 USDT
 			assert_ok!(Lending::set_asset_price(
-				RuntimeOrigin::signed(ALICE),
+				RuntimeOrigin::root(),
 				DOT,
 				USDT,
 				FixedU128::from_rational(1, 1), // 1 DOT = 1 USDT
@@ -291,7 +352,7 @@ fn test_get_asset_wise_borrows_collaterals_with_one_borrow() {
 
 			// Set KSM price in terms of USDT: 1 KSM = 2 USDT
 			assert_ok!(Lending::set_asset_price(
-				RuntimeOrigin::signed(ALICE),
+				RuntimeOrigin::root(),
 				KSM,
 				USDT,
 				FixedU128::from_rational(2, 1),
@@ -347,8 +408,7 @@ fn test_get_asset_wise_borrows_collaterals_with_two_borrows() {
 			(DOT, BOB, 1_000_000),
 			(KSM, BOB, 1_000_000),
 		])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			// Setup and activate the DOT lending pool
 			setup_active_pool(DOT, 1000);
 			assert_ok!(Lending::create_lending_pool(
@@ -363,7 +423,7 @@ fn test_get_asset_wise_borrows_collaterals_with_two_borrows() {
 			let ksm_borrow_amount_2 = 1000;
 			// Set DOT price in terms of USDT: 1 DOT = 1 USDT
 			assert_ok!(Lending::set_asset_price(
-				RuntimeOrigin::signed(ALICE),
+				RuntimeOrigin::root(),
 				DOT,
 				USDT,
 				FixedU128::from_rational(1, 1), // 1 DOT = 1 USDT
@@ -371,7 +431,7 @@ fn test_get_asset_wise_borrows_collaterals_with_two_borrows() {
 
 			// Set KSM price in terms of USDT: 1 KSM = 2 USDT
 			assert_ok!(Lending::set_asset_price(
-				RuntimeOrigin::signed(ALICE),
+				RuntimeOrigin::root(),
 				KSM,
 				USDT,
 				FixedU128::from_rational(2, 1),
@@ -467,8 +527,7 @@ fn test_get_lending_pools_without_params() {
 			(DOT, BOB, 1_000_000),
 			(KSM, BOB, 1_000_000),
 		])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			// Setup and activate the DOT lending pool
 			// setup_active_pool(DOT, 1000);
 			let initial_balance = 1000;
@@ -495,7 +554,7 @@ fn test_get_lending_pools_without_params() {
 			let ksm_supplied = 1000;
 			// Set DOT price in terms of USDT: 1 DOT = 1 USDT
 			assert_ok!(Lending::set_asset_price(
-				RuntimeOrigin::signed(ALICE),
+				RuntimeOrigin::root(),
 				DOT,
 				USDT,
 				FixedU128::from_rational(1, 1), // 1 DOT = 1 USDT
@@ -503,7 +562,7 @@ fn test_get_lending_pools_without_params() {
 
 			// Set KSM price in terms of USDT: 1 KSM = 2 USDT
 			assert_ok!(Lending::set_asset_price(
-				RuntimeOrigin::signed(ALICE),
+				RuntimeOrigin::root(),
 				KSM,
 				USDT,
 				FixedU128::from_rational(2, 1),
@@ -528,7 +587,7 @@ fn test_get_lending_pools_without_params() {
 			));
 
 			// case without parameters, all lending pools
-			let (lending_pools, totals) = Lending::get_lending_pools(None, None);
+			let (lending_pools, totals) = Lending::get_lending_pools(None, None, None, None, false);
 
 			assert_eq!(lending_pools.len(), 2);
 
@@ -581,8 +640,7 @@ fn test_get_lending_pools_with_asset_param() {
 			(DOT, BOB, 1_000_000),
 			(KSM, BOB, 1_000_000),
 		])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			// Setup and activate the DOT lending pool
 			// setup_active_pool(DOT, 1000);
 			let initial_balance = 1000;
@@ -609,7 +667,7 @@ fn test_get_lending_pools_with_asset_param() {
 			let ksm_supplied = 1000;
 			// Set DOT price in terms of USDT: 1 DOT = 1 USDT
 			assert_ok!(Lending::set_asset_price(
-				RuntimeOrigin::signed(ALICE),
+				RuntimeOrigin::root(),
 				DOT,
 				USDT,
 				FixedU128::from_rational(1, 1), // 1 DOT = 1 USDT
@@ -617,7 +675,7 @@ fn test_get_lending_pools_with_asset_param() {
 
 			// Set KSM price in terms of USDT: 1 KSM = 2 USDT
 			assert_ok!(Lending::set_asset_price(
-				RuntimeOrigin::signed(ALICE),
+				RuntimeOrigin::root(),
 				KSM,
 				USDT,
 				FixedU128::from_rational(2, 1),
@@ -642,7 +700,7 @@ fn test_get_lending_pools_with_asset_param() {
 			));
 
 			// case with asset id
-			let (lending_pools, totals) = Lending::get_lending_pools(Some(DOT), None);
+			let (lending_pools, totals) = Lending::get_lending_pools(Some(DOT), None, None, None, false);
 
 			assert_eq!(lending_pools.len(), 1);
 
@@ -673,8 +731,7 @@ fn test_get_lending_pools_with_account_and_asset() {
 			(DOT, BOB, 1_000_000),
 			(KSM, BOB, 1_000_000),
 		])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			// Setup and activate the DOT lending pool
 			// setup_active_pool(DOT, 4333);
 			let initial_balance = 4333;
@@ -699,7 +756,7 @@ fn test_get_lending_pools_with_account_and_asset() {
 			let ksm_supplied = 1234;
 			// Set DOT price in terms of USDT: 1 DOT = 1 USDT
 			assert_ok!(Lending::set_asset_price(
-				RuntimeOrigin::signed(ALICE),
+				RuntimeOrigin::root(),
 				DOT,
 				USDT,
 				FixedU128::from_rational(1, 1), // 1 DOT = 1 USDT
@@ -707,7 +764,7 @@ fn test_get_lending_pools_with_account_and_asset() {
 
 			// Set KSM price in terms of USDT: 1 KSM = 1 USDT
 			assert_ok!(Lending::set_asset_price(
-				RuntimeOrigin::signed(ALICE),
+				RuntimeOrigin::root(),
 				KSM,
 				USDT,
 				FixedU128::from_rational(1, 1),
@@ -732,7 +789,8 @@ fn test_get_lending_pools_with_account_and_asset() {
 			));
 
 			// case with asset id
-			let (lending_pools, totals) = Lending::get_lending_pools(Some(DOT), Some(&BOB));
+			let (lending_pools, totals) =
+				Lending::get_lending_pools(Some(DOT), Some(&BOB), None, None, false);
 			assert_eq!(lending_pools.len(), 1);
 			// DOT
 			let dot_lending_pool = lending_pools.first().unwrap();
@@ -750,7 +808,8 @@ fn test_get_lending_pools_with_account_and_asset() {
 			assert_eq!(totals.total_supply, initial_balance - dot_borrow_amount_1);
 			assert_eq!(totals.total_borrow, dot_borrow_amount_1);
 
-			let (lending_pools, totals) = Lending::get_lending_pools(Some(KSM), Some(&ALICE));
+			let (lending_pools, totals) =
+				Lending::get_lending_pools(Some(KSM), Some(&ALICE), None, None, false);
 			assert_eq!(lending_pools.len(), 1);
 			// KSM
 			let ksm_lending_pool = lending_pools.first().unwrap();
@@ -784,14 +843,13 @@ fn test_get_estimate_collateral_amount() {
 			(KSM, ALICE, 1_000_000),
 			(DOT, BOB, 1_000_000),
 		])
-		.build()
-		.execute_with(|| {
+		.build_and_execute(|| {
 			// Setup and activate the DOT lending pool
 			setup_active_pool(DOT, 1000);
 
 			// Set DOT price in terms of USDT: 1 DOT = 10 USDT
 			assert_ok!(Lending::set_asset_price(
-				RuntimeOrigin::signed(ALICE),
+				RuntimeOrigin::root(),
 				DOT,
 				USDT,
 				FixedU128::from_rational(10, 1),
@@ -799,7 +857,7 @@ fn test_get_estimate_collateral_amount() {
 
 			// Set KSM price in terms of USDT: 1 KSM = 5 USDT
 			assert_ok!(Lending::set_asset_price(
-				RuntimeOrigin::signed(ALICE),
+				RuntimeOrigin::root(),
 				KSM,
 				USDT,
 				FixedU128::from_rational(5, 1),
@@ -817,8 +875,225 @@ fn test_get_estimate_collateral_amount() {
 
 #[test]
 fn test_get_estimate_collateral_amount_with_error() {
-	ExtBuilder::default().build().execute_with(|| {
+	ExtBuilder::default().build_and_execute(|| {
 		let err_amount = Lending::estimate_collateral_amount(DOT, 100, KSM);
 		assert!(matches!(err_amount, Err(_)));
 	});
 }
+
+#[test]
+fn test_lending_api_error_from_pallet_error() {
+	ExtBuilder::default().build_and_execute(|| {
+		let err = Lending::estimate_collateral_amount(DOT, 100, KSM).unwrap_err();
+		assert_eq!(
+			LendingApiError::from_pallet_error(err, DOT, KSM),
+			LendingApiError::PoolNotFound { asset: DOT }
+		);
+
+		let err = Lending::get_asset_price(DOT, KSM).unwrap_err();
+		assert_eq!(
+			LendingApiError::from_pallet_error(err, DOT, KSM),
+			LendingApiError::PriceNotSet { asset: DOT, base_asset: KSM }
+		);
+
+		assert_eq!(
+			LendingApiError::from_pallet_error(Error::<Test>::OverflowError, DOT, KSM),
+			LendingApiError::Overflow
+		);
+		assert_eq!(
+			LendingApiError::from_pallet_error(Error::<Test>::LendingPoolNotActive, DOT, KSM),
+			LendingApiError::Other { error: b"LendingPoolNotActive".to_vec() }
+		);
+	});
+}
+
+#[test]
+fn test_get_lending_pools_pagination() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![
+			(USDT, ALICE, 1_000_000),
+			(DOT, ALICE, 1_000_000),
+			(KSM, ALICE, 1_000_000),
+		])
+		.build_and_execute(|| {
+			for (i, asset) in [USDT, DOT, KSM].into_iter().enumerate() {
+				assert_ok!(Lending::create_lending_pool(
+					RuntimeOrigin::signed(ALICE),
+					LENDING_POOL_TOKEN + i as u32,
+					asset,
+					1_000
+				));
+			}
+			assert_ok!(Lending::activate_lending_pool(RuntimeOrigin::signed(ALICE), DOT));
+			assert_ok!(Lending::activate_lending_pool(RuntimeOrigin::signed(ALICE), KSM));
+
+			let asset_ids = |pools: Vec<LendingPoolInfo>| -> Vec<AssetId> {
+				pools.into_iter().map(|pool| pool.asset_id).collect()
+			};
+			let (all, _) = Lending::get_lending_pools(None, None, None, None, false);
+			let all = asset_ids(all);
+			assert_eq!(all.len(), 3);
+
+			// pages follow the storage order, starting after the last pool of the previous page
+			let (first_page, _) = Lending::get_lending_pools(None, None, None, Some(2), false);
+			assert_eq!(asset_ids(first_page), all[..2].to_vec());
+			let (second_page, _) =
+				Lending::get_lending_pools(None, None, Some(all[1]), Some(2), false);
+			assert_eq!(asset_ids(second_page), all[2..].to_vec());
+			let (last_page, totals) =
+				Lending::get_lending_pools(None, None, Some(all[2]), Some(2), false);
+			assert!(last_page.is_empty());
+			assert_eq!(totals.total_supply, 0);
+
+			let (active, _) = Lending::get_lending_pools(None, None, None, None, true);
+			let expected: Vec<AssetId> = all.iter().copied().filter(|a| *a != USDT).collect();
+			assert_eq!(asset_ids(active), expected);
+			let (active_usdt, _) = Lending::get_lending_pools(Some(USDT), None, None, None, true);
+			assert!(active_usdt.is_empty());
+		});
+}
+
+#[test]
+fn test_max_borrowable_is_limited_by_collateral_and_liquidity() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![
+			(DOT, ALICE, 1_000_000),
+			(KSM, BOB, 1_001),
+			(KSM, ALICE, 1_000_000),
+		])
+		.build_and_execute(|| {
+			setup_active_pool(DOT, 100_000);
+			assert_ok!(Lending::set_asset_price(
+				RuntimeOrigin::root(),
+				DOT,
+				KSM,
+				FixedU128::from(1)
+			));
+
+			// 1_000 KSM can be locked, at a collateral factor of 50%
+			assert_eq!(Lending::max_borrowable(&BOB, DOT, KSM).ok(), Some(500));
+			// ALICE is limited by the 100_000 DOT of the pool
+			assert_eq!(Lending::max_borrowable(&ALICE, DOT, KSM).ok(), Some(100_000));
+			assert!(matches!(
+				Lending::max_borrowable(&BOB, KSM, DOT),
+				Err(Error::<Test>::LendingPoolDoesNotExist)
+			));
+		});
+}
+
+#[test]
+fn test_max_borrowable_is_accepted_by_borrow() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (KSM, BOB, 1_001)])
+		.build_and_execute(|| {
+			setup_active_pool(DOT, 100_000);
+			// a price whose conversions round
+			assert_ok!(Lending::set_asset_price(
+				RuntimeOrigin::root(),
+				DOT,
+				KSM,
+				FixedU128::from_rational(3, 7)
+			));
+
+			let max = Lending::max_borrowable(&BOB, DOT, KSM).unwrap();
+			assert!(max > 0);
+			assert_noop!(
+				Lending::borrow(RuntimeOrigin::signed(BOB), DOT, max + 1, KSM),
+				Error::<Test>::NotEnoughLiquiditySupply
+			);
+			assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), DOT, max, KSM));
+		});
+}
+
+#[test]
+fn test_max_borrowable_is_zero_for_inactive_pools() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (KSM, BOB, 1_001)])
+		.build_and_execute(|| {
+			assert_ok!(Lending::create_lending_pool(
+				RuntimeOrigin::signed(ALICE),
+				LENDING_POOL_TOKEN,
+				DOT,
+				100_000
+			));
+			assert_eq!(Lending::max_borrowable(&BOB, DOT, KSM).ok(), Some(0));
+		});
+}
+
+#[test]
+fn test_max_withdrawable() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (KSM, BOB, 1_000_000)])
+		.build_and_execute(|| {
+			setup_active_pool(DOT, 100_000);
+			assert_ok!(Lending::set_asset_price(
+				RuntimeOrigin::root(),
+				DOT,
+				KSM,
+				FixedU128::from(1)
+			));
+
+			assert_eq!(Lending::max_withdrawable(&ALICE, DOT).ok(), Some(100_000));
+			assert_eq!(Lending::max_withdrawable(&BOB, DOT).ok(), Some(0));
+
+			// the pool only holds 40_000 DOT once BOB borrowed
+			assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), DOT, 60_000, KSM));
+			assert_eq!(Lending::max_withdrawable(&ALICE, DOT).ok(), Some(40_000));
+			assert_ok!(Lending::withdraw(RuntimeOrigin::signed(ALICE), DOT, 40_000));
+			assert_eq!(Lending::max_withdrawable(&ALICE, DOT).ok(), Some(0));
+
+			assert!(matches!(
+				Lending::max_withdrawable(&ALICE, KSM),
+				Err(Error::<Test>::LendingPoolDoesNotExist)
+			));
+		});
+}
+
+#[test]
+fn test_get_pool_stats() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![
+			(DOT, ALICE, 1_000_000),
+			(KSM, ALICE, 1_000_000),
+			(DOT, BOB, 10_000),
+			(KSM, BOB, 1_000_000),
+		])
+		.build_and_execute(|| {
+			assert!(matches!(Lending::get_pool_stats(DOT), Err(Error::LendingPoolDoesNotExist)));
+
+			setup_active_pool(DOT, 100_000);
+			assert_ok!(Lending::supply(RuntimeOrigin::signed(BOB), DOT, 1_000));
+			assert_ok!(Lending::set_asset_price(
+				RuntimeOrigin::root(),
+				DOT,
+				KSM,
+				FixedU128::one()
+			));
+			assert_ok!(Lending::borrow(RuntimeOrigin::signed(ALICE), DOT, 200, KSM));
+			assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), DOT, 300, KSM));
+
+			let stats = Lending::get_pool_stats(DOT).unwrap();
+			assert_eq!(stats.asset_id, DOT);
+			assert_eq!(stats.pool_id, LENDING_POOL_TOKEN);
+			assert!(stats.is_activated);
+			assert_eq!(stats.total_supply, 101_000);
+			assert_eq!(stats.total_debt, 500);
+			assert_eq!(stats.reserve_balance, 100_500);
+			assert_eq!(stats.borrowed_balance, 500);
+			assert_eq!((stats.suppliers, stats.borrowers), (2, 2));
+			assert!(!stats.utilization.is_zero());
+			assert_eq!(stats.rate_at_zero_utilization, FixedU128::from_rational(5, 100));
+			assert_eq!(stats.reserve_factor, FixedU128::from_rational(1, 10));
+			assert_eq!(stats.collateral_factor, FixedU128::from_rational(1, 2));
+			assert_eq!(stats.liquidation_threshold, FixedU128::from_rational(4, 5));
+
+			// the totals include the interest accrued since the last update of the pool
+			run_to_block(1_000_000);
+			let stats = Lending::get_pool_stats(DOT).unwrap();
+			assert!(stats.total_debt > 500);
+			assert!(stats.total_supply > 101_000);
+			assert!(stats.borrow_index > FixedU128::one());
+			assert_eq!(stats.last_accrued_interest_at, Timestamp::get() / 1_000);
+			assert_eq!(stats.borrowed_balance, 500);
+		});
+}
@@ -0,0 +1,127 @@
+use crate::{
+	tests::mock::*, AssetPool, Borrows, LendingApiError, SimulatedAction, SimulationResult,
+};
+use frame_support::assert_ok;
+use sp_runtime::{traits::Zero, FixedU128};
+
+fn setup_prices() {
+	for (asset, base_asset) in [(DOT, KSM), (DOT, USDT), (KSM, USDT)] {
+		assert_ok!(Lending::set_asset_price(
+			RuntimeOrigin::root(),
+			asset,
+			base_asset,
+			FixedU128::from(1)
+		));
+	}
+}
+
+fn borrow_dot(balance: u128) -> SimulatedAction {
+	SimulatedAction::Borrow { asset: DOT, balance, collateral_asset: KSM }
+}
+
+fn error_name(result: &SimulationResult) -> Vec<u8> {
+	match &result.error {
+		Some(LendingApiError::Other { error }) => error.clone(),
+		error => panic!("unexpected error {:?}", error),
+	}
+}
+
+#[test]
+fn test_simulated_borrow_matches_the_real_one_and_is_rolled_back() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (KSM, BOB, 1_000_000)])
+		.build_and_execute(|| {
+			setup_active_pool(DOT, 100_000);
+			setup_prices();
+			let events = System::events().len();
+			let pool = Lending::reserve_pools(AssetPool::<Test>::from(DOT));
+
+			let simulated = Lending::simulate_action(&BOB, borrow_dot(50_000));
+
+			assert_eq!(simulated.error, None);
+			assert!(!simulated.current_ltv.is_zero());
+			assert!(simulated.health_factor.is_some());
+			assert_eq!(simulated.utilization, FixedU128::from_rational(1, 2));
+			// nothing was written
+			assert_eq!(Borrows::<Test>::get(BOB, (DOT, KSM)), None);
+			assert_eq!(Lending::reserve_pools(AssetPool::<Test>::from(DOT)), pool);
+			assert_eq!(System::events().len(), events);
+
+			assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), DOT, 50_000, KSM));
+			let (current_ltv, sale_ltv, liquidation_ltv) = Lending::compute_user_ltv(&BOB);
+			let pool = Lending::reserve_pools(AssetPool::<Test>::from(DOT)).unwrap();
+			assert_eq!(
+				simulated,
+				SimulationResult {
+					current_ltv,
+					sale_ltv,
+					liquidation_ltv,
+					health_factor: Lending::health_factor(current_ltv, liquidation_ltv),
+					utilization: pool.utilisation_ratio().unwrap().into(),
+					borrow_apr: pool.borrow_interest_rate().unwrap(),
+					supply_apr: pool.supply_interest_rate().unwrap(),
+					error: None,
+				}
+			);
+		});
+}
+
+#[test]
+fn test_simulated_full_repay_clears_the_health_factor() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (KSM, BOB, 1_000_000)])
+		.build_and_execute(|| {
+			setup_active_pool(DOT, 100_000);
+			setup_prices();
+			assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), DOT, 500, KSM));
+
+			let simulated = Lending::simulate_action(
+				&BOB,
+				SimulatedAction::Repay { asset: DOT, balance: 500, collateral_asset: KSM },
+			);
+
+			assert_eq!(simulated.error, None);
+			assert!(simulated.current_ltv.is_zero());
+			assert_eq!(simulated.health_factor, None);
+			assert!(simulated.utilization.is_zero());
+			assert!(Borrows::<Test>::get(BOB, (DOT, KSM)).is_some());
+		});
+}
+
+#[test]
+fn test_simulated_action_reports_errors() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (KSM, BOB, 1_000_000)])
+		.build_and_execute(|| {
+			setup_active_pool(DOT, 100_000);
+			setup_prices();
+			assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), DOT, 500, KSM));
+			let (current_ltv, _, _) = Lending::compute_user_ltv(&BOB);
+
+			// the position is the current one
+			let simulated = Lending::simulate_action(&BOB, borrow_dot(200_000));
+			assert_eq!(error_name(&simulated), b"NotEnoughLiquiditySupply".to_vec());
+			assert_eq!(simulated.current_ltv, current_ltv);
+
+			let simulated = Lending::simulate_action(
+				&ALICE,
+				SimulatedAction::Repay { asset: DOT, balance: 500, collateral_asset: KSM },
+			);
+			assert_eq!(error_name(&simulated), b"LoanDoesNotExists".to_vec());
+
+			let simulated = Lending::simulate_action(
+				&BOB,
+				SimulatedAction::Supply { asset: KYL, balance: 500 },
+			);
+			assert_eq!(simulated.error, Some(LendingApiError::PoolNotFound { asset: KYL }));
+
+			let simulated = Lending::simulate_action(
+				&BOB,
+				SimulatedAction::Borrow { asset: DOT, balance: 500, collateral_asset: KYL },
+			);
+			assert_eq!(
+				simulated.error,
+				Some(LendingApiError::PriceNotSet { asset: DOT, base_asset: KYL })
+			);
+		});
+}
@@ -0,0 +1,99 @@
+use crate::{tests::mock::*, AssetPool, Borrows, LendingPoolStorage, SupplyIndexStorage};
+use frame_support::{assert_err, assert_ok};
+use sp_runtime::{FixedU128, TryRuntimeError};
+
+fn setup_borrow() {
+	setup_active_pool(DOT, 1_000);
+	assert_ok!(Lending::set_asset_price(
+		RuntimeOrigin::root(),
+		DOT,
+		KSM,
+		FixedU128::from_rational(1, 1)
+	));
+	assert_ok!(Lending::borrow(RuntimeOrigin::signed(BOB), DOT, 500, KSM));
+}
+
+#[test]
+fn test_try_state_holds_after_borrow() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (KSM, BOB, 1_000_000)])
+		.build_and_execute(|| {
+			setup_borrow();
+			assert_ok!(Lending::do_try_state());
+		});
+}
+
+#[test]
+fn test_try_state_detects_borrowed_balance_mismatch() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (KSM, BOB, 1_000_000)])
+		.build()
+		.execute_with(|| {
+			setup_borrow();
+			LendingPoolStorage::<Test>::mutate(AssetPool::<Test>::from(DOT), |pool| {
+				pool.as_mut().unwrap().borrowed_balance += 1;
+			});
+			assert_err!(
+				Lending::do_try_state(),
+				TryRuntimeError::Other(
+					"The borrowed balance of a lending pool does not match its loans"
+				)
+			);
+		});
+}
+
+#[test]
+fn test_try_state_detects_missing_collateral() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000), (KSM, BOB, 1_000_000)])
+		.build()
+		.execute_with(|| {
+			setup_borrow();
+			Borrows::<Test>::mutate(BOB, (DOT, KSM), |loan| {
+				loan.as_mut().unwrap().collateral_balance += 1;
+			});
+			assert_err!(
+				Lending::do_try_state(),
+				TryRuntimeError::Other(
+					"The pallet account holds less than the reserves and the collateral"
+				)
+			);
+		});
+}
+
+#[test]
+fn test_try_state_detects_index_below_one() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000)])
+		.build()
+		.execute_with(|| {
+			setup_active_pool(DOT, 1_000);
+			LendingPoolStorage::<Test>::mutate(AssetPool::<Test>::from(DOT), |pool| {
+				pool.as_mut().unwrap().supply_index = FixedU128::from_rational(1, 2);
+			});
+			assert_err!(
+				Lending::do_try_state(),
+				TryRuntimeError::Other("The indexes of a lending pool are below one")
+			);
+		});
+}
+
+#[test]
+fn test_try_state_detects_supply_index_below_an_earlier_supply() {
+	ExtBuilder::default()
+		.with_endowed_balances(vec![(DOT, ALICE, 1_000_000)])
+		.build()
+		.execute_with(|| {
+			setup_active_pool(DOT, 1_000);
+			// as if the index had decreased since ALICE supplied at an index of two
+			SupplyIndexStorage::<Test>::mutate((ALICE, DOT), |supplied| {
+				supplied.supply_index = FixedU128::from_rational(2, 1);
+			});
+			assert_err!(
+				Lending::do_try_state(),
+				TryRuntimeError::Other(
+					"The supply index of a lending pool is below the one of an earlier supply"
+				)
+			);
+		});
+}
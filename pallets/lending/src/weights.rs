@@ -36,6 +36,7 @@ use core::marker::PhantomData;
 pub trait WeightInfo {
 	fn do_something() -> Weight;
 	fn cause_error() -> Weight;
+	fn accrue_interest() -> Weight;
 }
 
 /// Weights for pallet_template using the Substrate node and recommended hardware.
@@ -62,6 +63,18 @@ impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
 			.saturating_add(T::DbWeight::get().reads(1_u64))
 			.saturating_add(T::DbWeight::get().writes(1_u64))
 	}
+	/// Storage: Lending LendingPoolStorage (r:1 w:1)
+	/// Storage: Timestamp Now (r:1 w:0)
+	/// Storage: System EventCount (r:1 w:1)
+	/// Storage: System Events (r:0 w:1)
+	/// Not benchmarked: a hand estimate of updating the indexes of one lending pool and
+	/// depositing its `InterestAccrued` and `InterestAccrualCapped` events, to be replaced by a
+	/// generated weight once `accrue_interest` has a benchmark.
+	fn accrue_interest() -> Weight {
+		Weight::from_parts(34_000_000, 3877)
+			.saturating_add(T::DbWeight::get().reads(3_u64))
+			.saturating_add(T::DbWeight::get().writes(3_u64))
+	}
 }
 
 // For backwards compatibility and tests
@@ -87,4 +100,16 @@ impl WeightInfo for () {
 			.saturating_add(RocksDbWeight::get().reads(1_u64))
 			.saturating_add(RocksDbWeight::get().writes(1_u64))
 	}
+	/// Storage: Lending LendingPoolStorage (r:1 w:1)
+	/// Storage: Timestamp Now (r:1 w:0)
+	/// Storage: System EventCount (r:1 w:1)
+	/// Storage: System Events (r:0 w:1)
+	/// Not benchmarked: a hand estimate of updating the indexes of one lending pool and
+	/// depositing its `InterestAccrued` and `InterestAccrualCapped` events, to be replaced by a
+	/// generated weight once `accrue_interest` has a benchmark.
+	fn accrue_interest() -> Weight {
+		Weight::from_parts(34_000_000, 3877)
+			.saturating_add(RocksDbWeight::get().reads(3_u64))
+			.saturating_add(RocksDbWeight::get().writes(3_u64))
+	}
 }
@@ -10,8 +10,10 @@ use codec::{Decode, Encode};
 use frame_support::traits::AsEnsureOriginWithArg;
 use frame_system::{EnsureRoot, EnsureSigned};
 use lending::{
-	AggregatedTotals, BorrowedAsset, CollateralAsset, FixedU128, LendingPoolInfo, SuppliedAsset,
-	TotalBorrow, TotalCollateral, TotalDeposit,
+	AggregatedTotals, BorrowedAsset, BorrowedAssetV1, CollateralAsset, CollateralAssetV1,
+	FixedU128, LendingApiError, LendingPoolInfo, LendingPoolInfoV1, LiquidatablePosition,
+	LoanPosition, PoolStats, RateCurve, SimulatedAction, SimulationResult, SuppliedAsset,
+	SuppliedAssetV1, TotalBorrow, TotalCollateral, TotalDeposit,
 };
 use pallet_grandpa::AuthorityId as GrandpaId;
 use scale_info::TypeInfo;
@@ -22,9 +24,10 @@ use sp_core::{crypto::KeyTypeId, OpaqueMetadata};
 use sp_runtime::{
 	create_runtime_str, generic, impl_opaque_keys,
 	traits::{
-		AccountIdLookup, BlakeTwo256, Block as BlockT, IdentifyAccount, NumberFor, One, Verify,
+		AccountIdLookup, BlakeTwo256, Block as BlockT, CheckedDiv, Extrinsic as ExtrinsicT,
+		IdentifyAccount, NumberFor, One, Verify,
 	},
-	transaction_validity::{TransactionSource, TransactionValidity},
+	transaction_validity::{TransactionPriority, TransactionSource, TransactionValidity},
 	ApplyExtrinsicResult, MultiSignature,
 };
 use sp_std::prelude::*;
@@ -114,12 +117,14 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	// The version of the runtime specification. A full node will not attempt to use its native
 	//   runtime in substitute for the on-chain Wasm runtime unless all of `spec_name`,
 	//   `spec_version`, and `authoring_version` are the same between Wasm and native.
-	// This value is set to 100 to notify Polkadot-JS App (https://polkadot.js.org/apps) to use
-	//   the compatible custom types.
-	spec_version: 100,
+	// This value started at 100 to notify Polkadot-JS App (https://polkadot.js.org/apps) to use
+	//   the compatible custom types. It must be bumped with every runtime upgrade, or the upgrade
+	//   is not enacted and the `Migrations` never run.
+	spec_version: 101,
 	impl_version: 1,
 	apis: RUNTIME_API_VERSIONS,
-	transaction_version: 1,
+	// Bumped whenever the calls or their encoding change, e.g. the lending calls 0 to 20.
+	transaction_version: 2,
 	state_version: 1,
 };
 
@@ -284,6 +289,16 @@ use frame_support::PalletId;
 
 parameter_types! {
 	pub const LendingPalletId: PalletId = PalletId(*b"kylix_id");
+	pub const LiquidationInterval: BlockNumber = 10;
+	pub const MaxLiquidationsPerRun: u32 = 16;
+	/// A liquidation repays at most half of the debt of a loan
+	pub const LiquidationCloseFactor: Permill = Permill::from_percent(50);
+	/// and seizes 5% more collateral than the value of the repaid debt
+	pub const LiquidationBonus: Permill = Permill::from_percent(5);
+	pub const LiquidationUnsignedPriority: TransactionPriority = TransactionPriority::max_value() / 2;
+	pub const PriceFeedInterval: BlockNumber = MINUTES;
+	/// The lending incentives are paid in USDT
+	pub const LendingRewardAsset: AssetId = 1;
 }
 
 /// Configure the lending in pallets/lending.
@@ -294,6 +309,62 @@ impl lending::Config for Runtime {
 	type Fungibles = Assets;
 	type PalletId = LendingPalletId;
 	type Time = Timestamp;
+	type AuthorityId = lending::crypto::LendingAuthId;
+	type CloseFactor = LiquidationCloseFactor;
+	type LiquidationBonus = LiquidationBonus;
+	type MaxAuthorities = ConstU32<32>;
+	type LiquidationInterval = LiquidationInterval;
+	type MaxLiquidationsPerRun = MaxLiquidationsPerRun;
+	type SignedLiquidations = ConstBool<false>;
+	type UnsignedPriority = LiquidationUnsignedPriority;
+	type PriceFeedAssets = lending::LendingPoolAssets<Runtime>;
+	type PriceFeedInterval = PriceFeedInterval;
+	type ManagerOrigin = EnsureRoot<AccountId>;
+	type MaxAssetMetadataLength = ConstU32<256>;
+	type RewardAsset = LendingRewardAsset;
+}
+
+impl<LocalCall> frame_system::offchain::CreateSignedTransaction<LocalCall> for Runtime
+where
+	RuntimeCall: From<LocalCall>,
+{
+	fn create_transaction<C: frame_system::offchain::AppCrypto<Self::Public, Self::Signature>>(
+		call: RuntimeCall,
+		public: <Signature as Verify>::Signer,
+		account: AccountId,
+		nonce: Nonce,
+	) -> Option<(RuntimeCall, <UncheckedExtrinsic as ExtrinsicT>::SignaturePayload)> {
+		let period =
+			BlockHashCount::get().checked_next_power_of_two().map(|c| c / 2).unwrap_or(2) as u64;
+		let current_block = System::block_number().saturated_into::<u64>().saturating_sub(1);
+		let extra: SignedExtra = (
+			frame_system::CheckNonZeroSender::<Runtime>::new(),
+			frame_system::CheckSpecVersion::<Runtime>::new(),
+			frame_system::CheckTxVersion::<Runtime>::new(),
+			frame_system::CheckGenesis::<Runtime>::new(),
+			frame_system::CheckEra::<Runtime>::from(generic::Era::mortal(period, current_block)),
+			frame_system::CheckNonce::<Runtime>::from(nonce),
+			frame_system::CheckWeight::<Runtime>::new(),
+			pallet_transaction_payment::ChargeTransactionPayment::<Runtime>::from(0),
+		);
+		let raw_payload = SignedPayload::new(call, extra).ok()?;
+		let signature = raw_payload.using_encoded(|payload| C::sign(payload, public))?;
+		let (call, extra, _) = raw_payload.deconstruct();
+		Some((call, (sp_runtime::MultiAddress::Id(account), signature, extra)))
+	}
+}
+
+impl frame_system::offchain::SigningTypes for Runtime {
+	type Public = <Signature as Verify>::Signer;
+	type Signature = Signature;
+}
+
+impl<C> frame_system::offchain::SendTransactionTypes<C> for Runtime
+where
+	RuntimeCall: From<C>,
+{
+	type OverarchingCall = RuntimeCall;
+	type Extrinsic = UncheckedExtrinsic;
 }
 
 parameter_types! {
@@ -332,6 +403,34 @@ pub struct UserLTVInfo {
 	pub current_ltv: FixedU128,
 	pub sale_ltv: FixedU128,
 	pub liquidation_ltv: FixedU128,
+	/// The collateral of all the loans weighted by their liquidation threshold, over the debt.
+	/// The account can be liquidated below one. `None` without any debt
+	pub health_factor: Option<FixedU128>,
+	/// Every loan of the account, with its own health factor and liquidation price
+	pub positions: Vec<LoanPosition>,
+}
+
+/// The LTV information of version 1 of the lending runtime API
+#[derive(Encode, Decode, Clone, PartialEq, Serialize, Deserialize, Debug, TypeInfo)]
+pub struct UserLTVInfoV1 {
+	pub current_ltv: FixedU128,
+	pub sale_ltv: FixedU128,
+	/// The lowest liquidation threshold of the pools the account borrowed from
+	pub liquidation_ltv: FixedU128,
+}
+
+/// Version 1 reports neither the loans nor the weighted health factor, which is approximated
+/// with the lowest liquidation threshold
+impl From<UserLTVInfoV1> for UserLTVInfo {
+	fn from(info: UserLTVInfoV1) -> Self {
+		UserLTVInfo {
+			current_ltv: info.current_ltv,
+			sale_ltv: info.sale_ltv,
+			liquidation_ltv: info.liquidation_ltv,
+			health_factor: info.liquidation_ltv.checked_div(&info.current_ltv),
+			positions: Vec::new(),
+		}
+	}
 }
 
 // Create the runtime by composing the FRAME pallets that were previously configured.
@@ -373,6 +472,10 @@ pub type UncheckedExtrinsic =
 	generic::UncheckedExtrinsic<Address, RuntimeCall, Signature, SignedExtra>;
 /// The payload being signed in transactions.
 pub type SignedPayload = generic::SignedPayload<RuntimeCall, SignedExtra>;
+/// Storage migrations applied on runtime upgrade.
+pub type Migrations =
+	(lending::migrations::v1::MigrateToV1<Runtime>, lending::migrations::v2::MigrateToV2<Runtime>);
+
 /// Executive: handles dispatch to the various modules.
 pub type Executive = frame_executive::Executive<
 	Runtime,
@@ -380,6 +483,7 @@ pub type Executive = frame_executive::Executive<
 	frame_system::ChainContext<Runtime>,
 	Runtime,
 	AllPalletsWithSystem,
+	Migrations,
 >;
 
 #[cfg(feature = "runtime-benchmarks")]
@@ -399,13 +503,50 @@ mod benches {
 }
 
 decl_runtime_apis! {
+	/// The version is bumped with every change, so that clients can check for a method with
+	/// `has_api_with`. The methods of version 1 are kept behind `#[changed_in]`, with their
+	/// responses like `LendingPoolInfoV1`, for the node to query the blocks of older runtimes.
+	///
+	/// - 2: a `LendingApiError` is returned instead of a default value or `None` on failure
+	/// - 3: `get_lending_pools` is paginated and can filter out inactive pools
+	/// - 4: `simulate_action`
+	/// - 5: `max_borrowable` and `max_withdrawable`
+	/// - 6: `get_liquidatable_positions`
+	/// - 7: `get_pool_stats`
+	/// - 8: `get_rate_curve`
+	///
+	/// Versions 2 to 7 were never part of a released runtime, their responses changed along the
+	/// way. The node only queries older runtimes with the methods of version 1 and, for
+	/// `get_lending_pools`, of version 2.
+	#[api_version(8)]
 	pub trait LendingPoolApi {
-		fn get_lending_pools(asset: Option<AssetId>, account: Option<AccountId>) -> (Vec<LendingPoolInfo>, AggregatedTotals);
-		fn get_user_ltv(account: AccountId) -> UserLTVInfo;
-		fn get_asset_wise_supplies(account: AccountId) -> (Vec<SuppliedAsset>, TotalDeposit);
-		fn get_asset_wise_borrows_collaterals(account: AccountId) -> (Vec<BorrowedAsset>, Vec<CollateralAsset>, TotalBorrow, TotalCollateral);
+		#[changed_in(2)]
+		fn get_lending_pools(asset: Option<AssetId>, account: Option<AccountId>) -> (Vec<LendingPoolInfoV1>, AggregatedTotals);
+		#[changed_in(2)]
+		fn get_user_ltv(account: AccountId) -> UserLTVInfoV1;
+		#[changed_in(2)]
+		fn get_asset_wise_supplies(account: AccountId) -> (Vec<SuppliedAssetV1>, TotalDeposit);
+		#[changed_in(2)]
+		fn get_asset_wise_borrows_collaterals(account: AccountId) -> (Vec<BorrowedAssetV1>, Vec<CollateralAssetV1>, TotalBorrow, TotalCollateral);
+		#[changed_in(2)]
 		fn get_asset_price(asset: AssetId, base_asset: Option<AssetId>) -> Option<FixedU128>;
+		#[changed_in(2)]
 		fn get_estimate_collateral_amount(borrow_asset: AssetId, borrow_amount: Balance, collateral_asset: AssetId) -> Option<Balance>;
+		#[changed_in(3)]
+		fn get_lending_pools(asset: Option<AssetId>, account: Option<AccountId>) -> Result<(Vec<LendingPoolInfoV1>, AggregatedTotals), LendingApiError>;
+
+		fn get_lending_pools(asset: Option<AssetId>, account: Option<AccountId>, start_key: Option<AssetId>, limit: Option<u32>, active_only: bool) -> Result<(Vec<LendingPoolInfo>, AggregatedTotals), LendingApiError>;
+		fn get_user_ltv(account: AccountId) -> Result<UserLTVInfo, LendingApiError>;
+		fn get_asset_wise_supplies(account: AccountId) -> Result<(Vec<SuppliedAsset>, TotalDeposit), LendingApiError>;
+		fn get_asset_wise_borrows_collaterals(account: AccountId) -> Result<(Vec<BorrowedAsset>, Vec<CollateralAsset>, TotalBorrow, TotalCollateral), LendingApiError>;
+		fn get_asset_price(asset: AssetId, base_asset: Option<AssetId>) -> Result<FixedU128, LendingApiError>;
+		fn get_estimate_collateral_amount(borrow_asset: AssetId, borrow_amount: Balance, collateral_asset: AssetId) -> Result<Balance, LendingApiError>;
+		fn simulate_action(account: AccountId, action: SimulatedAction) -> Result<SimulationResult, LendingApiError>;
+		fn max_borrowable(account: AccountId, borrow_asset: AssetId, collateral_asset: AssetId) -> Result<Balance, LendingApiError>;
+		fn max_withdrawable(account: AccountId, asset: AssetId) -> Result<Balance, LendingApiError>;
+		fn get_liquidatable_positions(limit: u32, cursor: Option<(AccountId, AssetId, AssetId)>) -> Result<Vec<LiquidatablePosition<AccountId>>, LendingApiError>;
+		fn get_pool_stats(asset: AssetId) -> Result<PoolStats, LendingApiError>;
+		fn get_rate_curve(asset: AssetId, points: u32) -> Result<RateCurve, LendingApiError>;
 	}
 }
 
@@ -641,40 +782,78 @@ impl_runtime_apis! {
 	}
 
 	impl crate::LendingPoolApi<Block> for Runtime {
-		fn get_user_ltv(account: AccountId) -> UserLTVInfo {
+		fn get_user_ltv(account: AccountId) -> Result<UserLTVInfo, LendingApiError> {
 			let (current_ltv, sale_ltv, liquidation_ltv) = Lending::compute_user_ltv(&account);
-			UserLTVInfo {
+			let positions = Lending::get_loan_positions(&account);
+			Ok(UserLTVInfo {
 				current_ltv,
 				sale_ltv,
 				liquidation_ltv,
-			}
+				health_factor: Lending::weighted_health_factor(&positions),
+				positions,
+			})
 		}
 
-		fn get_lending_pools(asset: Option<AssetId>, account: Option<AccountId>) -> (Vec<LendingPoolInfo>, AggregatedTotals) {
-			lending::Pallet::<Runtime>::get_lending_pools(asset, account.as_ref())
+		fn get_lending_pools(asset: Option<AssetId>, account: Option<AccountId>, start_key: Option<AssetId>, limit: Option<u32>, active_only: bool) -> Result<(Vec<LendingPoolInfo>, AggregatedTotals), LendingApiError> {
+			if let Some(asset) = asset {
+				if Lending::reserve_pools(lending::AssetPool::<Runtime>::from(asset)).is_none() {
+					return Err(LendingApiError::PoolNotFound { asset });
+				}
+			}
+			Ok(lending::Pallet::<Runtime>::get_lending_pools(
+				asset,
+				account.as_ref(),
+				start_key,
+				limit,
+				active_only,
+			))
 		}
 
-		fn get_asset_wise_supplies(account: AccountId) -> (Vec<SuppliedAsset>, TotalDeposit) {
-			lending::Pallet::<Runtime>::get_asset_wise_supplies(&account)
+		fn get_asset_wise_supplies(account: AccountId) -> Result<(Vec<SuppliedAsset>, TotalDeposit), LendingApiError> {
+			Ok(lending::Pallet::<Runtime>::get_asset_wise_supplies(&account))
 		}
 
-		fn get_asset_wise_borrows_collaterals(account: AccountId) -> (Vec<BorrowedAsset>, Vec<CollateralAsset>, TotalBorrow, TotalCollateral) {
-			lending::Pallet::<Runtime>::get_asset_wise_borrows_collaterals(&account)
+		fn get_asset_wise_borrows_collaterals(account: AccountId) -> Result<(Vec<BorrowedAsset>, Vec<CollateralAsset>, TotalBorrow, TotalCollateral), LendingApiError> {
+			Ok(lending::Pallet::<Runtime>::get_asset_wise_borrows_collaterals(&account))
 		}
 
-		fn get_asset_price(asset: AssetId, base_asset: Option<AssetId>) -> Option<FixedU128>{
+		fn get_asset_price(asset: AssetId, base_asset: Option<AssetId>) -> Result<FixedU128, LendingApiError> {
 			let base_asset = base_asset.unwrap_or(1); // If base_asset is None, default to USDT = 1
-			match lending::Pallet::<Runtime>::get_asset_price(asset, base_asset){
-				Ok(price) => Some(price),
-				Err(_) => None
-			}
+			lending::Pallet::<Runtime>::get_asset_price(asset, base_asset)
+				.map_err(|e| LendingApiError::from_pallet_error(e, asset, base_asset))
 		}
 
-		fn get_estimate_collateral_amount(borrow_asset: AssetId, borrow_amount: Balance, collateral_asset: AssetId) -> Option<Balance> {
-			match lending::Pallet::<Runtime>::estimate_collateral_amount(borrow_asset, borrow_amount, collateral_asset){
-				Ok(estimate) => Some(estimate),
-				Err(_) => None
-			}
+		fn get_estimate_collateral_amount(borrow_asset: AssetId, borrow_amount: Balance, collateral_asset: AssetId) -> Result<Balance, LendingApiError> {
+			lending::Pallet::<Runtime>::estimate_collateral_amount(borrow_asset, borrow_amount, collateral_asset)
+				.map_err(|e| LendingApiError::from_pallet_error(e, borrow_asset, collateral_asset))
+		}
+
+		fn simulate_action(account: AccountId, action: SimulatedAction) -> Result<SimulationResult, LendingApiError> {
+			Ok(lending::Pallet::<Runtime>::simulate_action(&account, action))
+		}
+
+		fn max_borrowable(account: AccountId, borrow_asset: AssetId, collateral_asset: AssetId) -> Result<Balance, LendingApiError> {
+			lending::Pallet::<Runtime>::max_borrowable(&account, borrow_asset, collateral_asset)
+				.map_err(|e| LendingApiError::from_pallet_error(e, borrow_asset, collateral_asset))
+		}
+
+		fn max_withdrawable(account: AccountId, asset: AssetId) -> Result<Balance, LendingApiError> {
+			lending::Pallet::<Runtime>::max_withdrawable(&account, asset)
+				.map_err(|e| LendingApiError::from_pallet_error(e, asset, 1))
+		}
+
+		fn get_liquidatable_positions(limit: u32, cursor: Option<(AccountId, AssetId, AssetId)>) -> Result<Vec<LiquidatablePosition<AccountId>>, LendingApiError> {
+			Ok(lending::Pallet::<Runtime>::get_liquidatable_positions(limit, cursor))
+		}
+
+		fn get_pool_stats(asset: AssetId) -> Result<PoolStats, LendingApiError> {
+			lending::Pallet::<Runtime>::get_pool_stats(asset)
+				.map_err(|e| LendingApiError::from_pallet_error(e, asset, 1))
+		}
+
+		fn get_rate_curve(asset: AssetId, points: u32) -> Result<RateCurve, LendingApiError> {
+			lending::Pallet::<Runtime>::get_rate_curve(asset, points)
+				.map_err(|e| LendingApiError::from_pallet_error(e, asset, 1))
 		}
 	}
 }